@@ -0,0 +1,67 @@
+// Bake a TTF font into AngelCode BMFont files and PNG pages, which `BitmapFont::load_bmfont` loads
+//
+// Usage: quicksilver-bake-font <font.ttf> <output folder> <size>[,<size>...] [characters]
+//
+// Each size is written to `<name>-<size>.fnt`, with its pages next to it. The characters default
+// to printable ASCII.
+use quicksilver::graphics::{ImageEncoding, VectorFont};
+use std::error::Error;
+use std::path::Path;
+use std::{env, fs, process};
+
+const USAGE: &str =
+    "Usage: quicksilver-bake-font <font.ttf> <output folder> <size>[,<size>...] [characters]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 3 || args.len() > 4 {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+    if let Err(error) = bake(&args[0], &args[1], &args[2], args.get(3)) {
+        eprintln!("Failed to bake the font: {}", error);
+        process::exit(1);
+    }
+}
+
+fn bake(
+    font_path: &str,
+    output: &str,
+    sizes: &str,
+    characters: Option<&String>,
+) -> Result<(), Box<dyn Error>> {
+    let font_path = Path::new(font_path);
+    let name = font_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or("the font path has no file name")?;
+    let font = VectorFont::from_bytes(fs::read(font_path)?);
+    let ascii: String = (' '..='~').collect();
+    let characters = characters.map_or(ascii.as_str(), |characters| characters.as_str());
+    let output = Path::new(output);
+    fs::create_dir_all(output)?;
+
+    for size in sizes.split(',') {
+        let size: f32 = size
+            .trim()
+            .parse()
+            .map_err(|_| format!("{} isn't a font size", size))?;
+        let file_name = format!("{}-{}", name, size);
+        let baked = font.bake(size, characters, &file_name);
+        fs::write(
+            output.join(format!("{}.fnt", file_name)),
+            baked.descriptor(),
+        )?;
+        for (page, page_name) in baked.pages().iter().zip(baked.page_names()) {
+            fs::write(output.join(page_name), page.encode(ImageEncoding::Png)?)?;
+        }
+        println!(
+            "Baked {} at size {} into {} page(s)",
+            name,
+            size,
+            baked.pages().len()
+        );
+    }
+
+    Ok(())
+}
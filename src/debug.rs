@@ -0,0 +1,352 @@
+//! A global queue of shapes and text for visualizing what's going on while developing
+//!
+//! Any code can queue a debug shape without access to the [`Graphics`], and the queue is drawn on
+//! top of everything else the next time the window is flushed:
+//!
+//! ```no_run
+//! # use quicksilver::{debug, geom::{Rectangle, Vector}, graphics::Color};
+//! # use std::time::Duration;
+//! # fn update(hitbox: Rectangle, position: Vector, velocity: Vector) {
+//! debug::rect(hitbox, Color::RED);
+//! debug::line(position, position + velocity, Color::GREEN).persist(Duration::from_secs(2));
+//! debug::text(Vector::new(4.0, 4.0), "PAUSED", Color::WHITE).screen_space();
+//! # }
+//! ```
+//!
+//! Shapes are drawn for a single frame unless they're [persisted], and in world space (through
+//! the view set with [`Graphics::set_view`]) unless they're moved into [screen space]. Text is
+//! drawn with a tiny built-in pixel font, with each font pixel 2 units across.
+//!
+//! The queue belongs to the thread it's used from, so queue shapes on the same thread that draws
+//! with the [`Graphics`]. Nothing is drawn unless the `debug-draw` feature is enabled; without it
+//! every function here is a no-op, so debug draws can be left in the code without costing
+//! anything in release builds.
+//!
+//! [`Graphics`]: crate::Graphics
+//! [`Graphics::set_view`]: crate::Graphics::set_view
+//! [persisted]: DebugDraw::persist
+//! [screen space]: DebugDraw::screen_space
+
+use crate::geom::{Circle, Rectangle, Vector};
+use crate::graphics::Color;
+
+use std::time::Duration;
+
+#[cfg(feature = "debug-draw")]
+use crate::geom::Transform;
+#[cfg(feature = "debug-draw")]
+use crate::Graphics;
+#[cfg(feature = "debug-draw")]
+use instant::Instant;
+#[cfg(feature = "debug-draw")]
+use std::cell::RefCell;
+
+/// A queued debug shape, which can be adjusted until it's drawn
+pub struct DebugDraw {
+    #[cfg(feature = "debug-draw")]
+    id: u64,
+}
+
+impl DebugDraw {
+    /// Keep drawing the shape every frame for the given length of time
+    pub fn persist(self, duration: Duration) -> DebugDraw {
+        #[cfg(feature = "debug-draw")]
+        self.modify(|item| item.expires = Some(Instant::now() + duration));
+        #[cfg(not(feature = "debug-draw"))]
+        let _ = duration;
+
+        self
+    }
+
+    /// Draw the shape in camera coordinates, ignoring the view
+    ///
+    /// This is useful for overlays like frame timings, which shouldn't move with the world.
+    pub fn screen_space(self) -> DebugDraw {
+        #[cfg(feature = "debug-draw")]
+        self.modify(|item| item.screen_space = true);
+
+        self
+    }
+
+    #[cfg(feature = "debug-draw")]
+    fn modify(&self, change: impl FnOnce(&mut Item)) {
+        QUEUE.with(|queue| {
+            let mut queue = queue.borrow_mut();
+            if let Some(item) = queue.items.iter_mut().rev().find(|item| item.id == self.id) {
+                change(item);
+            }
+        });
+    }
+}
+
+/// Draw a line between two points
+pub fn line(start: Vector, end: Vector, color: Color) -> DebugDraw {
+    queue(color, || Shape::Path(vec![start, end]))
+}
+
+/// Draw a series of lines connecting the given points, in order
+pub fn path(points: &[Vector], color: Color) -> DebugDraw {
+    queue(color, || Shape::Path(points.to_vec()))
+}
+
+/// Outline a rectangle
+pub fn rect(rect: Rectangle, color: Color) -> DebugDraw {
+    queue(color, || Shape::Rect(rect))
+}
+
+/// Outline a circle
+pub fn circle(circle: Circle, color: Color) -> DebugDraw {
+    queue(color, || Shape::Circle(circle))
+}
+
+/// Write some text with its top-left corner at the given position
+///
+/// Letters are drawn in upper case, and characters the built-in font doesn't have are drawn as
+/// question marks. Use `\n` to start a new line.
+pub fn text(position: Vector, text: &str, color: Color) -> DebugDraw {
+    queue(color, || Shape::Text(position, text.to_owned()))
+}
+
+/// Remove everything from the queue, including shapes that were persisted
+pub fn clear() {
+    #[cfg(feature = "debug-draw")]
+    QUEUE.with(|queue| queue.borrow_mut().items.clear());
+}
+
+#[cfg(feature = "debug-draw")]
+fn queue(color: Color, shape: impl FnOnce() -> Shape) -> DebugDraw {
+    QUEUE.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        let id = queue.next_id;
+        queue.next_id += 1;
+        queue.items.push(Item {
+            id,
+            shape: shape(),
+            color,
+            expires: None,
+            screen_space: false,
+        });
+
+        DebugDraw { id }
+    })
+}
+
+#[cfg(not(feature = "debug-draw"))]
+fn queue(_color: Color, _shape: impl FnOnce() -> Shape) -> DebugDraw {
+    DebugDraw {}
+}
+
+#[cfg_attr(not(feature = "debug-draw"), allow(dead_code))]
+enum Shape {
+    Path(Vec<Vector>),
+    Rect(Rectangle),
+    Circle(Circle),
+    Text(Vector, String),
+}
+
+#[cfg(feature = "debug-draw")]
+struct Item {
+    id: u64,
+    shape: Shape,
+    color: Color,
+    expires: Option<Instant>,
+    screen_space: bool,
+}
+
+#[cfg(feature = "debug-draw")]
+#[derive(Default)]
+struct Queue {
+    items: Vec<Item>,
+    next_id: u64,
+}
+
+#[cfg(feature = "debug-draw")]
+thread_local! {
+    static QUEUE: RefCell<Queue> = RefCell::new(Queue::default());
+}
+
+// Draw the queue over everything else, then drop the shapes that have expired
+#[cfg(feature = "debug-draw")]
+pub(crate) fn render(gfx: &mut Graphics) {
+    QUEUE.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        if queue.items.is_empty() {
+            return;
+        }
+        let view = gfx.current_view();
+        let blend_mode = gfx.current_blend_mode();
+        let transform = gfx.transform();
+        gfx.set_transform(Transform::IDENTITY);
+        gfx.set_blend_mode(Some(Default::default()));
+        for item in queue.items.iter().filter(|item| !item.screen_space) {
+            draw_item(gfx, item);
+        }
+        gfx.push_view(Transform::IDENTITY);
+        for item in queue.items.iter().filter(|item| item.screen_space) {
+            draw_item(gfx, item);
+        }
+        gfx.push_view(view);
+        gfx.set_blend_mode(blend_mode);
+        gfx.set_transform(transform);
+
+        expire(&mut queue.items, Instant::now());
+    });
+}
+
+#[cfg(feature = "debug-draw")]
+fn expire(items: &mut Vec<Item>, now: Instant) {
+    items.retain(|item| match item.expires {
+        Some(expires) => expires > now,
+        None => false,
+    });
+}
+
+#[cfg(feature = "debug-draw")]
+fn draw_item(gfx: &mut Graphics, item: &Item) {
+    match &item.shape {
+        Shape::Path(points) => gfx.stroke_path(points, item.color),
+        Shape::Rect(rect) => gfx.stroke_rect(rect, item.color),
+        Shape::Circle(circle) => gfx.stroke_circle(circle, item.color),
+        Shape::Text(position, text) => {
+            for (x, y) in text_pixels(text) {
+                let pixel = Vector::new(x as f32, y as f32) * TEXT_SCALE;
+                let rect = Rectangle::new(*position + pixel, Vector::ONE * TEXT_SCALE);
+                gfx.fill_rect(&rect, item.color);
+            }
+        }
+    }
+}
+
+// How many units across each pixel of the debug font is
+#[cfg(feature = "debug-draw")]
+const TEXT_SCALE: f32 = 2.0;
+
+// The lit pixels of some text in the debug font, in font pixels from the top-left
+#[cfg(feature = "debug-draw")]
+fn text_pixels(text: &str) -> Vec<(u32, u32)> {
+    let mut pixels = Vec::new();
+    let (mut left, mut top) = (0, 0);
+    for c in text.chars() {
+        if c == '\n' {
+            left = 0;
+            top += GLYPH_HEIGHT + 1;
+            continue;
+        }
+        let bits = glyph(c);
+        for y in 0..GLYPH_HEIGHT {
+            for x in 0..GLYPH_WIDTH {
+                let bit = (GLYPH_HEIGHT - y) * GLYPH_WIDTH - x - 1;
+                if bits & (1 << bit) != 0 {
+                    pixels.push((left + x, top + y));
+                }
+            }
+        }
+        left += GLYPH_WIDTH + 1;
+    }
+
+    pixels
+}
+
+#[cfg(feature = "debug-draw")]
+const GLYPH_WIDTH: u32 = 3;
+#[cfg(feature = "debug-draw")]
+const GLYPH_HEIGHT: u32 = 5;
+
+// Each glyph is 3 pixels wide and 5 tall, stored a row at a time from the top
+#[cfg(feature = "debug-draw")]
+fn glyph(c: char) -> u16 {
+    match c.to_ascii_uppercase() {
+        ' ' => 0,
+        'A' => 0b010_101_111_101_101,
+        'B' => 0b110_101_110_101_110,
+        'C' => 0b011_100_100_100_011,
+        'D' => 0b110_101_101_101_110,
+        'E' => 0b111_100_110_100_111,
+        'F' => 0b111_100_110_100_100,
+        'G' => 0b011_100_101_101_011,
+        'H' => 0b101_101_111_101_101,
+        'I' => 0b111_010_010_010_111,
+        'J' => 0b001_001_001_101_010,
+        'K' => 0b101_101_110_101_101,
+        'L' => 0b100_100_100_100_111,
+        'M' => 0b101_111_111_101_101,
+        'N' => 0b110_101_101_101_101,
+        'O' => 0b010_101_101_101_010,
+        'P' => 0b110_101_110_100_100,
+        'Q' => 0b010_101_101_110_011,
+        'R' => 0b110_101_110_101_101,
+        'S' => 0b011_100_010_001_110,
+        'T' => 0b111_010_010_010_010,
+        'U' => 0b101_101_101_101_111,
+        'V' => 0b101_101_101_101_010,
+        'W' => 0b101_101_111_111_101,
+        'X' => 0b101_101_010_101_101,
+        'Y' => 0b101_101_010_010_010,
+        'Z' => 0b111_001_010_100_111,
+        '0' => 0b111_101_101_101_111,
+        '1' => 0b010_110_010_010_111,
+        '2' => 0b110_001_010_100_111,
+        '3' => 0b110_001_010_001_110,
+        '4' => 0b101_101_111_001_001,
+        '5' => 0b111_100_110_001_110,
+        '6' => 0b011_100_111_101_111,
+        '7' => 0b111_001_010_010_010,
+        '8' => 0b111_101_111_101_111,
+        '9' => 0b111_101_111_001_110,
+        '.' => 0b000_000_000_000_010,
+        ',' => 0b000_000_000_010_100,
+        ':' => 0b000_010_000_010_000,
+        ';' => 0b000_010_000_010_100,
+        '!' => 0b010_010_010_000_010,
+        '-' => 0b000_000_111_000_000,
+        '+' => 0b000_010_111_010_000,
+        '=' => 0b000_111_000_111_000,
+        '(' => 0b001_010_010_010_001,
+        ')' => 0b100_010_010_010_100,
+        '[' => 0b011_010_010_010_011,
+        ']' => 0b110_010_010_010_110,
+        '/' => 0b001_001_010_100_100,
+        '\\' => 0b100_100_010_001_001,
+        '_' => 0b000_000_000_000_111,
+        '\'' => 0b010_010_000_000_000,
+        '"' => 0b101_101_000_000_000,
+        '#' => 0b101_111_101_111_101,
+        '%' => 0b101_001_010_100_101,
+        '*' => 0b000_101_010_101_000,
+        '<' => 0b001_010_100_010_001,
+        '>' => 0b100_010_001_010_100,
+        // Anything else is drawn as a question mark
+        _ => 0b110_001_010_000_010,
+    }
+}
+
+#[cfg(all(test, feature = "debug-draw"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persisted_shapes_outlive_the_frame() {
+        clear();
+        line(Vector::ZERO, Vector::ONE, Color::RED);
+        rect(Rectangle::new_sized(Vector::ONE), Color::RED).persist(Duration::from_secs(60));
+        QUEUE.with(|queue| {
+            let mut queue = queue.borrow_mut();
+            expire(&mut queue.items, Instant::now());
+            assert_eq!(queue.items.len(), 1);
+            assert!(matches!(queue.items[0].shape, Shape::Rect(_)));
+            expire(&mut queue.items, Instant::now() + Duration::from_secs(61));
+            assert!(queue.items.is_empty());
+        });
+    }
+
+    #[test]
+    fn text_is_laid_out_in_rows() {
+        let pixels = text_pixels("1\n_");
+        // The 1 has a serif on its second row, and the underscore sits on the next line's bottom
+        assert!(pixels.contains(&(0, 1)));
+        assert!(pixels.contains(&(2, 4)));
+        assert!(pixels.contains(&(0, 10)));
+        assert!(!pixels.contains(&(0, 9)));
+        assert_eq!(text_pixels("?"), text_pixels("\u{2603}"));
+    }
+}
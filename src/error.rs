@@ -24,6 +24,15 @@ pub enum QuicksilverError {
     NoSurfaceImageBound,
     #[cfg(feature = "font")]
     FontError(FontError),
+    /// An animated GIF or APNG couldn't be decoded
+    #[cfg(any(feature = "gif", feature = "apng"))]
+    AnimationError(String),
+    /// A sprite sheet exported by Aseprite couldn't be parsed
+    #[cfg(feature = "aseprite")]
+    AsepriteError(String),
+    /// An SVG document couldn't be parsed
+    #[cfg(feature = "svg")]
+    SvgError(usvg::Error),
 }
 
 #[cfg(feature = "font")]
@@ -40,6 +49,10 @@ pub enum FontError {
     /// This is unlikely to be an issue for most applications, but if a character is rendered at a
     /// size larger than 2048x2048, this error may occur.
     StringTooLarge,
+    /// A bitmap font file couldn't be parsed
+    InvalidBitmapFont(String),
+    /// Rich text markup couldn't be parsed
+    InvalidMarkup(String),
 }
 
 impl From<ImageError> for QuicksilverError {
@@ -67,6 +80,13 @@ impl From<FontError> for QuicksilverError {
     }
 }
 
+#[cfg(feature = "svg")]
+impl From<usvg::Error> for QuicksilverError {
+    fn from(err: usvg::Error) -> QuicksilverError {
+        QuicksilverError::SvgError(err)
+    }
+}
+
 impl Display for QuicksilverError {
     fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
         match self {
@@ -90,6 +110,28 @@ impl Display for QuicksilverError {
                 fmt,
                 "A word or glyph passed to a font was too large to render."
             ),
+            #[cfg(feature = "font")]
+            QuicksilverError::FontError(FontError::InvalidBitmapFont(message)) => {
+                write!(fmt, "The bitmap font couldn't be parsed: {}", message)
+            }
+            #[cfg(feature = "font")]
+            QuicksilverError::FontError(FontError::InvalidMarkup(message)) => {
+                write!(fmt, "The rich text markup couldn't be parsed: {}", message)
+            }
+            #[cfg(any(feature = "gif", feature = "apng"))]
+            QuicksilverError::AnimationError(message) => {
+                write!(fmt, "The animation couldn't be decoded: {}", message)
+            }
+            #[cfg(feature = "aseprite")]
+            QuicksilverError::AsepriteError(message) => {
+                write!(
+                    fmt,
+                    "The Aseprite sprite sheet couldn't be parsed: {}",
+                    message
+                )
+            }
+            #[cfg(feature = "svg")]
+            QuicksilverError::SvgError(e) => e.fmt(fmt),
         }
     }
 }
@@ -100,6 +142,8 @@ impl Error for QuicksilverError {
             QuicksilverError::ImageError(e) => Some(e),
             QuicksilverError::IOError(e) => Some(e),
             QuicksilverError::GraphicsError(e) => Some(e),
+            #[cfg(feature = "svg")]
+            QuicksilverError::SvgError(e) => Some(e),
             _ => None,
         }
     }
@@ -11,27 +11,56 @@
 
 use crate::QuicksilverError;
 
+mod animation;
 mod circle_points;
 mod color;
+mod distance_field;
 #[cfg(feature = "font")]
 mod font;
 mod image;
+mod lighting;
+mod mask;
 mod mesh;
+mod parallax;
+mod pixmap;
+mod recorder;
 mod resize_handler;
+mod sprite;
+mod sprite_sheet;
 mod surface;
+#[cfg(feature = "svg")]
+mod svg;
 mod vertex;
 
+pub use self::animation::Animation;
 pub use self::color::Color;
+pub use self::distance_field::{DistanceField, TextEffects};
 #[cfg(feature = "ttf")]
-pub use self::font::VectorFont;
+pub use self::font::{BakedFont, VectorFont};
 #[cfg(feature = "font")]
-pub use self::font::{FontRenderer, LayoutGlyph};
+pub use self::font::{
+    BitmapFont, FontRenderer, HorizontalAlign, InlineImage, LayoutGlyph, LineMetrics, MissingGlyph,
+    RichLayout, RichSpan, RichText, TextLayout, TextMetrics, TextOptions, TextOverflow, TextStyle,
+    VerticalAlign,
+};
 pub use self::image::Image;
+pub use self::lighting::{Light, LightMap, Occluder};
+pub use self::mask::{Mask, MaskMode};
 pub use self::mesh::Mesh;
-pub use self::resize_handler::ResizeHandler;
+pub use self::parallax::ParallaxLayer;
+pub use self::pixmap::{ImageEncoding, Pixmap};
+pub use self::recorder::FrameRecorder;
+pub use self::resize_handler::{Letterbox, ResizeHandler};
+pub use self::sprite::Sprite;
+pub use self::sprite_sheet::{
+    AnimationClip, PlayDirection, SheetFrame, Slice, SliceKey, SpriteSheet,
+};
 pub use self::surface::Surface;
+#[cfg(feature = "svg")]
+pub use self::svg::Svg;
 pub use self::vertex::{Element, Vertex};
 
+use self::distance_field::FieldState;
 use crate::geom::*;
 use crate::Window;
 use golem::*;
@@ -82,11 +111,32 @@ pub struct Graphics {
     geom_mode_changes: Vec<(usize, GeometryMode)>,
     clear_changes: Vec<(usize, Color)>,
     blend_mode_changes: Vec<(usize, Option<blend::BlendMode>)>,
+    field_changes: Vec<(usize, Option<FieldState>)>,
     transform: Transform,
     unproject_view: Transform,
     resize: ResizeHandler,
     world_size: Vector,
     projection: Transform,
+    applied_view: Transform,
+    applied_blend_mode: Option<blend::BlendMode>,
+    applied_field: Option<FieldState>,
+    canvas: Option<Surface>,
+    letterbox: Letterbox,
+    frame: Option<Surface>,
+    offscreen: Option<Surface>,
+    window_size: Vector,
+}
+
+// The draw data that has been queued but not yet sent to the GPU
+struct PendingDraws {
+    vertex_data: Vec<f32>,
+    index_data: Vec<u32>,
+    image_changes: Vec<(usize, Image)>,
+    view_changes: Vec<(usize, Transform)>,
+    geom_mode_changes: Vec<(usize, GeometryMode)>,
+    clear_changes: Vec<(usize, Color)>,
+    blend_mode_changes: Vec<(usize, Option<blend::BlendMode>)>,
+    field_changes: Vec<(usize, Option<FieldState>)>,
 }
 
 const VERTEX_SIZE: usize = 8;
@@ -110,6 +160,17 @@ impl Graphics {
                     Uniform::new("image", UniformType::Sampler2D),
                     Uniform::new("projection", UniformType::Matrix(D3)),
                     Uniform::new("view", UniformType::Matrix(D3)),
+                    Uniform::new("image_size", UniformType::Vector(NumberType::Float, D2)),
+                    Uniform::new("field_mode", UniformType::Scalar(NumberType::Int)),
+                    Uniform::new("field_range", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("field_smoothing", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("outline_width", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("outline_color", UniformType::Vector(NumberType::Float, D4)),
+                    Uniform::new("glow_width", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("glow_color", UniformType::Vector(NumberType::Float, D4)),
+                    Uniform::new("shadow_offset", UniformType::Vector(NumberType::Float, D2)),
+                    Uniform::new("shadow_softness", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("shadow_color", UniformType::Vector(NumberType::Float, D4)),
                 ],
                 vertex_shader: r#" void main() {
                 vec3 transformed = projection * view * vec3(vert_position, 1.0);
@@ -117,12 +178,53 @@ impl Graphics {
                 frag_uv = vert_uv;
                 frag_color = vert_color;
             }"#,
-                fragment_shader: r#" void main() {
+                fragment_shader: r#"
+            // The signed distance stored at a point of a distance field image
+            float field_distance(vec2 uv) {
+                vec4 texel = texture(image, uv);
+                if(field_mode == 2) {
+                    return max(min(texel.r, texel.g), min(max(texel.r, texel.g), texel.b));
+                }
+                return texel.a;
+            }
+
+            // Draw a premultiplied color over another
+            vec4 over(vec4 top, vec4 bottom) {
+                return top + bottom * (1.0 - top.a);
+            }
+
+            vec4 layer(vec3 color, float alpha) {
+                return vec4(color * alpha, alpha);
+            }
+
+            void main() {
                 vec4 tex = vec4(1);
                 if(frag_uv.x >= 0.0 && frag_uv.y >= 0.0) {
                     tex = texture(image, frag_uv);
                 }
-                gl_FragColor = tex * frag_color;
+                if(field_mode == 0 || frag_uv.x < 0.0 || frag_uv.y < 0.0) {
+                    gl_FragColor = tex * frag_color;
+                    return;
+                }
+                float unit = 0.5 / field_range;
+                float dist = field_distance(frag_uv);
+                float edge = 0.5 - outline_width * unit;
+
+                float shadow_dist = field_distance(frag_uv - shadow_offset / image_size);
+                float shadow_blur = field_smoothing + shadow_softness * unit;
+                float shadow = smoothstep(0.5 - shadow_blur, 0.5 + shadow_blur, shadow_dist);
+                vec4 color = layer(shadow_color.rgb, shadow * shadow_color.a);
+                float glow = smoothstep(edge - glow_width * unit - field_smoothing, edge, dist);
+                color = over(layer(glow_color.rgb, glow * glow_color.a), color);
+                float outline = smoothstep(edge - field_smoothing, edge + field_smoothing, dist);
+                color = over(layer(outline_color.rgb, outline * outline_color.a), color);
+                float fill = smoothstep(0.5 - field_smoothing, 0.5 + field_smoothing, dist);
+                color = over(layer(frag_color.rgb, fill * frag_color.a), color);
+
+                if(color.a <= 0.0) {
+                    discard;
+                }
+                gl_FragColor = vec4(color.rgb / color.a, color.a);
             }"#,
             },
         )?;
@@ -143,6 +245,7 @@ impl Graphics {
             geom_mode_changes: Vec::new(),
             clear_changes: Vec::new(),
             blend_mode_changes: Vec::new(),
+            field_changes: Vec::new(),
             transform: Transform::IDENTITY,
             unproject_view: Transform::IDENTITY,
             resize: ResizeHandler::Fit {
@@ -151,6 +254,14 @@ impl Graphics {
             },
             world_size,
             projection: Transform::IDENTITY,
+            applied_view: Transform::IDENTITY,
+            applied_blend_mode: Some(Default::default()),
+            applied_field: None,
+            canvas: None,
+            letterbox: Letterbox::Unchanged,
+            frame: None,
+            offscreen: None,
+            window_size: world_size,
         })
     }
 
@@ -180,9 +291,14 @@ impl Graphics {
     /// great many objects (screen shake, rotations, etc.) setting the view matrix is a good way to
     /// do that.
     pub fn set_view(&mut self, transform: Transform) {
+        self.push_view(transform);
+        self.unproject_view = transform.inverse();
+    }
+
+    // Change the view for the following draws, without changing how the screen is unprojected
+    pub(crate) fn push_view(&mut self, transform: Transform) {
         let head = self.index_data.len();
         self.view_changes.push((head, transform));
-        self.unproject_view = transform.inverse();
     }
 
     /// Set the transformation matrix, which is applied to all vertices on the CPU
@@ -192,6 +308,27 @@ impl Graphics {
         self.transform = transform;
     }
 
+    #[cfg(feature = "debug-draw")]
+    pub(crate) fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    // The view that the next draw will use
+    pub(crate) fn current_view(&self) -> Transform {
+        match self.view_changes.last() {
+            Some((_, view)) => *view,
+            None => self.applied_view,
+        }
+    }
+
+    // The blend mode that the next draw will use
+    pub(crate) fn current_blend_mode(&self) -> Option<blend::BlendMode> {
+        match self.blend_mode_changes.last() {
+            Some((_, blend_mode)) => *blend_mode,
+            None => self.applied_blend_mode,
+        }
+    }
+
     /// Project a point from the screen to the world
     ///
     /// Use this when checking the mouse position against rendered objects, like a game or UI. The
@@ -202,12 +339,22 @@ impl Graphics {
     /// [`set_view`]: Graphics::set_view
     pub fn screen_to_camera(&self, window: &Window, position: Vector) -> Vector {
         let viewport = self.calculate_viewport(window);
+        let camera = self.camera_area(window.size());
         let mut projected = position - viewport.top_left();
 
-        projected.x *= self.world_size.x / viewport.width();
-        projected.y *= self.world_size.y / viewport.height();
+        projected.x *= camera.width() / viewport.width();
+        projected.y *= camera.height() / viewport.height();
+
+        self.unproject_view * (camera.top_left() + projected)
+    }
 
-        self.unproject_view * projected
+    /// Check if a point on the screen falls in the letterbox bars, outside the content area
+    ///
+    /// Pointer and touch events in the bars still get projected by
+    /// [`Graphics::screen_to_camera`], but they land outside the camera area, so use this to
+    /// ignore them.
+    pub fn is_letterboxed(&self, window: &Window, position: Vector) -> bool {
+        !self.calculate_viewport(window).contains(position)
     }
 
     /// Set the size of the virtual camera
@@ -237,6 +384,47 @@ impl Graphics {
         self.resize = resize;
     }
 
+    /// Render at a fixed, low resolution and scale the result up to the window
+    ///
+    /// This is meant for pixel art: everything drawn is rendered to a `width` by `height` canvas,
+    /// which is then scaled up to the window by a whole number with nearest-neighbor filtering, so
+    /// every canvas pixel becomes a crisp square on screen. The camera size is set to the size of
+    /// the canvas and the resize handler to [`ResizeHandler::IntegerScale`], so
+    /// [`Graphics::screen_to_camera`] maps the mouse to canvas pixels. The area around the scaled
+    /// canvas is cleared to black.
+    ///
+    /// Pass `None` to go back to drawing directly to the window. The camera size and resize
+    /// handler are left as they are.
+    pub fn set_pixel_canvas(&mut self, size: Option<(u32, u32)>) -> Result<(), QuicksilverError> {
+        self.canvas = match size {
+            Some((width, height)) => {
+                let image = Image::from_raw(self, None, width, height, PixelFormat::RGBA)?;
+                image.set_minification(TextureFilter::Nearest)?;
+                image.set_magnification(TextureFilter::Nearest)?;
+                self.set_camera_size(Vector::new(width as f32, height as f32));
+                self.set_resize_handler(ResizeHandler::IntegerScale {
+                    aspect_width: width,
+                    aspect_height: height,
+                });
+                Some(Surface::new(self, image)?)
+            }
+            None => None,
+        };
+
+        Ok(())
+    }
+
+    /// Choose what to draw in the bars around the content, see [`Letterbox`]
+    ///
+    /// By default the bars are left alone.
+    pub fn set_letterbox(&mut self, letterbox: Letterbox) {
+        // Only the blurred frame needs the frame rendered offscreen
+        if !matches!(letterbox, Letterbox::BlurredFrame(_)) {
+            self.frame = None;
+        }
+        self.letterbox = letterbox;
+    }
+
     /// Set the blend mode, which determines how pixels mix when drawn over each other
     ///
     /// Pass `None` to disable blending entirely
@@ -245,6 +433,56 @@ impl Graphics {
         self.blend_mode_changes.push((head, blend_mode));
     }
 
+    /// Draw images as distance fields with the given effects, until this is set back to `None`
+    ///
+    /// Distance fields stay sharp when scaled up or rotated. Font renderers made with
+    /// [`VectorFont::to_distance_field_renderer`] set this while drawing text, so this is mostly
+    /// useful for distance field images from other tools, like icons.
+    ///
+    /// The edges are antialiased based on the current view and transform, assuming each texel is
+    /// drawn one unit wide, so set this after setting the transform.
+    ///
+    /// [`VectorFont::to_distance_field_renderer`]: crate::graphics::VectorFont::to_distance_field_renderer
+    pub fn set_distance_field(&mut self, field: Option<DistanceField>, effects: TextEffects) {
+        match field {
+            Some(field) => self.set_scaled_distance_field(field, effects, 1.0),
+            None => {
+                let head = self.index_data.len();
+                insert_if_changed(&mut self.field_changes, (head, &None), PartialEq::eq);
+            }
+        }
+    }
+
+    // Draw a distance field image with each texel drawn `scale` units wide
+    pub(crate) fn set_scaled_distance_field(
+        &mut self,
+        field: DistanceField,
+        effects: TextEffects,
+        scale: f32,
+    ) {
+        let state = {
+            // Find how many pixels each unit covers after the view and transform
+            let camera = self.camera_area(self.window_size);
+            let pixels = self.window_size.x / camera.width().max(f32::EPSILON);
+            let matrix = self.current_view() * self.transform;
+            let origin = matrix * Vector::ZERO;
+            let scale = ((matrix * Vector::X - origin).len() + (matrix * Vector::Y - origin).len())
+                / 2.0
+                * pixels
+                * scale;
+            // Antialias over about a pixel
+            let smoothing = 0.25 / (field.range() * scale).max(f32::EPSILON);
+
+            Some(FieldState {
+                field,
+                effects,
+                smoothing: smoothing.min(0.5),
+            })
+        };
+        let head = self.index_data.len();
+        insert_if_changed(&mut self.field_changes, (head, &state), PartialEq::eq);
+    }
+
     /// Draw a collection of vertices
     ///
     /// Elements determines how to interpret the vertices. While it is convenient to mix-and-match
@@ -448,9 +686,8 @@ impl Graphics {
         location: Rectangle,
         tint: Color,
     ) {
-        let size = image.size();
         // Calculate the region of the image to draw
-        let size_recip = size.recip();
+        let size_recip = image.texture_size().recip();
         let min_uv = region.pos.times(size_recip);
         let max_uv = (region.pos + region.size).times(size_recip);
         // Calculate how big to draw it
@@ -484,6 +721,52 @@ impl Graphics {
         );
     }
 
+    /// Draw a [`Sprite`], with its position, origin, rotation, scale, flip and tint applied
+    ///
+    /// Like the other draw functions, the current transform is applied as well.
+    pub fn draw_sprite(&mut self, sprite: &Sprite) {
+        let corners = sprite.corners();
+        let uvs = sprite.uvs();
+        let vertices = corners.iter().zip(uvs.iter()).map(|(pos, uv)| Vertex {
+            pos: *pos,
+            uv: Some(*uv),
+            color: sprite.tint,
+        });
+        let indices = [Element::Triangle([0, 1, 2]), Element::Triangle([2, 3, 0])];
+        self.draw_elements(vertices, indices.iter().cloned(), Some(&sprite.image));
+    }
+
+    /// Draw several sprites in order of their `z`, from lowest to highest
+    ///
+    /// Sprites with the same `z` are drawn in the order they're given. Consecutive sprites that
+    /// share an image are drawn in the same batch, so sorting by image within each `z` is faster.
+    pub fn draw_sprites<'a>(&mut self, sprites: impl IntoIterator<Item = &'a Sprite>) {
+        let mut sprites: Vec<&Sprite> = sprites.into_iter().collect();
+        sprites.sort_by(|a, b| a.z.partial_cmp(&b.z).unwrap_or(std::cmp::Ordering::Equal));
+        for sprite in sprites {
+            self.draw_sprite(sprite);
+        }
+    }
+
+    /// Draw a parallax background layer, tiled across the camera
+    ///
+    /// The layer is scrolled by the translation of the current view (see [`ParallaxLayer`]), and
+    /// only the tiles that land on the camera are drawn. Draw the layers furthest back first.
+    pub fn draw_parallax(&mut self, layer: &ParallaxLayer) {
+        let camera = self.camera_area(self.window_size);
+        let view = self.current_view();
+        // The view moves the world the opposite way to the camera
+        let scroll = -(view * Vector::ZERO);
+        let transform = self.transform;
+        self.transform = Transform::IDENTITY;
+        self.push_view(Transform::IDENTITY);
+        for tile in layer.visible_tiles(camera, scroll) {
+            self.draw_image(&layer.image, tile);
+        }
+        self.push_view(view);
+        self.transform = transform;
+    }
+
     /// Draw to a Surface
     pub fn flush_surface(&mut self, surface: &Surface) -> Result<(), QuicksilverError> {
         if let (Some(width), Some(height)) = (surface.0.width(), surface.0.height()) {
@@ -504,10 +787,37 @@ impl Graphics {
 
     /// Draw to the Window, without writing those changes to the screen
     pub fn flush_window(&mut self, window: &Window) -> Result<(), QuicksilverError> {
-        self.projection = Transform::orthographic(Rectangle::new_sized(self.world_size));
+        #[cfg(feature = "debug-draw")]
+        crate::debug::render(self);
+        self.window_size = window.size();
+        let camera = self.camera_area(self.window_size);
         let viewport = self.calculate_viewport(window);
         let offset = viewport.top_left() * window.scale_factor();
         let size = viewport.size() * window.scale_factor();
+
+        // Render the frame offscreen first, if it's drawn to a canvas or needed for the letterbox
+        let canvas_image = match self.canvas.take() {
+            Some(mut canvas) => {
+                let image = self.flush_surface(&canvas).map(|_| {
+                    let image = canvas.detach().expect("The canvas always has an image");
+                    self.queue_frame_copy(&image, Rectangle::new_sized(self.world_size));
+                    image
+                });
+                self.canvas = Some(canvas);
+                Some(image?)
+            }
+            None => None,
+        };
+        let frame_image = match (&canvas_image, &self.letterbox) {
+            (None, Letterbox::BlurredFrame(_)) => {
+                let image = self.flush_frame(camera, size.x as u32, size.y as u32)?;
+                self.queue_frame_copy(&image, camera);
+                Some(image)
+            }
+            _ => None,
+        };
+
+        self.projection = Transform::orthographic(camera);
         self.ctx.set_viewport(
             offset.x as u32,
             offset.y as u32,
@@ -515,10 +825,433 @@ impl Graphics {
             size.y as u32,
         );
         golem::Surface::unbind(&self.ctx);
-        self.flush_gpu()?;
+        let mut result = self.flush_gpu();
+        if result.is_ok() {
+            let frame = canvas_image.as_ref().or(frame_image.as_ref());
+            result = self.flush_letterbox(window, viewport, frame);
+        }
+
+        if let (Some(canvas), Some(image)) = (self.canvas.as_mut(), canvas_image) {
+            canvas.attach(image)?;
+        }
+        if let (Some(frame), Some(image)) = (self.frame.as_mut(), frame_image) {
+            frame.attach(image)?;
+        }
+        result
+    }
+
+    // Render the queued draws to the offscreen frame, and detach its image so it can be drawn
+    fn flush_frame(
+        &mut self,
+        camera: Rectangle,
+        width: u32,
+        height: u32,
+    ) -> Result<Image, QuicksilverError> {
+        let mut frame = match self.frame.take() {
+            Some(mut frame) => {
+                if frame.size() != Some(Vector::new(width as f32, height as f32)) {
+                    frame.resize(self, width, height)?;
+                }
+                frame
+            }
+            None => Surface::with_size(self, width, height, PixelFormat::RGBA)?,
+        };
+        self.ctx.set_viewport(0, 0, width, height);
+        let flip = Transform::scale(Vector::new(1.0, -1.0));
+        self.projection = flip * Transform::orthographic(camera);
+        frame.0.bind();
+        let result = self.flush_gpu();
+        let image = frame.detach().expect("The frame always has an image");
+        self.frame = Some(frame);
+        result?;
+
+        Ok(image)
+    }
+
+    // Queue drawing a frame rendered offscreen over the camera area
+    fn queue_frame_copy(&mut self, image: &Image, camera: Rectangle) {
+        // Copy the frame exactly, and put back the render state for the next frame
+        let view = self.applied_view;
+        let blend_mode = self.applied_blend_mode;
+        let transform = self.transform;
+        self.transform = Transform::IDENTITY;
+        self.clear(Color::BLACK);
+        self.push_view(Transform::IDENTITY);
+        self.set_blend_mode(None);
+        self.draw_image(image, camera);
+        self.set_blend_mode(blend_mode);
+        self.push_view(view);
+        self.transform = transform;
+    }
+
+    // Draw the letterbox into the bars around the content, in window coordinates
+    fn flush_letterbox(
+        &mut self,
+        window: &Window,
+        viewport: Rectangle,
+        frame: Option<&Image>,
+    ) -> Result<(), QuicksilverError> {
+        let window_area = Rectangle::new_sized(window.size());
+        let bars = letterbox_bars(window_area, viewport);
+        if bars.is_empty() || matches!(self.letterbox, Letterbox::Unchanged) {
+            return Ok(());
+        }
+        let view = self.applied_view;
+        let blend_mode = self.applied_blend_mode;
+        let transform = self.transform;
+        self.transform = Transform::IDENTITY;
+        self.push_view(Transform::IDENTITY);
+        self.set_blend_mode(Some(Default::default()));
+        match (self.letterbox.clone(), frame) {
+            (Letterbox::Unchanged, _) => (),
+            (Letterbox::Color(color), _) => {
+                for bar in bars.iter() {
+                    self.fill_rect(bar, color);
+                }
+            }
+            (Letterbox::Image(image), _) => {
+                let cover = cover_area(window_area, image.size());
+                self.draw_covering(&image, cover, &bars, Color::WHITE);
+            }
+            (Letterbox::BlurredFrame(tint), Some(frame)) => {
+                // Average copies of the frame spread around a small circle
+                const OFFSETS: [(f32, f32); 9] = [
+                    (0.0, 0.0),
+                    (1.0, 0.0),
+                    (-1.0, 0.0),
+                    (0.0, 1.0),
+                    (0.0, -1.0),
+                    (0.7, 0.7),
+                    (-0.7, 0.7),
+                    (0.7, -0.7),
+                    (-0.7, -0.7),
+                ];
+                let radius = window_area.width().max(window_area.height()) * 0.02;
+                let cover = cover_area(window_area, frame.size());
+                for (i, (x, y)) in OFFSETS.iter().enumerate() {
+                    let shifted =
+                        Rectangle::new(cover.pos + Vector::new(*x, *y) * radius, cover.size);
+                    let tint = Color {
+                        a: 1.0 / (i + 1) as f32,
+                        ..tint
+                    };
+                    self.draw_covering(frame, shifted, &bars, tint);
+                }
+            }
+            (Letterbox::BlurredFrame(_), None) => (),
+        }
+        self.transform = transform;
+
+        self.projection = Transform::orthographic(window_area);
+        let size = window_area.size() * window.scale_factor();
+        self.ctx.set_viewport(0, 0, size.x as u32, size.y as u32);
+        let result = self.flush_gpu();
+        // The next frame should start with the state it would have had without the letterbox
+        self.push_view(view);
+        self.set_blend_mode(blend_mode);
+
+        result
+    }
+
+    // Draw the parts of an image placed over `cover` that fall within the bars
+    fn draw_covering(&mut self, image: &Image, cover: Rectangle, bars: &[Rectangle], tint: Color) {
+        let scale = image.size().times(cover.size.recip());
+        for bar in bars {
+            let region = Rectangle::new((bar.pos - cover.pos).times(scale), bar.size.times(scale));
+            self.draw_subimage_tinted(image, region, *bar, tint);
+        }
+    }
+
+    /// Render the draws queued since the window was last flushed into a [`Pixmap`]
+    ///
+    /// The window itself can't be read back, so this isn't a screenshot of it: only the draws
+    /// still waiting to be flushed are captured, so call it right before [`Graphics::present`] or
+    /// [`Graphics::flush_window`], and after any earlier flushes in the frame. The queued draws are
+    /// rendered to an offscreen image at the physical resolution of the content area, and are left
+    /// in place to be drawn to the window as normal. Any letterboxing added by the
+    /// [`ResizeHandler`] is not included.
+    pub fn render_pending_to_pixmap(
+        &mut self,
+        window: &Window,
+    ) -> Result<Pixmap, QuicksilverError> {
+        let size = self.calculate_viewport(window).size() * window.scale_factor();
+        let pending = self.clone_pending();
+        let camera = self.camera_area(window.size());
+        let view = self.applied_view;
+        self.render_offscreen(size.x as u32, size.y as u32, camera, view, move |gfx| {
+            gfx.restore_pending(pending)
+        })
+    }
+
+    // Render some draw calls to an image of the given size, and read the result back
+    //
+    // The target is kept around and reused by later calls, as long as the size doesn't change
+    pub(crate) fn render_offscreen(
+        &mut self,
+        width: u32,
+        height: u32,
+        camera: Rectangle,
+        view: Transform,
+        draw: impl FnOnce(&mut Graphics),
+    ) -> Result<Pixmap, QuicksilverError> {
+        let surface = match self.offscreen.take() {
+            Some(mut surface) => {
+                if surface.size() != Some(Vector::new(width as f32, height as f32)) {
+                    surface.resize(self, width, height)?;
+                }
+                surface
+            }
+            None => Surface::with_size(self, width, height, PixelFormat::RGBA)?,
+        };
+        let result = self.render_to_surface(&surface, camera, view, |gfx| {
+            gfx.transform = Transform::IDENTITY;
+            draw(gfx);
+            // Start from a blank target, since it may hold the results of an earlier call
+            gfx.clear_changes
+                .insert(0, (0, Color::from_rgba(0, 0, 0, 0.0)));
+        });
+        let data = result.map(|_| surface.screenshot(self, 0, 0, width, height, PixelFormat::RGBA));
+        self.offscreen = Some(surface);
+
+        Ok(Pixmap::from_raw(width, height, data?))
+    }
+
+    // Render some draw calls to a surface right away
+    //
+    // The draws queued before this call are set aside and restored afterwards, as is the GPU
+    // state touched by rendering, so this can be called at any point in a frame.
+    fn render_to_surface(
+        &mut self,
+        surface: &Surface,
+        camera: Rectangle,
+        view: Transform,
+        draw: impl FnOnce(&mut Graphics),
+    ) -> Result<(), QuicksilverError> {
+        let (width, height) = match (surface.0.width(), surface.0.height()) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Err(QuicksilverError::NoSurfaceImageBound),
+        };
+        let pending = self.take_pending();
+        let transform = self.transform;
+        let previous_view = self.applied_view;
+        let previous_blend_mode = self.applied_blend_mode;
+        let previous_field = self.applied_field;
+        draw(self);
+        self.view_changes.insert(0, (0, view));
+
+        self.ctx.set_viewport(0, 0, width, height);
+        let flip = Transform::scale(Vector::new(1.0, -1.0));
+        self.projection = flip * Transform::orthographic(camera);
+        surface.0.bind();
+        let result = self.flush_gpu();
+
+        // Put back the draws and render state the caller expects
+        self.restore_pending(pending);
+        self.transform = transform;
+        self.shader.set_uniform(
+            "view",
+            UniformValue::Matrix3(Self::transform_to_gl(previous_view)),
+        )?;
+        self.applied_view = previous_view;
+        self.ctx.set_blend_mode(previous_blend_mode);
+        self.applied_blend_mode = previous_blend_mode;
+        Self::set_field_uniforms(&mut self.shader, previous_field.as_ref())?;
+        self.applied_field = previous_field;
+
+        result
+    }
+
+    /// Draw some content clipped to the inside or outside of an arbitrary shape
+    ///
+    /// The `shape` closure draws the mask: anything drawn there, like shapes, meshes or images,
+    /// becomes part of the mask, weighted by its alpha. The `content` closure then draws what
+    /// should be clipped. Both closures use the current camera, view and transform, and neither
+    /// of them is drawn to the screen directly.
+    ///
+    /// ```no_run
+    /// # use quicksilver::{geom::{Circle, Rectangle, Vector}, graphics::{Color, Graphics, Image, Mask, MaskMode}};
+    /// # fn portrait(gfx: &mut Graphics, mask: &mut Mask, image: &Image) -> quicksilver::Result<()> {
+    /// gfx.draw_masked(
+    ///     mask,
+    ///     MaskMode::Inside,
+    ///     |gfx| gfx.fill_circle(&Circle::new(Vector::new(64.0, 64.0), 64.0), Color::WHITE),
+    ///     |gfx| gfx.draw_image(image, Rectangle::new_sized(Vector::new(128.0, 128.0))),
+    /// )
+    /// # }
+    /// ```
+    ///
+    /// This isn't stencil masking: neither the graphics backend nor the window [`Settings`] can
+    /// provide a stencil buffer, for surfaces or for the window. Instead the mask and content are
+    /// rendered to the images in the [`Mask`] right away, which costs two extra render passes, and
+    /// the result is drawn over the camera area. Unlike a stencil test, this keeps the edges of
+    /// the shape smooth, and partly transparent shapes fade the content out. If the mask was
+    /// already used since the last flush, this returns [`QuicksilverError::SurfaceImageError`].
+    ///
+    /// [`Settings`]: crate::Settings
+    pub fn draw_masked(
+        &mut self,
+        mask: &mut Mask,
+        mode: MaskMode,
+        shape: impl FnOnce(&mut Graphics),
+        content: impl FnOnce(&mut Graphics),
+    ) -> Result<(), QuicksilverError> {
+        let camera = self.camera_area(self.window_size);
+        let view = self.current_view();
+        let blend_mode = self.current_blend_mode();
+        let transparent = Color::from_rgba(0, 0, 0, 0.0);
+
+        // If the images are still waiting to be drawn, they can't be attached to the surface
+        if !mask.is_ready() {
+            return Err(QuicksilverError::SurfaceImageError);
+        }
+        let shape_image = mask.shape.take().expect("The mask is ready");
+        mask.shape_surface.attach(shape_image)?;
+        self.render_to_surface(&mask.shape_surface, camera, view, |gfx| {
+            gfx.clear(transparent);
+            gfx.set_blend_mode(Some(mask::layer_blend_mode()));
+            shape(gfx);
+        })?;
+        let shape_image = mask
+            .shape_surface
+            .detach()
+            .expect("The mask shape was just attached");
+
+        let content_image = mask.content.take().expect("The mask is ready");
+        mask.content_surface.attach(content_image)?;
+        self.render_to_surface(&mask.content_surface, camera, view, |gfx| {
+            gfx.clear(transparent);
+            gfx.set_blend_mode(Some(mask::layer_blend_mode()));
+            content(gfx);
+            gfx.transform = Transform::IDENTITY;
+            gfx.push_view(Transform::IDENTITY);
+            gfx.set_blend_mode(Some(mode.blend_mode()));
+            gfx.draw_image(&shape_image, camera);
+        })?;
+        let content_image = mask
+            .content_surface
+            .detach()
+            .expect("The mask content was just attached");
+
+        let transform = self.transform;
+        self.transform = Transform::IDENTITY;
+        self.push_view(Transform::IDENTITY);
+        self.set_blend_mode(Some(mask::premultiplied_blend_mode()));
+        self.draw_image(&content_image, camera);
+        self.set_blend_mode(blend_mode);
+        self.push_view(view);
+        self.transform = transform;
+
+        mask.shape = Some(shape_image);
+        mask.content = Some(content_image);
+
+        Ok(())
+    }
+
+    /// Light the scene drawn so far, casting shadows from the occluders
+    ///
+    /// The light map is cleared to the `ambient` color, each light is added on top of it, and
+    /// then everything drawn before this call is multiplied by the light map. Draw anything that
+    /// shouldn't be lit, like the UI, after calling this.
+    ///
+    /// ```no_run
+    /// # use quicksilver::{geom::{Rectangle, Vector}, graphics::{Color, Graphics, Light, LightMap, Occluder}};
+    /// # fn dungeon(gfx: &mut Graphics, light_map: &mut LightMap, torch: Vector) -> quicksilver::Result<()> {
+    /// let lights = [Light::point(torch, 200.0, Color::from_rgba(255, 200, 120, 1.0))];
+    /// let walls = [Occluder::from(Rectangle::new(Vector::new(100.0, 100.0), Vector::new(32.0, 32.0)))];
+    /// gfx.draw_lighting(light_map, Color::from_rgba(20, 20, 40, 1.0), &lights, &walls)
+    /// # }
+    /// ```
+    ///
+    /// Lights and occluders are positioned like any other draw, through the current view but
+    /// not the transform. Like [`Graphics::draw_masked`], this renders to the [`LightMap`] right
+    /// away, and returns [`QuicksilverError::SurfaceImageError`] if the light map was already used
+    /// since the last flush.
+    pub fn draw_lighting(
+        &mut self,
+        light_map: &mut LightMap,
+        ambient: Color,
+        lights: &[Light],
+        occluders: &[Occluder],
+    ) -> Result<(), QuicksilverError> {
+        let camera = self.camera_area(self.window_size);
+        let view = self.current_view();
+        let blend_mode = self.current_blend_mode();
+
+        if !light_map.is_ready() {
+            return Err(QuicksilverError::SurfaceImageError);
+        }
+        let image = light_map.image.take().expect("The light map is ready");
+        light_map.surface.attach(image)?;
+        self.render_to_surface(&light_map.surface, camera, view, |gfx| {
+            gfx.clear(ambient);
+            gfx.transform = Transform::IDENTITY;
+            gfx.set_blend_mode(Some(lighting::additive_blend_mode()));
+            for light in lights {
+                let (vertices, elements) = lighting::light_geometry(light, occluders);
+                gfx.draw_elements(vertices.into_iter(), elements.into_iter(), None);
+            }
+        })?;
+        let image = light_map
+            .surface
+            .detach()
+            .expect("The light map was just attached");
+
+        let transform = self.transform;
+        self.transform = Transform::IDENTITY;
+        self.push_view(Transform::IDENTITY);
+        self.set_blend_mode(Some(lighting::multiply_blend_mode()));
+        self.draw_image(&image, camera);
+        self.set_blend_mode(blend_mode);
+        self.push_view(view);
+        self.transform = transform;
+
+        light_map.image = Some(image);
+
         Ok(())
     }
 
+    fn take_pending(&mut self) -> PendingDraws {
+        use std::mem::take;
+        PendingDraws {
+            vertex_data: take(&mut self.vertex_data),
+            index_data: take(&mut self.index_data),
+            image_changes: take(&mut self.image_changes),
+            view_changes: take(&mut self.view_changes),
+            geom_mode_changes: take(&mut self.geom_mode_changes),
+            clear_changes: take(&mut self.clear_changes),
+            blend_mode_changes: take(&mut self.blend_mode_changes),
+            field_changes: take(&mut self.field_changes),
+        }
+    }
+
+    fn clone_pending(&self) -> PendingDraws {
+        PendingDraws {
+            vertex_data: self.vertex_data.clone(),
+            index_data: self.index_data.clone(),
+            image_changes: self.image_changes.clone(),
+            view_changes: self.view_changes.clone(),
+            geom_mode_changes: self.geom_mode_changes.clone(),
+            clear_changes: self.clear_changes.clone(),
+            blend_mode_changes: self.blend_mode_changes.clone(),
+            field_changes: self.field_changes.clone(),
+        }
+    }
+
+    fn restore_pending(&mut self, pending: PendingDraws) {
+        self.vertex_data = pending.vertex_data;
+        self.index_data = pending.index_data;
+        self.image_changes = pending.image_changes;
+        self.view_changes = pending.view_changes;
+        self.geom_mode_changes = pending.geom_mode_changes;
+        self.clear_changes = pending.clear_changes;
+        self.blend_mode_changes = pending.blend_mode_changes;
+        self.field_changes = pending.field_changes;
+    }
+
+    fn camera_area(&self, window_size: Vector) -> Rectangle {
+        self.resize.camera_area(self.world_size, window_size)
+    }
+
     fn calculate_viewport(&self, window: &Window) -> Rectangle {
         let size = self.resize.content_size(window.size());
         Rectangle::new((window.size() - size) / 2.0, size)
@@ -552,6 +1285,7 @@ impl Graphics {
             "projection",
             UniformValue::Matrix3(Self::transform_to_gl(self.projection)),
         )?;
+        Self::set_field_uniforms(&mut self.shader, self.applied_field.as_ref())?;
 
         let mut previous = 0;
         let mut element_mode = GeometryMode::Triangles;
@@ -562,14 +1296,20 @@ impl Graphics {
         let change_list = join_change_lists(
             join_change_lists(
                 join_change_lists(
-                    join_change_lists(self.image_changes.drain(..), self.view_changes.drain(..)),
-                    self.geom_mode_changes.drain(..),
+                    join_change_lists(
+                        join_change_lists(
+                            self.image_changes.drain(..),
+                            self.view_changes.drain(..),
+                        ),
+                        self.geom_mode_changes.drain(..),
+                    ),
+                    self.clear_changes.drain(..),
                 ),
-                self.clear_changes.drain(..),
+                self.blend_mode_changes.drain(..),
             ),
-            self.blend_mode_changes.drain(..),
+            self.field_changes.drain(..),
         );
-        for (index, changes) in change_list {
+        for (index, (changes, field)) in change_list {
             // Before we change state, draw the old state
             if previous != index {
                 unsafe {
@@ -578,34 +1318,48 @@ impl Graphics {
                 previous = index;
             }
             // Change the render state
-            if let Some(changes) = changes.0 {
+            if let Some(changes) = changes {
                 if let Some(changes) = changes.0 {
                     if let Some(changes) = changes.0 {
-                        // If we're switching what image to use, do so now
-                        if let Some(image) = changes.0 {
-                            let bind_point = std::num::NonZeroU32::new(TEX_BIND_POINT).unwrap();
-                            image.raw().set_active(bind_point);
-                            retained_image = Some(image);
+                        if let Some(changes) = changes.0 {
+                            // If we're switching what image to use, do so now
+                            if let Some(image) = changes.0 {
+                                let bind_point = std::num::NonZeroU32::new(TEX_BIND_POINT).unwrap();
+                                image.raw().set_active(bind_point);
+                                let size = image.size();
+                                self.shader.set_uniform(
+                                    "image_size",
+                                    UniformValue::Vector2([size.x, size.y]),
+                                )?;
+                                retained_image = Some(image);
+                            }
+                            // If we're switching what projection to use, do so now
+                            if let Some(view) = changes.1 {
+                                let matrix = Self::transform_to_gl(view);
+                                self.shader
+                                    .set_uniform("view", UniformValue::Matrix3(matrix))?;
+                                self.applied_view = view;
+                            }
                         }
-                        // If we're switching what projection to use, do so now
-                        if let Some(view) = changes.1 {
-                            let matrix = Self::transform_to_gl(view);
-                            self.shader
-                                .set_uniform("view", UniformValue::Matrix3(matrix))?;
+                        // If we're switching the element mode, do so now
+                        if let Some(g_m) = changes.1 {
+                            element_mode = g_m;
                         }
                     }
-                    // If we're switching the element mode, do so now
-                    if let Some(g_m) = changes.1 {
-                        element_mode = g_m;
+                    if let Some(color) = changes.1 {
+                        self.ctx.set_clear_color(color.r, color.g, color.b, color.a);
+                        self.ctx.clear();
                     }
                 }
-                if let Some(color) = changes.1 {
-                    self.ctx.set_clear_color(color.r, color.g, color.b, color.a);
-                    self.ctx.clear();
+                if let Some(blend_mode) = changes.1 {
+                    self.ctx.set_blend_mode(blend_mode);
+                    self.applied_blend_mode = blend_mode;
                 }
             }
-            if let Some(blend_mode) = changes.1 {
-                self.ctx.set_blend_mode(blend_mode);
+            // If we're switching to or from drawing distance fields, do so now
+            if let Some(field) = field {
+                Self::set_field_uniforms(&mut self.shader, field.as_ref())?;
+                self.applied_field = field;
             }
         }
         if previous != self.index_data.len() {
@@ -622,6 +1376,39 @@ impl Graphics {
         Ok(())
     }
 
+    fn set_field_uniforms(
+        shader: &mut ShaderProgram,
+        state: Option<&FieldState>,
+    ) -> Result<(), QuicksilverError> {
+        let (mode, range, smoothing, effects) = match state {
+            None => (0, 1.0, 0.0, TextEffects::default()),
+            Some(state) => {
+                let mode = match state.field {
+                    DistanceField::Sdf { .. } => 1,
+                    DistanceField::Msdf { .. } => 2,
+                };
+                (mode, state.field.range(), state.smoothing, state.effects)
+            }
+        };
+        let color = |color: Color| UniformValue::Vector4([color.r, color.g, color.b, color.a]);
+        shader.set_uniform("field_mode", UniformValue::Int(mode))?;
+        shader.set_uniform("field_range", UniformValue::Float(range))?;
+        shader.set_uniform("field_smoothing", UniformValue::Float(smoothing))?;
+        shader.set_uniform("outline_width", UniformValue::Float(effects.outline_width))?;
+        shader.set_uniform("outline_color", color(effects.outline_color))?;
+        shader.set_uniform("glow_width", UniformValue::Float(effects.glow_width))?;
+        shader.set_uniform("glow_color", color(effects.glow_color))?;
+        let offset = effects.shadow_offset;
+        shader.set_uniform("shadow_offset", UniformValue::Vector2([offset.x, offset.y]))?;
+        shader.set_uniform(
+            "shadow_softness",
+            UniformValue::Float(effects.shadow_softness),
+        )?;
+        shader.set_uniform("shadow_color", color(effects.shadow_color))?;
+
+        Ok(())
+    }
+
     // Handle converting a row-matrix transformation to a column-major array
     fn transform_to_gl(trans: Transform) -> [f32; 9] {
         let matrix: mint::RowMatrix3<f32> = trans.into();
@@ -642,6 +1429,38 @@ impl Graphics {
     }
 }
 
+// The areas of the window outside the content
+fn letterbox_bars(window: Rectangle, content: Rectangle) -> Vec<Rectangle> {
+    let content_bottom_right = content.pos + content.size;
+    let bars = [
+        Rectangle::new(window.pos, Vector::new(content.x(), window.height())),
+        Rectangle::new(
+            Vector::new(content_bottom_right.x, 0.0),
+            Vector::new(window.width() - content_bottom_right.x, window.height()),
+        ),
+        Rectangle::new(
+            Vector::new(content.x(), 0.0),
+            Vector::new(content.width(), content.y()),
+        ),
+        Rectangle::new(
+            Vector::new(content.x(), content_bottom_right.y),
+            Vector::new(content.width(), window.height() - content_bottom_right.y),
+        ),
+    ];
+
+    bars.iter()
+        .filter(|bar| bar.width() > 0.0 && bar.height() > 0.0)
+        .cloned()
+        .collect()
+}
+
+// The smallest area with the aspect ratio of `size` that covers the window, centered on it
+fn cover_area(window: Rectangle, size: Vector) -> Rectangle {
+    let scale = (window.width() / size.x).max(window.height() / size.y);
+    let size = size * scale;
+    Rectangle::new(window.pos + (window.size - size) / 2.0, size)
+}
+
 fn insert_if_changed<T: Clone>(
     buffer: &mut Vec<(usize, T)>,
     (index, value): (usize, &T),
@@ -0,0 +1,313 @@
+use super::{Graphics, Image, Pixmap};
+use crate::QuicksilverError;
+
+use std::path::Path;
+use std::time::Duration;
+
+/// A looping sequence of images, each shown for some length of time
+///
+/// Animations can be decoded from animated GIFs (with the `gif` feature) or APNGs (with the
+/// `apng` feature). Any other supported image format loads as an animation with a single frame.
+///
+/// To play an animation, keep track of how long it has been running and draw
+/// [`Animation::frame_at`] that elapsed time.
+#[derive(Clone)]
+pub struct Animation {
+    frames: Vec<(Image, Duration)>,
+    duration: Duration,
+}
+
+impl Animation {
+    /// Create an animation from a list of images and how long each should be shown
+    pub fn new(frames: Vec<(Image, Duration)>) -> Animation {
+        assert!(!frames.is_empty(), "An animation needs at least one frame");
+        let duration = frames.iter().map(|(_, delay)| *delay).sum();
+
+        Animation { frames, duration }
+    }
+
+    /// Upload a list of decoded frames to the GPU
+    ///
+    /// See [`Animation::decode_frames`]
+    pub fn from_pixmaps(
+        gfx: &Graphics,
+        frames: &[(Pixmap, Duration)],
+    ) -> Result<Animation, QuicksilverError> {
+        let frames = frames
+            .iter()
+            .map(|(pixmap, delay)| Ok((Image::from_pixmap(gfx, pixmap)?, *delay)))
+            .collect::<Result<Vec<_>, QuicksilverError>>()?;
+
+        Ok(Animation::new(frames))
+    }
+
+    /// Create an animation from an encoded image format
+    ///
+    /// Animated GIFs and APNGs are decoded frame by frame if the `gif` or `apng` features are
+    /// enabled. Anything else is decoded as a single, still frame.
+    pub fn from_encoded_bytes(gfx: &Graphics, raw: &[u8]) -> Result<Animation, QuicksilverError> {
+        Animation::from_pixmaps(gfx, &Animation::decode_frames(raw)?)
+    }
+
+    /// Load an animation from a file at the given path
+    ///
+    /// See [`Animation::from_encoded_bytes`]
+    pub async fn load(
+        gfx: &Graphics,
+        path: impl AsRef<Path>,
+    ) -> Result<Animation, QuicksilverError> {
+        let file_contents = platter::load_file(path).await?;
+        Animation::from_encoded_bytes(gfx, file_contents.as_slice())
+    }
+
+    /// Decode the frames of an animated image on the CPU, without uploading them
+    ///
+    /// Each frame is the full canvas of the animation, with the previous frames already
+    /// composited underneath it, along with how long it should be shown for.
+    pub fn decode_frames(raw: &[u8]) -> Result<Vec<(Pixmap, Duration)>, QuicksilverError> {
+        #[cfg(feature = "gif")]
+        {
+            if raw.starts_with(b"GIF8") {
+                return decode_gif(raw);
+            }
+        }
+        #[cfg(feature = "apng")]
+        {
+            if raw.starts_with(b"\x89PNG") {
+                return decode_apng(raw);
+            }
+        }
+
+        Ok(vec![(
+            Pixmap::from_encoded_bytes(raw)?,
+            Duration::from_secs(0),
+        )])
+    }
+
+    /// The frames of the animation, along with how long each is shown for
+    pub fn frames(&self) -> &[(Image, Duration)] {
+        &self.frames
+    }
+
+    /// How long the animation takes to play through once
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The index of the frame shown after the animation has been playing for `elapsed`
+    ///
+    /// The animation loops forever, so times past the end wrap around to the start.
+    pub fn frame_index_at(&self, elapsed: Duration) -> usize {
+        let total = self.duration.as_micros();
+        if total == 0 {
+            return 0;
+        }
+        let mut remaining = elapsed.as_micros() % total;
+        for (i, (_, delay)) in self.frames.iter().enumerate() {
+            let delay = delay.as_micros();
+            if remaining < delay {
+                return i;
+            }
+            remaining -= delay;
+        }
+
+        self.frames.len() - 1
+    }
+
+    /// The image shown after the animation has been playing for `elapsed`
+    pub fn frame_at(&self, elapsed: Duration) -> &Image {
+        &self.frames[self.frame_index_at(elapsed)].0
+    }
+}
+
+#[cfg(any(feature = "gif", feature = "apng"))]
+fn decode_error(err: impl std::fmt::Display) -> QuicksilverError {
+    QuicksilverError::AnimationError(err.to_string())
+}
+
+// Reset a region of the canvas to transparent black
+#[cfg(any(feature = "gif", feature = "apng"))]
+fn clear_region(canvas: &mut Pixmap, x: u32, y: u32, width: u32, height: u32) {
+    let right = (x + width).min(canvas.width());
+    let bottom = (y + height).min(canvas.height());
+    for y in y..bottom {
+        for x in x..right {
+            canvas.set_pixel(x, y, super::Color::from_rgba(0, 0, 0, 0.0));
+        }
+    }
+}
+
+#[cfg(feature = "gif")]
+fn decode_gif(raw: &[u8]) -> Result<Vec<(Pixmap, Duration)>, QuicksilverError> {
+    use image_gif::{ColorOutput, DisposalMethod, SetParameter};
+
+    let mut decoder = image_gif::Decoder::new(raw);
+    decoder.set(ColorOutput::RGBA);
+    let mut reader = decoder.read_info().map_err(decode_error)?;
+    let mut canvas = Pixmap::new(reader.width() as u32, reader.height() as u32);
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.read_next_frame().map_err(decode_error)? {
+        let previous = match frame.dispose {
+            DisposalMethod::Previous => Some(canvas.clone()),
+            _ => None,
+        };
+        let (x, y) = (frame.left as u32, frame.top as u32);
+        let (width, height) = (frame.width as u32, frame.height as u32);
+        let image = Pixmap::from_raw(width, height, frame.buffer.to_vec());
+        canvas.blit(&image, x as i32, y as i32);
+        // GIF delays are measured in hundredths of a second
+        frames.push((
+            canvas.clone(),
+            Duration::from_millis(frame.delay as u64 * 10),
+        ));
+        match (frame.dispose, previous) {
+            (DisposalMethod::Background, _) => clear_region(&mut canvas, x, y, width, height),
+            (_, Some(previous)) => canvas = previous,
+            _ => (),
+        }
+    }
+    if frames.is_empty() {
+        return Err(decode_error("GIF contains no frames"));
+    }
+
+    Ok(frames)
+}
+
+#[cfg(feature = "apng")]
+fn decode_apng(raw: &[u8]) -> Result<Vec<(Pixmap, Duration)>, QuicksilverError> {
+    use image_png::{BlendOp, DisposeOp, Transformations};
+
+    let mut decoder = image_png::Decoder::new(raw);
+    decoder.set_transformations(Transformations::normalize_to_color8() | Transformations::ALPHA);
+    let mut reader = decoder.read_info().map_err(decode_error)?;
+    let info = reader.info();
+    let mut canvas = Pixmap::new(info.width, info.height);
+    // The default image is only part of the animation if it has its own frame control chunk
+    let frame_count = match info.animation_control() {
+        Some(control) if info.frame_control().is_none() => control.num_frames + 1,
+        Some(control) => control.num_frames,
+        None => 1,
+    };
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let mut frames = Vec::new();
+    for _ in 0..frame_count {
+        let output = reader.next_frame(&mut buffer).map_err(decode_error)?;
+        let control = match reader.info().frame_control() {
+            Some(control) => *control,
+            None if reader.info().animation_control().is_some() => continue,
+            None => {
+                let data = expand_to_rgba(&buffer[..output.buffer_size()], output.color_type);
+                let image = Pixmap::from_raw(output.width, output.height, data);
+                return Ok(vec![(image, Duration::from_secs(0))]);
+            }
+        };
+        let data = expand_to_rgba(&buffer[..output.buffer_size()], output.color_type);
+        let image = Pixmap::from_raw(control.width, control.height, data);
+        let (x, y) = (control.x_offset, control.y_offset);
+        let previous = match control.dispose_op {
+            DisposeOp::Previous => Some(canvas.clone()),
+            _ => None,
+        };
+        if control.blend_op == BlendOp::Source {
+            clear_region(&mut canvas, x, y, control.width, control.height);
+        }
+        canvas.blit(&image, x as i32, y as i32);
+        // A zero denominator means the delay is in hundredths of a second
+        let denominator = match control.delay_den {
+            0 => 100,
+            den => den,
+        };
+        let delay = Duration::from_millis(control.delay_num as u64 * 1000 / denominator as u64);
+        frames.push((canvas.clone(), delay));
+        match (control.dispose_op, previous) {
+            (DisposeOp::Background, _) => {
+                clear_region(&mut canvas, x, y, control.width, control.height)
+            }
+            (_, Some(previous)) => canvas = previous,
+            _ => (),
+        }
+    }
+
+    Ok(frames)
+}
+
+// APNG frames come out as 8-bit RGBA or grayscale with alpha
+#[cfg(feature = "apng")]
+fn expand_to_rgba(data: &[u8], color_type: image_png::ColorType) -> Vec<u8> {
+    match color_type {
+        image_png::ColorType::GrayscaleAlpha => data
+            .chunks_exact(2)
+            .flat_map(|pixel| vec![pixel[0], pixel[0], pixel[0], pixel[1]])
+            .collect(),
+        _ => data.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn decodes_gif_frames() {
+        use image_gif::SetParameter;
+
+        let mut gif = Vec::new();
+        {
+            let mut encoder = image_gif::Encoder::new(&mut gif, 2, 2, &[]).unwrap();
+            encoder.set(image_gif::Repeat::Infinite).unwrap();
+            let mut red = [255, 0, 0, 255].repeat(4);
+            let mut frame = image_gif::Frame::from_rgba(2, 2, &mut red[..]);
+            frame.delay = 10;
+            encoder.write_frame(&frame).unwrap();
+            // Only update the top-right pixel, leaving the rest of the canvas as-is
+            let mut blue = [0, 0, 255, 255];
+            let mut frame = image_gif::Frame::from_rgba(1, 1, &mut blue[..]);
+            frame.left = 1;
+            frame.delay = 25;
+            encoder.write_frame(&frame).unwrap();
+        }
+        let frames = Animation::decode_frames(&gif[..]).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].1, Duration::from_millis(100));
+        assert_eq!(frames[1].1, Duration::from_millis(250));
+        assert_eq!(frames[1].0.pixel(0, 0), super::super::Color::RED);
+        assert_eq!(frames[1].0.pixel(1, 0), super::super::Color::BLUE);
+    }
+
+    #[cfg(feature = "apng")]
+    #[test]
+    fn decodes_apng_frames() {
+        let mut png = Vec::new();
+        {
+            let mut encoder = image_png::Encoder::new(&mut png, 2, 1);
+            encoder.set_color(image_png::ColorType::Rgba);
+            encoder.set_depth(image_png::BitDepth::Eight);
+            encoder.set_animated(2, 0).unwrap();
+            encoder.set_frame_delay(1, 4).unwrap();
+            let mut writer = encoder.write_header().unwrap();
+            writer
+                .write_image_data(&[255, 0, 0, 255, 255, 0, 0, 255])
+                .unwrap();
+            writer.set_frame_delay(1, 2).unwrap();
+            writer
+                .write_image_data(&[0, 255, 0, 255, 0, 255, 0, 255])
+                .unwrap();
+        }
+        let frames = Animation::decode_frames(&png[..]).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].1, Duration::from_millis(250));
+        assert_eq!(frames[1].1, Duration::from_millis(500));
+        assert_eq!(frames[0].0.pixel(1, 0), super::super::Color::RED);
+        assert_eq!(frames[1].0.pixel(1, 0), super::super::Color::GREEN);
+    }
+
+    #[test]
+    fn still_images_are_one_frame() {
+        let pixmap = Pixmap::from_raw(1, 1, vec![1, 2, 3, 255]);
+        let png = pixmap.encode(super::super::ImageEncoding::Png).unwrap();
+        let frames = Animation::decode_frames(&png[..]).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].0, pixmap);
+    }
+}
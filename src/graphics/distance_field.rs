@@ -0,0 +1,70 @@
+use super::Color;
+use crate::geom::Vector;
+
+/// How an image stores a signed distance field, which stays sharp when scaled or rotated
+///
+/// Each texel holds how far it is from the nearest edge of the shape, mapped so that 0.5 is on
+/// the edge and 0 and 1 are `range` texels outside and inside.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DistanceField {
+    /// The alpha channel holds the distance
+    ///
+    /// Single-channel fields are cheaper to generate, but round off sharp corners when scaled up.
+    Sdf {
+        /// How many texels away from the edge the field reaches
+        range: f32,
+    },
+    /// The red, green and blue channels each hold the distance to some of the edges, and the
+    /// median of the three is the distance
+    ///
+    /// Multi-channel fields keep corners sharp at any scale.
+    Msdf {
+        /// How many texels away from the edge the field reaches
+        range: f32,
+    },
+}
+
+impl DistanceField {
+    /// How many texels away from the edge the field reaches
+    pub fn range(&self) -> f32 {
+        match self {
+            DistanceField::Sdf { range } | DistanceField::Msdf { range } => *range,
+        }
+    }
+}
+
+/// Outlines, glows and drop shadows drawn around text
+///
+/// Set them with [`FontRenderer::set_effects`]. Widths and offsets are measured in pixels at the
+/// size the font was rendered at, which are texels of a distance field. Distance field effects
+/// only reach as far as the field's range from each glyph, so the outline and glow widths
+/// together, and the shadow offset and softness together, should stay within it. An effect with
+/// a transparent color isn't drawn, which is the default.
+///
+/// [`FontRenderer::set_effects`]: crate::graphics::FontRenderer::set_effects
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextEffects {
+    /// How far the outline extends past the edge of the glyphs
+    pub outline_width: f32,
+    /// The color of the outline
+    pub outline_color: Color,
+    /// How far a glow fades out past the outline
+    pub glow_width: f32,
+    /// The color of the glow
+    pub glow_color: Color,
+    /// How far the shadow is moved from the glyphs
+    pub shadow_offset: Vector,
+    /// How far the edge of the shadow is blurred
+    pub shadow_softness: f32,
+    /// The color of the shadow
+    pub shadow_color: Color,
+}
+
+// The state of the distance field part of the shader
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct FieldState {
+    pub field: DistanceField,
+    pub effects: TextEffects,
+    // How wide the antialiased edge is, in distance field units
+    pub smoothing: f32,
+}
@@ -1,10 +1,44 @@
 use super::*;
 
+#[cfg(feature = "ttf")]
+mod bake;
+mod bitmap;
+mod effects;
+mod layout;
+mod lines;
+mod rich;
+#[cfg(feature = "ttf")]
+mod sdf;
+#[cfg(feature = "shaping")]
+mod shaping;
+mod unicode;
+
+#[cfg(feature = "ttf")]
+pub use self::bake::BakedFont;
+pub use self::bitmap::BitmapFont;
+pub use self::layout::{
+    HorizontalAlign, LineMetrics, TextLayout, TextMetrics, TextOptions, TextOverflow, VerticalAlign,
+};
+pub use self::rich::{InlineImage, RichLayout, RichSpan, RichText, TextStyle};
+
+use self::effects::{EffectCache, EffectGlyph, EffectLayers};
+use self::layout::GlyphCaret;
+use self::lines::{PlacedGlyph, RunContent, TextBlock, TextRun};
+
 use crate::error::FontError;
-use elefont::{CacheError, FontCache, FontProvider, PixelType, Texture, TextureGlyph};
+use elefont::{
+    Bounds, CacheError, FontCache, FontProvider, Glyph, PixelType, Texture, TextureGlyph,
+};
 use std::iter;
+use std::mem;
+#[cfg(feature = "shaping")]
+use std::ops::Range;
 #[cfg(feature = "ttf")]
 use std::path::Path;
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "ttf")]
+use std::sync::Arc;
 
 #[cfg(feature = "ttf")]
 /// VectorFonts allow drawing characters from TTF files
@@ -14,28 +48,33 @@ use std::path::Path;
 /// create a [`FontRenderer`].
 ///
 /// [`to_renderer`]: VectorFont::to_renderer
-pub struct VectorFont(rusttype::Font<'static>);
+pub struct VectorFont {
+    font: rusttype::Font<'static>,
+    // The font file, which shaping reads the substitution and positioning tables from
+    #[cfg(feature = "shaping")]
+    data: Arc<[u8]>,
+}
 
 #[cfg(feature = "ttf")]
 impl VectorFont {
     /// Create a VectorFont from a slice of binary TTF data
     pub fn from_slice(data: &[u8]) -> Self {
-        VectorFont(
-            rusttype::FontCollection::from_bytes(data.to_vec())
-                .unwrap()
-                .into_font()
-                .unwrap(),
-        )
+        Self::from_bytes(data.to_vec())
     }
 
     /// Create a VectorFont from an owned Vec of TTF data
     pub fn from_bytes(data: Vec<u8>) -> Self {
-        VectorFont(
-            rusttype::FontCollection::from_bytes(data)
-                .unwrap()
-                .into_font()
-                .unwrap(),
-        )
+        let data: Arc<[u8]> = data.into();
+        let font = rusttype::FontCollection::from_bytes(data.clone())
+            .unwrap()
+            .into_font()
+            .unwrap();
+
+        VectorFont {
+            font,
+            #[cfg(feature = "shaping")]
+            data,
+        }
     }
 
     /// Load a VectorFont from a TTF file at the given path
@@ -46,17 +85,110 @@ impl VectorFont {
 
     /// Convert a VectorFont to a [`FontRenderer`] for actual use
     pub fn to_renderer(&self, gfx: &Graphics, font_size: f32) -> crate::Result<FontRenderer> {
-        let provider = elefont::rusttype_provider::SizedFont::new(self.0.clone(), font_size);
-        FontRenderer::from_font(gfx, Box::new(provider))
+        let provider = elefont::rusttype_provider::SizedFont::new(self.font.clone(), font_size);
+        let mut renderer = FontRenderer::from_font(gfx, Box::new(provider))?;
+        renderer.ascent = self
+            .font
+            .v_metrics(rusttype::Scale::uniform(font_size))
+            .ascent;
+        #[cfg(feature = "shaping")]
+        {
+            renderer.files[0] = self.shaping_file(font_size);
+        }
+
+        Ok(renderer)
+    }
+
+    /// Convert a VectorFont to a [`FontRenderer`] that caches glyphs as distance fields
+    ///
+    /// Distance field glyphs stay crisp when the text is scaled up or rotated, so one renderer
+    /// can draw text at many sizes, and they can be drawn with outlines, glows and drop shadows
+    /// (see [`FontRenderer::set_effects`]). The glyphs are rendered at `font_size`, which is the
+    /// size the text is laid out at; scale the text up or down with [`Graphics::set_transform`].
+    /// A `font_size` of around 32 to 48 and a range of around 4 works well for most text.
+    pub fn to_distance_field_renderer(
+        &self,
+        gfx: &Graphics,
+        font_size: f32,
+        field: DistanceField,
+    ) -> crate::Result<FontRenderer> {
+        let sized = elefont::rusttype_provider::SizedFont::new(self.font.clone(), font_size);
+        let provider = sdf::DistanceFieldFont::new(sized, field);
+        let mut renderer = FontRenderer::from_font(gfx, Box::new(provider))?;
+        renderer.ascent = self
+            .font
+            .v_metrics(rusttype::Scale::uniform(font_size))
+            .ascent;
+        if let GlyphSource::Cache(_, distance_field) = &mut renderer.source {
+            *distance_field = Some(field);
+        }
+        #[cfg(feature = "shaping")]
+        {
+            renderer.files[0] = self.shaping_file(font_size);
+        }
+
+        Ok(renderer)
+    }
+
+    // The font file to shape text with at a font size, if the shaper can read it
+    #[cfg(feature = "shaping")]
+    fn shaping_file(&self, font_size: f32) -> Option<shaping::FontFile> {
+        // Glyphs are scaled so the distance from the ascent to the descent is the font size
+        let v_metrics = self.font.v_metrics_unscaled();
+        let scale = font_size / (v_metrics.ascent - v_metrics.descent);
+        shaping::FontFile::new(self.data.clone(), scale)
     }
 }
 
-/// A FontRenderer pairs a font source (typically a [`VectorFont`] or bitmap font) and a GPU cache
-/// for efficient rendering
+/// A FontRenderer pairs a font source (typically a [`VectorFont`] or [`BitmapFont`]) and a GPU
+/// cache for efficient rendering
 ///
 /// Instead of uploading glyphs to the GPU every time they're drawn, this method allows for future
 /// draws to reference these glyphs multiple times.
-pub struct FontRenderer(FontCache<FontImage>);
+pub struct FontRenderer {
+    source: GlyphSource,
+    // How far the tops of the tallest glyphs are above the baseline
+    ascent: f32,
+    // Identifies the renderer, so layouts can tell when they're given a different font
+    id: u64,
+    fallbacks: Vec<GlyphSource>,
+    missing: MissingGlyph,
+    // The box drawn for missing characters, made the first time it's needed
+    tofu: Option<Image>,
+    // Drawn around the glyphs
+    effects: TextEffects,
+    // The outlines, glows and shadows of glyphs that aren't distance fields
+    effect_cache: EffectCache,
+    // The font file of each source that text is shaped with, by the index of the source
+    #[cfg(feature = "shaping")]
+    files: Vec<Option<shaping::FontFile>>,
+}
+
+static NEXT_RENDERER_ID: AtomicU64 = AtomicU64::new(0);
+
+fn next_renderer_id() -> u64 {
+    NEXT_RENDERER_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Bitmap fonts aren't a FontProvider in a cache: elefont's Metrics can't be built outside of
+// elefont, and the glyphs are already on the GPU, so copying them into a cache page would only
+// cost memory and upload time
+enum GlyphSource {
+    // Glyphs rasterized on demand into a texture, possibly as distance fields
+    Cache(FontCache<FontImage>, Option<DistanceField>),
+    // Glyphs already in the font's pages
+    Bitmap(BitmapFont),
+}
+
+// A glyph ready to be placed on a line
+struct WordGlyph {
+    glyph: TextureGlyph,
+    image: Image,
+    offset: Vector,
+    advance: f32,
+    // Which of the renderer's sources the glyph came from
+    source: usize,
+}
 
 impl FontRenderer {
     /// Create a font from an arbitrary [`FontProvider`]
@@ -64,9 +196,44 @@ impl FontRenderer {
     /// If you want to load a TTF file, consider [`VectorFont::load`] and [`VectorFont::to_renderer`]
     /// instead.
     pub fn from_font(gfx: &Graphics, source: Box<dyn FontProvider>) -> crate::Result<Self> {
+        // Providers don't report their ascent, so estimate it from the height of a capital
+        let capital = source.single_glyph('M');
+        let ascent = source
+            .metrics(capital)
+            .bounds
+            .map_or(source.line_height() * 0.8, |bounds| -bounds.y as f32);
         let cache = FontCache::new(source, FontImage::new(gfx)?);
 
-        Ok(Self(cache))
+        Ok(Self {
+            source: GlyphSource::Cache(cache, None),
+            ascent,
+            id: next_renderer_id(),
+            fallbacks: Vec::new(),
+            missing: MissingGlyph::Tofu,
+            tofu: None,
+            effects: TextEffects::default(),
+            effect_cache: EffectCache::default(),
+            #[cfg(feature = "shaping")]
+            files: vec![None],
+        })
+    }
+
+    /// Create a font that draws glyphs straight from a [`BitmapFont`]'s images
+    ///
+    /// Bitmap fonts are drawn at the size they were rendered at, and don't need a glyph cache.
+    pub fn from_bitmap(font: BitmapFont) -> Self {
+        Self {
+            ascent: font.base,
+            source: GlyphSource::Bitmap(font),
+            id: next_renderer_id(),
+            fallbacks: Vec::new(),
+            missing: MissingGlyph::Tofu,
+            tofu: None,
+            effects: TextEffects::default(),
+            effect_cache: EffectCache::default(),
+            #[cfg(feature = "shaping")]
+            files: vec![None],
+        }
     }
 
     /// Draw some text to the screen with a given color at a given position, returning the text
@@ -91,6 +258,10 @@ impl FontRenderer {
     /// If a maximum width is provided, the text will not extend beyond it. If a word encounters
     /// the maximum width, it will be wrapped down to a newline. The returned value is how far the
     /// text extended past the offset, e.g. the furthest right and furthest down position.
+    ///
+    /// To limit the number of lines, cut text off with an ellipsis or shrink it to fit, use
+    /// [`FontRenderer::draw_with_options`] with [`TextOptions::max_lines`] and
+    /// [`TextOptions::overflow`].
     pub fn draw_wrapping(
         &mut self,
         gfx: &mut Graphics,
@@ -99,22 +270,52 @@ impl FontRenderer {
         color: Color,
         offset: Vector,
     ) -> crate::Result<Vector> {
-        self.layout_glyphs(gfx, text, max_width, |gfx, layout| {
-            let LayoutGlyph {
-                position,
-                glyph,
-                image,
-            } = layout;
-
-            let tex_bounds = glyph.bounds;
-            let glyph_size = Vector::new(tex_bounds.width as f32, tex_bounds.height as f32);
-            let region = Rectangle::new(
-                Vector::new(tex_bounds.x as f32, tex_bounds.y as f32),
-                glyph_size,
-            );
-            let location = Rectangle::new(offset + position, glyph_size);
-            gfx.draw_subimage_tinted(&image, region, location, color);
-        })
+        let options = TextOptions {
+            max_width,
+            ..TextOptions::default()
+        };
+
+        Ok(self
+            .draw_with_options(gfx, text, &options, color, offset)?
+            .extents)
+    }
+
+    /// Draw some text to the screen with a given color at a given position, laid out with the
+    /// given options
+    ///
+    /// The offset is the top-left of the box the text is aligned in. The returned value describes
+    /// where each line ended up, relative to the offset.
+    pub fn draw_with_options(
+        &mut self,
+        gfx: &mut Graphics,
+        text: &str,
+        options: &TextOptions,
+        color: Color,
+        offset: Vector,
+    ) -> crate::Result<TextMetrics> {
+        let mut glyphs = Vec::new();
+        let mut carets = Vec::new();
+        let metrics = self.arrange(gfx, text, options, &mut glyphs, &mut carets)?;
+        // Draw every glyph's effects first, so they don't cover the glyphs next to them
+        self.effect_layers(gfx, &glyphs, &carets)?.draw(gfx, offset);
+        for glyph in glyphs.iter() {
+            draw_glyph(gfx, glyph, color, offset, self.effects);
+        }
+        gfx.set_distance_field(None, TextEffects::default());
+
+        Ok(metrics)
+    }
+
+    /// Lay out some text once, so it can be measured, hit-tested and drawn many times
+    ///
+    /// See [`TextLayout`] for more.
+    pub fn layout(
+        &mut self,
+        gfx: &Graphics,
+        text: impl Into<String>,
+        options: &TextOptions,
+    ) -> crate::Result<TextLayout> {
+        TextLayout::new(gfx, self, text.into(), *options)
     }
 
     /// Lay out the given text at a given font size, with a given maximum width, returning its
@@ -127,22 +328,491 @@ impl FontRenderer {
         gfx: &mut Graphics,
         text: &str,
         max_width: Option<f32>,
-        mut callback: impl FnMut(&mut Graphics, LayoutGlyph),
+        callback: impl FnMut(&mut Graphics, LayoutGlyph),
     ) -> crate::Result<Vector> {
-        let mut cursor = Vector::ZERO;
-        let mut extents = Vector::ZERO;
-        let space_glyph = self.0.font().single_glyph(' ');
-        let space_metrics = self.0.font().metrics(space_glyph);
+        let options = TextOptions {
+            max_width,
+            ..TextOptions::default()
+        };
+
+        Ok(self
+            .layout_glyphs_with_options(gfx, text, &options, callback)?
+            .extents)
+    }
+
+    /// Lay out the given text with the given alignment and spacing, returning the extents and
+    /// the metrics of each line
+    ///
+    /// Each glyph is passed into the callback as it is layed out, in the same way as
+    /// [`FontRenderer::layout_glyphs`]. Lines are broken at newlines, and if the text would
+    /// extend past `max_width`, wherever the Unicode line breaking algorithm allows: after spaces
+    /// and hyphens or between ideographs, but not at no-break spaces or within a grapheme cluster.
+    ///
+    /// With the `shaping` feature, text drawn with a [`VectorFont`] is shaped with the font's own
+    /// tables, so Arabic letters join and Indic conjuncts form, and right-to-left text is laid out
+    /// from right to left. Text from other fonts is still drawn a character at a time.
+    pub fn layout_glyphs_with_options(
+        &mut self,
+        gfx: &mut Graphics,
+        text: &str,
+        options: &TextOptions,
+        mut callback: impl FnMut(&mut Graphics, LayoutGlyph),
+    ) -> crate::Result<TextMetrics> {
         let mut glyphs = Vec::new();
-        let line_height = self.0.font().line_height();
+        let metrics = self.arrange(gfx, text, options, &mut glyphs, &mut Vec::new())?;
+        for glyph in glyphs {
+            callback(gfx, glyph);
+        }
+
+        Ok(metrics)
+    }
+
+    // Lay out the text, adding each glyph and where its caret goes to the lists
+    pub(crate) fn arrange(
+        &mut self,
+        gfx: &Graphics,
+        text: &str,
+        options: &TextOptions,
+        glyphs: &mut Vec<LayoutGlyph>,
+        carets: &mut Vec<GlyphCaret>,
+    ) -> crate::Result<TextMetrics> {
+        let runs = [TextRun {
+            text: 0..text.len(),
+            scale: 1.0,
+            content: RunContent::Font(0),
+        }];
+        let mut block = TextBlock {
+            fonts: slice::from_mut(self),
+            text,
+            runs: &runs,
+        };
+        let (placed, metrics) = block.arrange(gfx, options)?;
+        for PlacedGlyph {
+            glyph,
+            area,
+            scale,
+            pen,
+            advance,
+            index,
+            rtl,
+            ..
+        } in placed
+        {
+            glyphs.push(LayoutGlyph {
+                position: area.pos,
+                scale,
+                distance_field: self
+                    .source(glyph.source)
+                    .and_then(GlyphSource::distance_field),
+                glyph: glyph.glyph,
+                image: glyph.image,
+            });
+            carets.push(GlyphCaret {
+                x: pen.x,
+                advance,
+                index,
+                source: glyph.source,
+                rtl,
+            });
+        }
+
+        Ok(metrics)
+    }
+
+    // Find the outlines, glows and shadows of laid-out glyphs that aren't distance fields
+    pub(crate) fn effect_layers(
+        &mut self,
+        gfx: &Graphics,
+        glyphs: &[LayoutGlyph],
+        carets: &[GlyphCaret],
+    ) -> crate::Result<EffectLayers> {
+        let mut layers = EffectLayers::default();
+        let mut cache = mem::take(&mut self.effect_cache);
+        let result = glyphs.iter().zip(carets).try_for_each(|(glyph, caret)| {
+            let glyph = EffectGlyph {
+                source: caret.source,
+                glyph: &glyph.glyph,
+                position: glyph.position,
+                scale: glyph.scale,
+                coverage: &|| self.coverage(caret.source, &glyph.glyph),
+            };
+            cache.add(gfx, &self.effects, glyph, &mut layers)
+        });
+        self.effect_cache = cache;
+        result?;
+
+        Ok(layers)
+    }
+
+    // How much of each pixel a glyph covers, if it isn't a distance field
+    fn coverage(&self, source: usize, glyph: &TextureGlyph) -> Option<Vec<u8>> {
+        let alpha = |pixels: &[u8]| pixels.chunks(4).map(|pixel| pixel[3]).collect();
+        match self.source(source) {
+            Some(GlyphSource::Cache(cache, None)) => {
+                let font = cache.font();
+                let data = font.rasterize(glyph.glyph).ok()?;
+                match font.pixel_type() {
+                    PixelType::Alpha => Some(data),
+                    PixelType::RGBA => Some(alpha(&data)),
+                }
+            }
+            Some(GlyphSource::Bitmap(font)) => font.coverage(std::char::from_u32(glyph.glyph.0)?),
+            // Distance fields draw their effects in the shader
+            Some(GlyphSource::Cache(_, Some(_))) => None,
+            None => Some(alpha(&tofu_pixels(glyph.bounds.width, glyph.bounds.height))),
+        }
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Add a font to fall back to for characters this renderer's fonts don't have
+    ///
+    /// Fallbacks are tried in the order they were added, after the renderer's own font. Lines are
+    /// still spaced by the first font, and glyphs from different fonts aren't kerned together.
+    pub fn push_fallback(&mut self, font: FontRenderer) {
+        self.fallbacks.push(font.source);
+        self.fallbacks.extend(font.fallbacks);
+        #[cfg(feature = "shaping")]
+        self.files.extend(font.files);
+        self.id = next_renderer_id();
+    }
+
+    /// Add fonts to fall back to, in order, for characters this renderer's fonts don't have
+    ///
+    /// See [`FontRenderer::push_fallback`].
+    pub fn with_fallbacks(mut self, fonts: impl IntoIterator<Item = FontRenderer>) -> Self {
+        for font in fonts {
+            self.push_fallback(font);
+        }
+
+        self
+    }
+
+    /// Set the outlines, glows and drop shadows drawn around the text
+    ///
+    /// Distance field fonts (see [`VectorFont::to_distance_field_renderer`]) draw the effects as
+    /// part of each glyph. Other fonts spread and blur a copy of each glyph the first time it's
+    /// drawn with a given outline width, glow width or shadow softness, and cache it; the effects
+    /// of every glyph are drawn before any of the glyphs, so they don't cover neighboring letters.
+    /// Changing those sizes empties the cache. Glyphs from a [`BitmapFont`] only get effects if
+    /// its pixels are known (see [`BitmapFont::read_pixels`]), and [`RichText`] only draws the
+    /// effects of distance field fonts.
+    ///
+    /// [`VectorFont::to_distance_field_renderer`]: crate::graphics::VectorFont::to_distance_field_renderer
+    pub fn set_effects(&mut self, effects: TextEffects) {
+        let resized = effects.outline_width != self.effects.outline_width
+            || effects.glow_width != self.effects.glow_width
+            || effects.shadow_softness != self.effects.shadow_softness;
+        // The cached bitmaps were spread and blurred for the old sizes, so they won't be used again
+        if resized {
+            self.effect_cache = EffectCache::default();
+        }
+        self.effects = effects;
+    }
 
-        for line in text.split('\n') {
-            for word in line.split(' ') {
-                match self.0.cache_string(word) {
+    /// The effects drawn around the text
+    pub fn effects(&self) -> TextEffects {
+        self.effects
+    }
+
+    /// Choose what to draw for characters that none of the fonts have
+    ///
+    /// By default, an empty box is drawn.
+    pub fn set_missing_glyph(&mut self, missing: MissingGlyph) {
+        self.missing = missing;
+        self.id = next_renderer_id();
+    }
+
+    fn line_height(&self) -> f32 {
+        self.source.line_height()
+    }
+
+    fn space_advance(&self) -> f32 {
+        self.source.space_advance()
+    }
+
+    // The source with the given index, where 0 is the renderer's own font and the rest are the
+    // fallbacks
+    fn source(&self, index: usize) -> Option<&GlyphSource> {
+        match index {
+            0 => Some(&self.source),
+            _ => self.fallbacks.get(index - 1),
+        }
+    }
+
+    fn source_mut(&mut self, index: usize) -> &mut GlyphSource {
+        match index {
+            0 => &mut self.source,
+            _ => &mut self.fallbacks[index - 1],
+        }
+    }
+
+    // The index of the first source with a glyph for the character
+    fn source_for(&self, c: char) -> Option<usize> {
+        iter::once(&self.source)
+            .chain(self.fallbacks.iter())
+            .position(|source| source.has_glyph(c))
+    }
+
+    fn kerning(&self, a: &WordGlyph, b: &WordGlyph) -> f32 {
+        if a.source != b.source {
+            return 0.0;
+        }
+        // Shaping has already kerned the glyphs
+        #[cfg(feature = "shaping")]
+        if matches!(self.files.get(a.source), Some(Some(_))) {
+            return 0.0;
+        }
+        self.source(a.source)
+            .map_or(0.0, |source| source.kerning(a.glyph.glyph, b.glyph.glyph))
+    }
+
+    // Find the glyphs for some text that's all in one direction, with the byte index (plus an
+    // offset) of the character each came from
+    //
+    // With the `shaping` feature, text from sources with a font file is shaped with the font's
+    // own tables, and right-to-left text comes out in the order it's drawn. Otherwise the text is
+    // a single grapheme cluster, and its glyphs are found one character at a time.
+    #[cfg(not(feature = "shaping"))]
+    fn shaped_glyphs(
+        &mut self,
+        gfx: &Graphics,
+        text: &str,
+        offset: usize,
+        _rtl: bool,
+        glyphs: &mut Vec<(usize, WordGlyph)>,
+    ) -> crate::Result<()> {
+        let mut word = Vec::new();
+        self.word_glyphs(gfx, text, &mut word)?;
+        glyphs.extend(word.into_iter().map(|glyph| (offset, glyph)));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "shaping")]
+    fn shaped_glyphs(
+        &mut self,
+        gfx: &Graphics,
+        text: &str,
+        offset: usize,
+        rtl: bool,
+        glyphs: &mut Vec<(usize, WordGlyph)>,
+    ) -> crate::Result<()> {
+        // Split the text into runs of characters from the same source, like word_glyphs does
+        let mut runs: Vec<(Range<usize>, Option<usize>)> = Vec::new();
+        for (i, c) in text.char_indices() {
+            let end = i + c.len_utf8();
+            match runs.last_mut() {
+                Some((run, _)) if c.is_whitespace() || unicode::is_invisible(c) => run.end = end,
+                Some((run, source)) if *source == self.source_for(c) => run.end = end,
+                _ => runs.push((i..end, self.source_for(c))),
+            }
+        }
+        let mut word = Vec::new();
+        for (run, source) in runs {
+            let file = source.and_then(|source| self.files.get(source).cloned().flatten());
+            match (source, file) {
+                (Some(source), Some(file)) => {
+                    let cache = match self.source_mut(source) {
+                        GlyphSource::Cache(cache, _) => cache,
+                        GlyphSource::Bitmap(_) => unreachable!("Bitmap fonts aren't shaped"),
+                    };
+                    for shaped in shaping::shape(&file, &text[run.clone()], rtl) {
+                        let glyph = cached_glyph(gfx, cache, Glyph(shaped.glyph), source)?;
+                        let glyph = WordGlyph {
+                            offset: glyph.offset + shaped.offset,
+                            advance: shaped.advance,
+                            ..glyph
+                        };
+                        glyphs.push((offset + run.start + shaped.cluster, glyph));
+                    }
+                }
+                // Fonts that can't be shaped, and missing characters, go one character at a time
+                _ => {
+                    for (i, c) in text[run.clone()].char_indices() {
+                        let start = run.start + i;
+                        self.word_glyphs(gfx, &text[start..start + c.len_utf8()], &mut word)?;
+                        glyphs.extend(word.drain(..).map(|glyph| (offset + start, glyph)));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Find the glyphs for a word, uploading them to the cache if necessary
+    //
+    // The word is split into runs of characters that come from the same source, and invisible
+    // formatting characters are left out
+    fn word_glyphs(
+        &mut self,
+        gfx: &Graphics,
+        word: &str,
+        glyphs: &mut Vec<WordGlyph>,
+    ) -> crate::Result<()> {
+        let visible: String;
+        let word = if word.chars().any(unicode::is_invisible) {
+            visible = word
+                .chars()
+                .filter(|c| !unicode::is_invisible(*c))
+                .collect();
+            &visible
+        } else {
+            word
+        };
+        let mut run_start = 0;
+        let mut run_source = None;
+        for (i, c) in word.char_indices() {
+            // Whitespace isn't drawn, so it can go with any run
+            let source = if c.is_whitespace() && i > 0 {
+                run_source
+            } else {
+                self.source_for(c)
+            };
+            if i > 0 && source != run_source {
+                self.run_glyphs(gfx, &word[run_start..i], run_source, glyphs)?;
+                run_start = i;
+            }
+            run_source = source;
+        }
+        if run_start < word.len() {
+            self.run_glyphs(gfx, &word[run_start..], run_source, glyphs)?;
+        }
+
+        Ok(())
+    }
+
+    fn run_glyphs(
+        &mut self,
+        gfx: &Graphics,
+        run: &str,
+        source: Option<usize>,
+        glyphs: &mut Vec<WordGlyph>,
+    ) -> crate::Result<()> {
+        if let Some(source) = source {
+            return self
+                .source_mut(source)
+                .word_glyphs(gfx, run, source, glyphs);
+        }
+        // None of the sources have these characters
+        for c in run.chars().filter(|c| !c.is_whitespace()) {
+            match self.missing {
+                MissingGlyph::Error => {
+                    return Err(FontError::NonRenderableGlyph(Glyph(c as u32)).into());
+                }
+                MissingGlyph::Replace(replacement) => {
+                    if let Some(source) = self.source_for(replacement) {
+                        let mut buffer = [0; 4];
+                        let replacement = replacement.encode_utf8(&mut buffer);
+                        self.source_mut(source)
+                            .word_glyphs(gfx, replacement, source, glyphs)?;
+                        continue;
+                    }
+                }
+                MissingGlyph::Tofu => {}
+            }
+            glyphs.push(self.tofu(gfx)?);
+        }
+
+        Ok(())
+    }
+
+    // An empty box to stand in for a missing character
+    fn tofu(&mut self, gfx: &Graphics) -> crate::Result<WordGlyph> {
+        let height = self.ascent.round().max(4.0) as u32;
+        let width = (height * 3 / 5).max(3);
+        let image = match &self.tofu {
+            Some(image) => image.clone(),
+            None => {
+                let pixels = tofu_pixels(width, height);
+                let image = Image::from_raw(gfx, Some(&pixels), width, height, PixelFormat::RGBA)?;
+                self.tofu = Some(image.clone());
+                image
+            }
+        };
+        let padding = (width / 5).max(1) as f32;
+
+        Ok(WordGlyph {
+            glyph: TextureGlyph {
+                glyph: Glyph(0),
+                bounds: Bounds {
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                },
+            },
+            image,
+            offset: Vector::new(padding, -(height as f32)),
+            advance: width as f32 + padding * 2.0,
+            source: TOFU_SOURCE,
+        })
+    }
+}
+
+impl GlyphSource {
+    fn distance_field(&self) -> Option<DistanceField> {
+        match self {
+            GlyphSource::Cache(_, field) => *field,
+            GlyphSource::Bitmap(_) => None,
+        }
+    }
+
+    fn line_height(&self) -> f32 {
+        match self {
+            GlyphSource::Cache(cache, _) => cache.font().line_height(),
+            GlyphSource::Bitmap(font) => font.line_height,
+        }
+    }
+
+    fn space_advance(&self) -> f32 {
+        match self {
+            GlyphSource::Cache(cache, _) => {
+                let space_glyph = cache.font().single_glyph(' ');
+                cache.font().metrics(space_glyph).advance_x
+            }
+            GlyphSource::Bitmap(font) => font.glyphs.get(&' ').map_or(0.0, |glyph| glyph.advance),
+        }
+    }
+
+    fn has_glyph(&self, c: char) -> bool {
+        match self {
+            // Glyph 0 is the "missing glyph" glyph of TTF fonts
+            GlyphSource::Cache(cache, _) => cache.font().single_glyph(c) != Glyph(0),
+            GlyphSource::Bitmap(font) => font.has_glyph(c),
+        }
+    }
+
+    fn kerning(&self, a: Glyph, b: Glyph) -> f32 {
+        match self {
+            GlyphSource::Cache(cache, _) => cache.font().kerning(a, b),
+            GlyphSource::Bitmap(font) => {
+                match (std::char::from_u32(a.0), std::char::from_u32(b.0)) {
+                    (Some(a), Some(b)) => font.kerning.get(&(a, b)).copied().unwrap_or(0.0),
+                    _ => 0.0,
+                }
+            }
+        }
+    }
+
+    // Find the glyphs for some text, uploading them to the cache if necessary
+    fn word_glyphs(
+        &mut self,
+        gfx: &Graphics,
+        word: &str,
+        source: usize,
+        glyphs: &mut Vec<WordGlyph>,
+    ) -> crate::Result<()> {
+        match self {
+            GlyphSource::Cache(cache, _) => {
+                match cache.cache_string(word) {
                     Ok(()) => {}
                     Err(CacheError::OutOfSpace) => {
                         // If the cache is out of space, clear it and insert a new page
-                        self.0.replace_texture(FontImage::new(&gfx)?);
+                        cache.replace_texture(FontImage::new(&gfx)?);
                     }
                     Err(CacheError::NonRenderableGlyph(g)) => {
                         return Err(FontError::NonRenderableGlyph(g).into());
@@ -152,78 +822,179 @@ impl FontRenderer {
                     }
                 }
                 // Retrieve the glyphs from the font
-                glyphs.extend(
-                    self.0.render_string(word).map(|glyph| {
-                        glyph.expect("A character failed to be rendered unexpectedly")
-                    }),
-                );
-
-                // If we're word wrapping, look ahead to the total width of the word. In the case
-                // where the word would overflow the line, move down a line
-                if let Some(width) = max_width {
-                    let mut word_width = 0.0;
-                    let mut it = glyphs.iter().peekable();
-                    while let Some((metrics, glyph)) = it.next() {
-                        word_width += metrics.advance_x;
-                        // If there's a next glyph, try kerning
-                        if let Some((_, next)) = it.peek() {
-                            word_width += self.0.font().kerning(glyph.glyph, next.glyph)
-                        }
-                    }
-                    if cursor.x + word_width > width {
-                        cursor.x = 0.0;
-                        cursor.y += line_height;
+                let image = cache.texture().image.clone();
+                glyphs.extend(cache.render_string(word).map(|glyph| {
+                    let (metrics, glyph) =
+                        glyph.expect("A character failed to be rendered unexpectedly");
+                    WordGlyph {
+                        glyph,
+                        image: image.clone(),
+                        offset: metrics
+                            .bounds
+                            .map_or(Vector::ZERO, |b| Vector::new(b.x as f32, b.y as f32)),
+                        advance: metrics.advance_x,
+                        source,
                     }
-                }
-
-                // Send each glyph to the callback
-                let mut it = glyphs.drain(..).peekable();
-                while let Some((metrics, glyph)) = it.next() {
-                    let glyph_position = metrics
-                        .bounds
-                        .map_or(Vector::ZERO, |b| Vector::new(b.x as f32, b.y as f32));
-
-                    callback(
-                        gfx,
-                        LayoutGlyph {
-                            position: cursor + glyph_position,
-                            glyph,
-                            image: self.0.texture().image.clone(),
+                }));
+            }
+            GlyphSource::Bitmap(font) => {
+                for c in word.chars().filter(|c| !c.is_whitespace()) {
+                    let bitmap_glyph = match font.glyphs.get(&c) {
+                        Some(glyph) => glyph,
+                        None => {
+                            return Err(FontError::NonRenderableGlyph(Glyph(c as u32)).into());
+                        }
+                    };
+                    let region = bitmap_glyph.region;
+                    glyphs.push(WordGlyph {
+                        glyph: TextureGlyph {
+                            glyph: Glyph(c as u32),
+                            bounds: Bounds {
+                                x: region.x() as i32,
+                                y: region.y() as i32,
+                                width: region.width() as u32,
+                                height: region.height() as u32,
+                            },
                         },
-                    );
+                        image: font.pages[bitmap_glyph.page].clone(),
+                        offset: bitmap_glyph.offset,
+                        advance: bitmap_glyph.advance,
+                        source,
+                    });
+                }
+            }
+        }
 
-                    let bounds = Vector::new(glyph.bounds.width as f32, glyph.bounds.height as f32);
-                    extents = extents.max(cursor + glyph_position + bounds);
+        Ok(())
+    }
+}
 
-                    cursor.x += metrics.advance_x;
-                    // If there's a next glyph, try kerning
-                    if let Some((_, next)) = it.peek() {
-                        cursor.x += self.0.font().kerning(glyph.glyph, next.glyph)
-                    }
-                }
-                cursor.x += space_metrics.advance_x;
+// Find a glyph by its index in the font, uploading it to the cache if necessary
+#[cfg(feature = "shaping")]
+fn cached_glyph(
+    gfx: &Graphics,
+    cache: &mut FontCache<FontImage>,
+    glyph: Glyph,
+    source: usize,
+) -> crate::Result<WordGlyph> {
+    let metrics = cache.font().metrics(glyph);
+    let texture_glyph = match metrics.bounds {
+        // Glyphs without an outline take up room, but there's nothing to draw
+        None => TextureGlyph {
+            glyph,
+            bounds: Bounds {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            },
+        },
+        Some(_) => match cache.render_glyph(glyph) {
+            Ok((_, texture_glyph)) => texture_glyph,
+            // If the cache is out of space, clear it and insert a new page
+            Err(CacheError::OutOfSpace) => {
+                cache.replace_texture(FontImage::new(gfx)?);
+                cache.render_glyph(glyph).map_err(cache_error)?.1
             }
-            cursor.x = 0.0;
-            cursor.y += line_height;
+            Err(error) => return Err(cache_error(error)),
+        },
+    };
 
-            extents = extents.max(cursor);
+    Ok(WordGlyph {
+        glyph: texture_glyph,
+        image: cache.texture().image.clone(),
+        offset: metrics
+            .bounds
+            .map_or(Vector::ZERO, |b| Vector::new(b.x as f32, b.y as f32)),
+        advance: metrics.advance_x,
+        source,
+    })
+}
+
+#[cfg(feature = "shaping")]
+fn cache_error(error: CacheError) -> QuicksilverError {
+    match error {
+        CacheError::NonRenderableGlyph(glyph) => FontError::NonRenderableGlyph(glyph).into(),
+        CacheError::TextureTooSmall | CacheError::OutOfSpace => FontError::StringTooLarge.into(),
+    }
+}
+
+/// What a [`FontRenderer`] draws for characters that none of its fonts have
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MissingGlyph {
+    /// Fail with a `NonRenderableGlyph` font error
+    Error,
+    /// Draw another character instead, such as `'?'` or `'\u{FFFD}'`
+    ///
+    /// If the fonts don't have that character either, an empty box is drawn.
+    Replace(char),
+    /// Draw an empty box, about as tall as a capital letter
+    Tofu,
+}
+
+// The source of glyphs that aren't from any font
+const TOFU_SOURCE: usize = usize::MAX;
+// The source of inline images in rich text
+const IMAGE_SOURCE: usize = usize::MAX - 1;
+
+// The outline of a box, in white RGBA pixels
+fn tofu_pixels(width: u32, height: u32) -> Vec<u8> {
+    let thickness = (height / 12).max(1);
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let edge =
+                x < thickness || y < thickness || x + thickness >= width || y + thickness >= height;
+            pixels.extend_from_slice(&[255, 255, 255, if edge { 255 } else { 0 }]);
         }
+    }
+
+    pixels
+}
 
-        Ok(extents)
+// Draw a laid-out glyph with its position relative to the offset, and the effects of a distance
+// field if it's one
+pub(crate) fn draw_glyph(
+    gfx: &mut Graphics,
+    layout: &LayoutGlyph,
+    color: Color,
+    offset: Vector,
+    effects: TextEffects,
+) {
+    match layout.distance_field {
+        Some(field) => gfx.set_scaled_distance_field(field, effects, layout.scale),
+        None => gfx.set_distance_field(None, TextEffects::default()),
     }
+    let tex_bounds = layout.glyph.bounds;
+    let glyph_size = Vector::new(tex_bounds.width as f32, tex_bounds.height as f32);
+    let region = Rectangle::new(
+        Vector::new(tex_bounds.x as f32, tex_bounds.y as f32),
+        glyph_size,
+    );
+    let location = Rectangle::new(offset + layout.position, glyph_size * layout.scale);
+    gfx.draw_subimage_tinted(&layout.image, region, location, color);
 }
 
 /// A glyph that has been laid-out and uploaded to the GPU, making it ready to render
+#[derive(Clone)]
 pub struct LayoutGlyph {
     /// What glyph this is, and what region of the image it takes up
     pub glyph: TextureGlyph,
     /// Where the glyph should be drawn, relative to the beginning of its block of text
     pub position: Vector,
+    /// How much the glyph is scaled when it's drawn
+    ///
+    /// This is 1, unless the text was shrunk to fit with [`TextOverflow::Shrink`].
+    pub scale: f32,
     /// The GPU texture where this glyph is stored
     ///
     /// It is not the whole image! Use [`LayoutGlyph::glyph`] to find the region that the glyph
     /// lies in
     pub image: Image,
+    /// How the glyph's image stores a distance field, if the font was cached as one
+    ///
+    /// Pass this to [`Graphics::set_distance_field`] before drawing the glyph.
+    pub distance_field: Option<DistanceField>,
 }
 
 struct FontImage {
@@ -285,3 +1056,22 @@ impl Texture for FontImage {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tofu_is_an_outline() {
+        let pixels = tofu_pixels(3, 4);
+        let alpha: Vec<u8> = pixels.chunks(4).map(|pixel| pixel[3]).collect();
+        #[rustfmt::skip]
+        assert_eq!(alpha, vec![
+            255, 255, 255,
+            255, 0, 255,
+            255, 0, 255,
+            255, 255, 255,
+        ]);
+        assert!(pixels.chunks(4).all(|pixel| pixel[..3] == [255, 255, 255]));
+    }
+}
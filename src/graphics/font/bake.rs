@@ -0,0 +1,272 @@
+use super::VectorFont;
+use crate::graphics::Pixmap;
+
+use rusttype::{point, Scale};
+use std::fmt::Write;
+
+/// A [`VectorFont`] rendered ahead of time into pages of glyphs, described by an AngelCode BMFont
+/// file
+///
+/// Create one with [`VectorFont::bake`], and save the descriptor and each page (encoded as PNG)
+/// next to each other. At runtime, [`BitmapFont::load_bmfont`] loads them back, so the glyphs
+/// don't have to be rasterized on first use and the `ttf` feature can be left off. The
+/// `quicksilver-bake-font` binary does this from the command line.
+///
+/// [`BitmapFont::load_bmfont`]: crate::graphics::BitmapFont::load_bmfont
+pub struct BakedFont {
+    descriptor: String,
+    page_names: Vec<String>,
+    pages: Vec<Pixmap>,
+}
+
+impl BakedFont {
+    /// The contents of the BMFont file, in the text format
+    pub fn descriptor(&self) -> &str {
+        &self.descriptor
+    }
+
+    /// The file names the descriptor expects each page to be saved as
+    pub fn page_names(&self) -> &[String] {
+        &self.page_names
+    }
+
+    /// The images the glyphs are drawn on, in the same order as their names
+    pub fn pages(&self) -> &[Pixmap] {
+        &self.pages
+    }
+}
+
+// Pages are packed up to this size, then trimmed to the rows they use, unless a glyph needs a
+// larger page
+const PAGE_SIZE: u32 = 1024;
+
+// Where glyphs were packed: each glyph's page and position, how tall each page is, and how wide
+// every page is
+struct Packing {
+    placements: Vec<(usize, u32, u32)>,
+    page_heights: Vec<u32>,
+    page_width: u32,
+}
+
+// A rasterized glyph waiting to be packed
+struct BakedGlyph {
+    character: char,
+    width: u32,
+    height: u32,
+    coverage: Vec<u8>,
+    offset: (i32, i32),
+    advance: f32,
+}
+
+impl VectorFont {
+    /// Render the given characters at a font size into pages of white glyphs, ready to be saved
+    ///
+    /// The pages are named `{page_name}_0.png`, `{page_name}_1.png` and so on, which is what the
+    /// descriptor refers to them as. Characters the font doesn't have are left out, and kerning
+    /// is stored for every pair of characters that has it. Positions and advances are rounded to
+    /// whole pixels, as the format expects. Pages are 1024 pixels across, or larger if a glyph
+    /// doesn't fit on one.
+    pub fn bake(&self, font_size: f32, characters: &str, page_name: &str) -> BakedFont {
+        let scale = Scale::uniform(font_size);
+        let v_metrics = self.font.v_metrics(scale);
+        let base = v_metrics.ascent.round();
+        let line_height = (v_metrics.ascent - v_metrics.descent + v_metrics.line_gap).round();
+
+        let mut characters: Vec<char> = characters.chars().collect();
+        characters.sort_unstable();
+        characters.dedup();
+        characters.retain(|c| self.font.glyph(*c).id().0 != 0 || c.is_whitespace());
+        let glyphs: Vec<BakedGlyph> = characters
+            .iter()
+            .map(|c| {
+                let glyph = self.font.glyph(*c).scaled(scale);
+                let advance = glyph.h_metrics().advance_width;
+                let glyph = glyph.positioned(point(0.0, 0.0));
+                let bounds = glyph.pixel_bounding_box();
+                let (width, height) =
+                    bounds.map_or((0, 0), |b| (b.width() as u32, b.height() as u32));
+                let mut coverage = vec![0; (width * height) as usize];
+                glyph.draw(|x, y, value| {
+                    coverage[(y * width + x) as usize] = (value * 255.0).round() as u8;
+                });
+                BakedGlyph {
+                    character: *c,
+                    width,
+                    height,
+                    coverage,
+                    offset: bounds.map_or((0, 0), |b| (b.min.x, b.min.y)),
+                    advance,
+                }
+            })
+            .collect();
+
+        let sizes: Vec<(u32, u32)> = glyphs
+            .iter()
+            .map(|glyph| (glyph.width, glyph.height))
+            .collect();
+        let Packing {
+            placements,
+            page_heights,
+            page_width,
+        } = pack(&sizes);
+
+        let mut pages: Vec<Pixmap> = page_heights
+            .iter()
+            .map(|height| Pixmap::new(page_width, (*height).max(1)))
+            .collect();
+        for (glyph, (page, x, y)) in glyphs.iter().zip(placements.iter()) {
+            let page = &mut pages[*page];
+            let stride = page.width();
+            let data = page.data_mut();
+            for row in 0..glyph.height {
+                for column in 0..glyph.width {
+                    let alpha = glyph.coverage[(row * glyph.width + column) as usize];
+                    let start = (((y + row) * stride + x + column) * 4) as usize;
+                    data[start..start + 4].copy_from_slice(&[255, 255, 255, alpha]);
+                }
+            }
+        }
+        let page_names: Vec<String> = (0..pages.len())
+            .map(|i| format!("{}_{}.png", page_name, i))
+            .collect();
+
+        let mut descriptor = String::new();
+        // Writing to a String can't fail
+        let _ = writeln!(
+            descriptor,
+            "info face=\"\" size={} bold=0 italic=0 charset=\"\" unicode=1 stretchH=100 smooth=1 aa=1 padding=0,0,0,0 spacing=1,1",
+            font_size.round()
+        );
+        let _ = writeln!(
+            descriptor,
+            "common lineHeight={} base={} scaleW={} scaleH={} pages={} packed=0",
+            line_height,
+            base,
+            page_width,
+            page_heights.iter().max().copied().unwrap_or(1).max(1),
+            pages.len()
+        );
+        for (id, name) in page_names.iter().enumerate() {
+            let _ = writeln!(descriptor, "page id={} file=\"{}\"", id, name);
+        }
+        let _ = writeln!(descriptor, "chars count={}", glyphs.len());
+        for (glyph, (page, x, y)) in glyphs.iter().zip(placements.iter()) {
+            let _ = writeln!(
+                descriptor,
+                "char id={} x={} y={} width={} height={} xoffset={} yoffset={} xadvance={} page={} chnl=15",
+                glyph.character as u32,
+                x,
+                y,
+                glyph.width,
+                glyph.height,
+                glyph.offset.0,
+                // Offsets are measured from the top of the line, rather than the baseline
+                glyph.offset.1 + base as i32,
+                glyph.advance.round(),
+                page
+            );
+        }
+        let kerning: Vec<(char, char, f32)> = characters
+            .iter()
+            .flat_map(|a| characters.iter().map(move |b| (*a, *b)))
+            .map(|(a, b)| (a, b, self.font.pair_kerning(scale, a, b).round()))
+            .filter(|(_, _, amount)| *amount != 0.0)
+            .collect();
+        if !kerning.is_empty() {
+            let _ = writeln!(descriptor, "kernings count={}", kerning.len());
+            for (a, b, amount) in kerning {
+                let _ = writeln!(
+                    descriptor,
+                    "kerning first={} second={} amount={}",
+                    a as u32, b as u32, amount
+                );
+            }
+        }
+
+        BakedFont {
+            descriptor,
+            page_names,
+            pages,
+        }
+    }
+}
+
+// Pack glyphs of the given sizes in rows, a pixel apart so they don't bleed into each other
+//
+// Pages are as wide as the widest glyph if it's wider than a page, and a glyph taller than a page
+// starts a page of its own that's as tall as it is.
+fn pack(sizes: &[(u32, u32)]) -> Packing {
+    let page_width = sizes
+        .iter()
+        .map(|(width, _)| *width)
+        .fold(PAGE_SIZE, u32::max);
+    let mut placements = Vec::with_capacity(sizes.len());
+    let mut page_heights = vec![0];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for (width, height) in sizes.iter().copied() {
+        if x + width > page_width {
+            x = 0;
+            y += row_height + 1;
+            row_height = 0;
+        }
+        // An empty page takes the glyph, however tall it is
+        let empty = x == 0 && y == 0;
+        if y + height > PAGE_SIZE && !empty {
+            page_heights.push(0);
+            x = 0;
+            y = 0;
+            row_height = 0;
+        }
+        let page = page_heights.len() - 1;
+        placements.push((page, x, y));
+        page_heights[page] = page_heights[page].max(y + height);
+        if width > 0 {
+            x += width + 1;
+        }
+        row_height = row_height.max(height);
+    }
+
+    Packing {
+        placements,
+        page_heights,
+        page_width,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bakes_a_bmfont() {
+        let font = VectorFont::from_slice(include_bytes!("../../../static/font.ttf"));
+        let baked = font.bake(24.0, "ABBA \u{E000}", "font-24");
+        assert_eq!(baked.page_names(), ["font-24_0.png"]);
+        assert_eq!(baked.pages().len(), 1);
+        let lines: Vec<&str> = baked.descriptor().lines().collect();
+        assert!(lines[1].starts_with("common lineHeight="));
+        assert_eq!(lines[2], "page id=0 file=\"font-24_0.png\"");
+        // Duplicates and characters the font doesn't have are left out
+        assert_eq!(lines[3], "chars count=3");
+        assert!(lines[4].starts_with("char id=32 x=0 y=0 width=0 height=0"));
+        assert!(lines[5].starts_with("char id=65 x=0 y=0 "));
+        assert!(lines[6].starts_with("char id=66 "));
+        // The glyphs are white, and some of them are covered
+        let page = &baked.pages()[0];
+        assert!(page
+            .data()
+            .chunks(4)
+            .any(|pixel| pixel == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn pages_grow_to_fit_large_glyphs() {
+        let packing = pack(&[(10, 10), (1500, 1200), (10, 10)]);
+        assert_eq!(packing.page_width, 1500);
+        assert_eq!(packing.placements, vec![(0, 0, 0), (1, 0, 0), (2, 0, 0)]);
+        assert_eq!(packing.page_heights, vec![10, 1200, 10]);
+        let packing = pack(&[(10, 10), (10, 10)]);
+        assert_eq!(packing.page_width, PAGE_SIZE);
+        assert_eq!(packing.placements, vec![(0, 0, 0), (0, 11, 0)]);
+    }
+}
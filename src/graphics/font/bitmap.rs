@@ -0,0 +1,506 @@
+use crate::error::FontError;
+use crate::geom::{Rectangle, Vector};
+use crate::graphics::{Graphics, Image, Pixmap};
+use crate::QuicksilverError;
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::path::Path;
+use std::rc::Rc;
+
+/// A font made of pre-rendered glyphs in one or more images
+///
+/// Bitmap fonts are drawn exactly as they were rendered, which makes them a good fit for pixel
+/// art. They can be loaded from [AngelCode BMFont](http://www.angelcode.com/products/bmfont/)
+/// files, in either the text or binary format, or sliced from a grid of equally sized cells. To
+/// draw text, use [`FontRenderer::from_bitmap`] to create a [`FontRenderer`].
+///
+/// The glyphs are drawn tinted by the text color, so they should usually be white. Fonts that
+/// pack glyphs into separate color channels aren't supported. Outlines, glows and shadows are
+/// drawn from the alpha of the pages, which fonts loaded with [`load_bmfont`] keep; for other
+/// fonts, call [`read_pixels`] first.
+///
+/// [`load_bmfont`]: BitmapFont::load_bmfont
+/// [`read_pixels`]: BitmapFont::read_pixels
+///
+/// [`FontRenderer`]: crate::graphics::FontRenderer
+/// [`FontRenderer::from_bitmap`]: crate::graphics::FontRenderer::from_bitmap
+#[derive(Clone)]
+pub struct BitmapFont {
+    pub(crate) pages: Vec<Image>,
+    // The contents of the pages, if they're known, which effects are made from
+    pub(crate) pixels: Option<Rc<[Pixmap]>>,
+    pub(crate) glyphs: HashMap<char, BitmapGlyph>,
+    pub(crate) kerning: HashMap<(char, char), f32>,
+    pub(crate) line_height: f32,
+    // How far the top of the line is above the baseline
+    pub(crate) base: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct BitmapGlyph {
+    pub page: usize,
+    // The area of the page the glyph is in
+    pub region: Rectangle,
+    // Where to draw the glyph, relative to the baseline
+    pub offset: Vector,
+    pub advance: f32,
+}
+
+impl BitmapFont {
+    /// Create a font from the contents of a BMFont file and its page images
+    ///
+    /// The pages should be given in the order the file lists them. Both the text and binary
+    /// formats are supported, including kerning pairs.
+    pub fn from_bmfont(data: &[u8], pages: Vec<Image>) -> Result<BitmapFont, QuicksilverError> {
+        let parsed = parse_bmfont(data)?;
+        if pages.len() < parsed.pages.len() {
+            return Err(invalid(format!(
+                "the font has {} pages, but {} images were given",
+                parsed.pages.len(),
+                pages.len()
+            )));
+        }
+
+        Ok(parsed.into_font(pages))
+    }
+
+    /// Load a BMFont file at the given path, and the page images it refers to
+    ///
+    /// The paths to the pages are relative to the folder the font file is in.
+    pub async fn load_bmfont(
+        gfx: &Graphics,
+        path: impl AsRef<Path>,
+    ) -> Result<BitmapFont, QuicksilverError> {
+        let path = path.as_ref();
+        let data = platter::load_file(path).await?;
+        let parsed = parse_bmfont(&data)?;
+        let mut pages = Vec::with_capacity(parsed.pages.len());
+        let mut pixels = Vec::with_capacity(parsed.pages.len());
+        for page in parsed.pages.iter() {
+            let page_path = match path.parent() {
+                Some(folder) => folder.join(page),
+                None => Path::new(page).to_path_buf(),
+            };
+            let pixmap = Pixmap::load(page_path).await?;
+            pages.push(Image::from_pixmap(gfx, &pixmap)?);
+            pixels.push(pixmap);
+        }
+        let mut font = parsed.into_font(pages);
+        font.pixels = Some(pixels.into());
+
+        Ok(font)
+    }
+
+    /// Slice a monospace font from an image divided into a grid of equally sized cells
+    ///
+    /// The cells are read left to right and top to bottom, and assigned the characters of
+    /// `characters` in order. Each glyph advances the width of a cell, and each line is the
+    /// height of a cell.
+    pub fn from_grid(image: Image, cell_size: Vector, characters: &str) -> BitmapFont {
+        assert!(
+            cell_size.x >= 1.0 && cell_size.y >= 1.0,
+            "The cells of a bitmap font must be at least a pixel across"
+        );
+        let columns = (image.size().x / cell_size.x).floor().max(1.0) as usize;
+        let glyphs = characters
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                let cell = Vector::new((i % columns) as f32, (i / columns) as f32);
+                let glyph = BitmapGlyph {
+                    page: 0,
+                    region: Rectangle::new(cell.times(cell_size), cell_size),
+                    offset: Vector::new(0.0, -cell_size.y),
+                    advance: cell_size.x,
+                };
+                (c, glyph)
+            })
+            .collect();
+
+        BitmapFont {
+            pages: vec![image],
+            pixels: None,
+            glyphs,
+            kerning: HashMap::new(),
+            line_height: cell_size.y,
+            base: cell_size.y,
+        }
+    }
+
+    /// How far apart the baselines of each line of text are
+    pub fn line_height(&self) -> f32 {
+        self.line_height
+    }
+
+    /// The images the glyphs are drawn from
+    pub fn pages(&self) -> &[Image] {
+        &self.pages
+    }
+
+    /// If the font has a glyph for the given character
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.glyphs.contains_key(&c)
+    }
+
+    /// Read the pages back from the GPU, so outlines, glows and shadows can be drawn around the
+    /// glyphs
+    ///
+    /// Fonts loaded with [`BitmapFont::load_bmfont`] don't need this. Call it before passing the
+    /// font to [`FontRenderer::from_bitmap`].
+    ///
+    /// [`FontRenderer::from_bitmap`]: crate::graphics::FontRenderer::from_bitmap
+    pub fn read_pixels(&mut self, gfx: &mut Graphics) -> Result<(), QuicksilverError> {
+        let pixels = self
+            .pages
+            .iter()
+            .map(|page| page.to_pixels(gfx))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.pixels = Some(pixels.into());
+
+        Ok(())
+    }
+
+    // How much of each pixel of a character's glyph is covered, if the pages' pixels are known
+    pub(crate) fn coverage(&self, c: char) -> Option<Vec<u8>> {
+        let glyph = self.glyphs.get(&c)?;
+        let page = self.pixels.as_ref()?.get(glyph.page)?;
+
+        region_alpha(page, glyph.region)
+    }
+}
+
+// The alpha of each pixel in a region of a page, if the region is all on the page
+fn region_alpha(page: &Pixmap, region: Rectangle) -> Option<Vec<u8>> {
+    let (x, y) = (region.x() as u32, region.y() as u32);
+    let (width, height) = (region.width() as u32, region.height() as u32);
+    if x + width > page.width() || y + height > page.height() {
+        return None;
+    }
+    let pixels = page.crop(x, y, width, height);
+
+    Some(pixels.data().chunks(4).map(|pixel| pixel[3]).collect())
+}
+
+fn invalid(message: impl Into<String>) -> QuicksilverError {
+    FontError::InvalidBitmapFont(message.into()).into()
+}
+
+#[derive(Debug, Default)]
+struct ParsedFont {
+    line_height: f32,
+    base: f32,
+    pages: Vec<String>,
+    chars: Vec<ParsedChar>,
+    kerning: Vec<(u32, u32, f32)>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct ParsedChar {
+    id: u32,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    x_offset: f32,
+    y_offset: f32,
+    advance: f32,
+    page: usize,
+}
+
+impl ParsedFont {
+    fn into_font(self, pages: Vec<Image>) -> BitmapFont {
+        let base = self.base;
+        let glyphs = self
+            .chars
+            .into_iter()
+            .filter_map(|c| {
+                let character = std::char::from_u32(c.id)?;
+                let glyph = BitmapGlyph {
+                    page: c.page,
+                    region: Rectangle::new(Vector::new(c.x, c.y), Vector::new(c.width, c.height)),
+                    // Offsets are measured from the top of the line, rather than the baseline
+                    offset: Vector::new(c.x_offset, c.y_offset - base),
+                    advance: c.advance,
+                };
+                Some((character, glyph))
+            })
+            .collect();
+        let kerning = self
+            .kerning
+            .into_iter()
+            .filter_map(|(first, second, amount)| {
+                let pair = (std::char::from_u32(first)?, std::char::from_u32(second)?);
+                Some((pair, amount))
+            })
+            .collect();
+
+        BitmapFont {
+            pages,
+            pixels: None,
+            glyphs,
+            kerning,
+            line_height: self.line_height,
+            base,
+        }
+    }
+}
+
+fn parse_bmfont(data: &[u8]) -> Result<ParsedFont, QuicksilverError> {
+    let font = if data.starts_with(b"BMF") {
+        parse_binary(data)?
+    } else {
+        let text = std::str::from_utf8(data).map_err(|_| invalid("the file isn't valid UTF-8"))?;
+        parse_text(text)?
+    };
+    if let Some(c) = font.chars.iter().find(|c| c.page >= font.pages.len()) {
+        return Err(invalid(format!(
+            "character {} is on page {}, which doesn't exist",
+            c.id, c.page
+        )));
+    }
+
+    Ok(font)
+}
+
+fn parse_text(text: &str) -> Result<ParsedFont, QuicksilverError> {
+    let mut font = ParsedFont::default();
+    let mut pages = Vec::new();
+    for line in text.lines() {
+        let (tag, attributes) = match split_tag(line) {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let number = |key: &str| -> Result<f32, QuicksilverError> {
+            let value = attributes
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.as_str())
+                .unwrap_or("0");
+            value
+                .parse()
+                .map_err(|_| invalid(format!("{}={} isn't a number", key, value)))
+        };
+        match tag {
+            "common" => {
+                font.line_height = number("lineHeight")?;
+                font.base = number("base")?;
+            }
+            "page" => {
+                let file = attributes
+                    .iter()
+                    .find(|(name, _)| *name == "file")
+                    .map(|(_, value)| value.clone())
+                    .ok_or_else(|| invalid("a page is missing its file"))?;
+                pages.push((number("id")? as usize, file));
+            }
+            "char" => font.chars.push(ParsedChar {
+                id: number("id")? as u32,
+                x: number("x")?,
+                y: number("y")?,
+                width: number("width")?,
+                height: number("height")?,
+                x_offset: number("xoffset")?,
+                y_offset: number("yoffset")?,
+                advance: number("xadvance")?,
+                page: number("page")? as usize,
+            }),
+            "kerning" => font.kerning.push((
+                number("first")? as u32,
+                number("second")? as u32,
+                number("amount")?,
+            )),
+            _ => (),
+        }
+    }
+    pages.sort_by_key(|(id, _)| *id);
+    font.pages = pages.into_iter().map(|(_, file)| file).collect();
+
+    Ok(font)
+}
+
+// Split a line like `page id=0 file="font 0.png"` into its tag and attributes
+fn split_tag(line: &str) -> Option<(&str, Vec<(&str, String)>)> {
+    let line = line.trim();
+    let tag_end = line.find(char::is_whitespace).unwrap_or(line.len());
+    let tag = &line[..tag_end];
+    if tag.is_empty() {
+        return None;
+    }
+    let mut attributes = Vec::new();
+    let mut rest = line[tag_end..].trim_start();
+    while let Some(equals) = rest.find('=') {
+        let name = rest[..equals].trim();
+        rest = &rest[equals + 1..];
+        let value = if rest.starts_with('"') {
+            let end = rest[1..].find('"').map_or(rest.len(), |end| end + 1);
+            let value = rest[1..end].to_owned();
+            rest = rest.get(end + 1..).unwrap_or("");
+            value
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let value = rest[..end].to_owned();
+            rest = &rest[end..];
+            value
+        };
+        attributes.push((name, value));
+        rest = rest.trim_start();
+    }
+
+    Some((tag, attributes))
+}
+
+fn parse_binary(data: &[u8]) -> Result<ParsedFont, QuicksilverError> {
+    let truncated = || invalid("the file is truncated");
+    match data.get(3) {
+        Some(3) => (),
+        Some(version) => return Err(invalid(format!("version {} isn't supported", version))),
+        None => return Err(truncated()),
+    }
+    let mut font = ParsedFont::default();
+    let mut rest = &data[4..];
+    while !rest.is_empty() {
+        if rest.len() < 5 {
+            return Err(truncated());
+        }
+        let kind = rest[0];
+        let size = u32::from_le_bytes(rest[1..5].try_into().unwrap()) as usize;
+        let block = rest.get(5..5 + size).ok_or_else(truncated)?;
+        rest = &rest[5 + size..];
+        let u16_at = |i: usize| u16::from_le_bytes([block[i], block[i + 1]]);
+        let i16_at = |i: usize| i16::from_le_bytes([block[i], block[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes(block[i..i + 4].try_into().unwrap());
+        match kind {
+            2 => {
+                if block.len() < 4 {
+                    return Err(truncated());
+                }
+                font.line_height = u16_at(0) as f32;
+                font.base = u16_at(2) as f32;
+            }
+            3 => {
+                font.pages = block
+                    .split(|byte| *byte == 0)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| String::from_utf8_lossy(name).into_owned())
+                    .collect();
+            }
+            4 => {
+                for i in (0..block.len() / 20).map(|i| i * 20) {
+                    font.chars.push(ParsedChar {
+                        id: u32_at(i),
+                        x: u16_at(i + 4) as f32,
+                        y: u16_at(i + 6) as f32,
+                        width: u16_at(i + 8) as f32,
+                        height: u16_at(i + 10) as f32,
+                        x_offset: i16_at(i + 12) as f32,
+                        y_offset: i16_at(i + 14) as f32,
+                        advance: i16_at(i + 16) as f32,
+                        page: block[i + 18] as usize,
+                    });
+                }
+            }
+            5 => {
+                for i in (0..block.len() / 10).map(|i| i * 10) {
+                    font.kerning
+                        .push((u32_at(i), u32_at(i + 4), i16_at(i + 8) as f32));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(font)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT_FONT: &str = r#"info face="Pixel Font" size=8 bold=0 italic=0 charset="" unicode=1
+common lineHeight=10 base=8 scaleW=64 scaleH=64 pages=2 packed=0
+page id=1 file="pixel font_1.png"
+page id=0 file="pixel font_0.png"
+chars count=2
+char id=65   x=0 y=0 width=5 height=7 xoffset=0 yoffset=1 xadvance=6 page=0 chnl=15
+char id=86   x=8 y=0 width=5 height=7 xoffset=0 yoffset=1 xadvance=6 page=1 chnl=15
+kernings count=1
+kerning first=65 second=86 amount=-1
+"#;
+
+    #[test]
+    fn parses_text_fonts() {
+        let font = parse_bmfont(TEXT_FONT.as_bytes()).unwrap();
+        assert_eq!(font.line_height, 10.0);
+        assert_eq!(font.base, 8.0);
+        assert_eq!(font.pages, vec!["pixel font_0.png", "pixel font_1.png"]);
+        assert_eq!(font.chars.len(), 2);
+        assert_eq!(font.chars[1].page, 1);
+        assert_eq!(font.chars[1].x, 8.0);
+        assert_eq!(font.kerning, vec![(65, 86, -1.0)]);
+    }
+
+    #[test]
+    fn parses_binary_fonts() {
+        let mut data = b"BMF\x03".to_vec();
+        let mut block = |kind: u8, contents: Vec<u8>| {
+            data.push(kind);
+            data.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            data.extend(contents);
+        };
+        let mut common = vec![10, 0, 8, 0, 64, 0, 64, 0, 1, 0];
+        common.extend_from_slice(&[0; 5]);
+        block(2, common);
+        block(3, b"page.png\0".to_vec());
+        let mut chars = Vec::new();
+        chars.extend_from_slice(&65u32.to_le_bytes());
+        for value in [0u16, 0, 5, 7].iter() {
+            chars.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [0i16, 1, 6].iter() {
+            chars.extend_from_slice(&value.to_le_bytes());
+        }
+        chars.extend_from_slice(&[0, 15]);
+        block(4, chars);
+        let mut kerning = Vec::new();
+        kerning.extend_from_slice(&65u32.to_le_bytes());
+        kerning.extend_from_slice(&65u32.to_le_bytes());
+        kerning.extend_from_slice(&(-2i16).to_le_bytes());
+        block(5, kerning);
+
+        let font = parse_bmfont(&data).unwrap();
+        assert_eq!(font.line_height, 10.0);
+        assert_eq!(font.pages, vec!["page.png"]);
+        assert_eq!(
+            font.chars,
+            vec![ParsedChar {
+                id: 65,
+                width: 5.0,
+                height: 7.0,
+                y_offset: 1.0,
+                advance: 6.0,
+                ..ParsedChar::default()
+            }]
+        );
+        assert_eq!(font.kerning, vec![(65, 65, -2.0)]);
+    }
+
+    #[test]
+    fn rejects_missing_pages() {
+        let font = TEXT_FONT.replace("page=1", "page=2");
+        assert!(parse_bmfont(font.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn coverage_is_the_alpha_of_the_region() {
+        let mut page = Pixmap::new(4, 4);
+        page.set_pixel(2, 1, crate::graphics::Color::WHITE);
+        let region =
+            |x, y, width, height| Rectangle::new(Vector::new(x, y), Vector::new(width, height));
+        assert_eq!(
+            region_alpha(&page, region(1.0, 1.0, 2.0, 2.0)),
+            Some(vec![0, 255, 0, 0])
+        );
+        assert_eq!(region_alpha(&page, region(3.0, 0.0, 2.0, 1.0)), None);
+    }
+}
@@ -0,0 +1,324 @@
+use super::FontImage;
+use crate::geom::{Rectangle, Vector};
+use crate::graphics::{Color, Graphics, Image, TextEffects};
+
+use elefont::{Bounds, Glyph, PixelType, Texture, TextureGlyph};
+use std::collections::HashMap;
+
+// Outlines, glows and shadows of glyphs that aren't distance fields, made by spreading and
+// blurring their coverage and packing the result into cache pages
+#[derive(Default)]
+pub(crate) struct EffectCache {
+    page: Option<FontImage>,
+    // Where the next bitmap goes on the page, and how tall the current row is
+    cursor: (u32, u32),
+    row_height: u32,
+    bitmaps: HashMap<EffectKey, Option<EffectBitmap>>,
+}
+
+// Which glyph an effect bitmap is for, and how far it was spread and blurred
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct EffectKey {
+    source: usize,
+    glyph: u32,
+    spread: u32,
+    blur: u32,
+}
+
+#[derive(Clone)]
+struct EffectBitmap {
+    image: Image,
+    region: Rectangle,
+    // How far the bitmap extends past the glyph on each side
+    padding: f32,
+}
+
+// An effect bitmap placed under a glyph
+#[derive(Clone)]
+struct EffectSprite {
+    image: Image,
+    region: Rectangle,
+    area: Rectangle,
+    color: Color,
+}
+
+// The effects under a block of text, drawn back to front
+#[derive(Clone, Default)]
+pub(crate) struct EffectLayers {
+    shadows: Vec<EffectSprite>,
+    glows: Vec<EffectSprite>,
+    outlines: Vec<EffectSprite>,
+}
+
+// A glyph that needs effects drawn under it
+pub(crate) struct EffectGlyph<'a> {
+    pub source: usize,
+    pub glyph: &'a TextureGlyph,
+    pub position: Vector,
+    pub scale: f32,
+    // How much of the glyph is covered by each pixel, if it can have effects
+    pub coverage: &'a dyn Fn() -> Option<Vec<u8>>,
+}
+
+impl EffectCache {
+    // Add the effects under a glyph to the layers
+    pub fn add(
+        &mut self,
+        gfx: &Graphics,
+        effects: &TextEffects,
+        glyph: EffectGlyph,
+        layers: &mut EffectLayers,
+    ) -> crate::Result<()> {
+        let spread = effects.outline_width.max(0.0);
+        let mut add_layer = |cache: &mut EffectCache,
+                             blur: f32,
+                             color: Color,
+                             offset: Vector,
+                             layer: fn(&mut EffectLayers) -> &mut Vec<EffectSprite>|
+         -> crate::Result<()> {
+            if color.a <= 0.0 {
+                return Ok(());
+            }
+            let key = EffectKey {
+                source: glyph.source,
+                glyph: glyph.glyph.glyph.0,
+                spread: spread.to_bits(),
+                blur: blur.to_bits(),
+            };
+            let bitmap = match cache.bitmaps.get(&key) {
+                Some(bitmap) => bitmap.clone(),
+                None => {
+                    let bitmap = match (glyph.coverage)() {
+                        Some(coverage) => {
+                            let Bounds { width, height, .. } = glyph.glyph.bounds;
+                            cache.upload(gfx, &coverage, width, height, spread, blur)?
+                        }
+                        None => None,
+                    };
+                    cache.bitmaps.insert(key, bitmap.clone());
+                    bitmap
+                }
+            };
+            if let Some(bitmap) = bitmap {
+                let position =
+                    glyph.position + (offset - Vector::ONE * bitmap.padding) * glyph.scale;
+                layer(layers).push(EffectSprite {
+                    image: bitmap.image,
+                    area: Rectangle::new(position, bitmap.region.size * glyph.scale),
+                    region: bitmap.region,
+                    color,
+                });
+            }
+
+            Ok(())
+        };
+        if spread > 0.0 {
+            add_layer(self, 0.0, effects.outline_color, Vector::ZERO, |l| {
+                &mut l.outlines
+            })?;
+        }
+        if effects.glow_width > 0.0 {
+            add_layer(
+                self,
+                effects.glow_width,
+                effects.glow_color,
+                Vector::ZERO,
+                |l| &mut l.glows,
+            )?;
+        }
+        add_layer(
+            self,
+            effects.shadow_softness.max(0.0),
+            effects.shadow_color,
+            effects.shadow_offset,
+            |l| &mut l.shadows,
+        )?;
+
+        Ok(())
+    }
+
+    // Spread and blur a glyph's coverage and put it on the page, if it fits
+    fn upload(
+        &mut self,
+        gfx: &Graphics,
+        coverage: &[u8],
+        width: u32,
+        height: u32,
+        spread: f32,
+        blur: f32,
+    ) -> crate::Result<Option<EffectBitmap>> {
+        if coverage.len() != (width * height) as usize {
+            return Ok(None);
+        }
+        let (alpha, padding) = spread_and_blur(coverage, width, height, spread, blur);
+        let (width, height) = (width + padding * 2, height + padding * 2);
+        if self.page.is_none() {
+            self.page = Some(FontImage::new(gfx)?);
+        }
+        let page = match &mut self.page {
+            Some(page) => page,
+            None => return Ok(None),
+        };
+        if width > page.width() || height > page.height() {
+            return Ok(None);
+        }
+        // Pack bitmaps in rows, moving to a new page when this one is full
+        if self.cursor.0 + width > page.width() {
+            self.cursor = (0, self.cursor.1 + self.row_height + 1);
+            self.row_height = 0;
+        }
+        if self.cursor.1 + height > page.height() {
+            // Forget the bitmaps on the old page, so the cache never holds more than one page;
+            // layouts that still draw them keep the old page alive
+            *page = FontImage::new(gfx)?;
+            self.bitmaps.clear();
+            self.cursor = (0, 0);
+            self.row_height = 0;
+        }
+        let bounds = Bounds {
+            x: self.cursor.0 as i32,
+            y: self.cursor.1 as i32,
+            width,
+            height,
+        };
+        page.put_rect(
+            PixelType::Alpha,
+            &alpha,
+            &TextureGlyph {
+                glyph: Glyph(0),
+                bounds,
+            },
+        );
+        self.cursor.0 += width + 1;
+        self.row_height = self.row_height.max(height);
+
+        Ok(Some(EffectBitmap {
+            image: page.image.clone(),
+            region: Rectangle::new(
+                Vector::new(bounds.x as f32, bounds.y as f32),
+                Vector::new(width as f32, height as f32),
+            ),
+            padding: padding as f32,
+        }))
+    }
+}
+
+impl EffectLayers {
+    pub fn draw(&self, gfx: &mut Graphics, offset: Vector) {
+        let layers = [&self.shadows, &self.glows, &self.outlines];
+        for sprite in layers.iter().flat_map(|layer| layer.iter()) {
+            let area = Rectangle::new(offset + sprite.area.pos, sprite.area.size);
+            gfx.draw_subimage_tinted(&sprite.image, sprite.region, area, sprite.color);
+        }
+    }
+}
+
+// Grow the coverage outwards by `spread` pixels, then blur it over about `blur` pixels, returning
+// the new coverage and how many pixels it was padded by on each side
+fn spread_and_blur(
+    coverage: &[u8],
+    width: u32,
+    height: u32,
+    spread: f32,
+    blur: f32,
+) -> (Vec<u8>, u32) {
+    let reach = spread.ceil() as i32 + 1;
+    let radius = blur.ceil() as i32;
+    let padding = (reach + radius) as u32;
+    let (padded_width, padded_height) = (width + padding * 2, height + padding * 2);
+    let (w, h) = (padded_width as i32, padded_height as i32);
+    let mut values = vec![0.0f32; (padded_width * padded_height) as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let target = (y + padding) * padded_width + x + padding;
+            values[target as usize] = coverage[(y * width + x) as usize] as f32 / 255.0;
+        }
+    }
+
+    if spread > 0.0 {
+        let source = values.clone();
+        for y in 0..h {
+            for x in 0..w {
+                let mut value = 0.0f32;
+                for dy in -reach..=reach {
+                    for dx in -reach..=reach {
+                        let (sx, sy) = (x + dx, y + dy);
+                        if sx < 0 || sy < 0 || sx >= w || sy >= h {
+                            continue;
+                        }
+                        // Pixels within the spread count fully, fading out over the next pixel
+                        let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                        let weight = (spread + 1.0 - distance).clamp(0.0, 1.0);
+                        value = value.max(source[(sy * w + sx) as usize] * weight);
+                    }
+                }
+                values[(y * w + x) as usize] = value;
+            }
+        }
+    }
+
+    if radius > 0 {
+        // A gaussian blur, once across and once down
+        let sigma = blur / 2.0;
+        let kernel: Vec<f32> = (-radius..=radius)
+            .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let total: f32 = kernel.iter().sum();
+        for (step, length, lines) in [(1, w, h), (w, h, w)].iter().copied() {
+            let source = values.clone();
+            for line in 0..lines {
+                let start = if step == 1 { line * w } else { line };
+                for i in 0..length {
+                    let mut value = 0.0;
+                    for (k, weight) in kernel.iter().enumerate() {
+                        let j = i + k as i32 - radius;
+                        if j >= 0 && j < length {
+                            value += source[(start + j * step) as usize] * weight;
+                        }
+                    }
+                    values[(start + i * step) as usize] = value / total;
+                }
+            }
+        }
+    }
+
+    let alpha = values
+        .iter()
+        .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+
+    (alpha, padding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spread_grows_the_coverage() {
+        // A single covered pixel
+        let (alpha, padding) = spread_and_blur(&[255], 1, 1, 1.0, 0.0);
+        assert_eq!(padding, 2);
+        let size = 5;
+        assert_eq!(alpha.len(), size * size);
+        let at = |x: usize, y: usize| alpha[y * size + x];
+        assert_eq!(at(2, 2), 255);
+        assert_eq!(at(1, 2), 255);
+        assert_eq!(at(2, 3), 255);
+        // Diagonals are a little further than the spread
+        assert!(at(1, 1) > 0 && at(1, 1) < 255);
+        assert_eq!(at(0, 2), 0);
+    }
+
+    #[test]
+    fn blur_keeps_the_total_coverage() {
+        let coverage = [255; 9];
+        let (alpha, padding) = spread_and_blur(&coverage, 3, 3, 0.0, 2.0);
+        assert_eq!(padding, 3);
+        let total: u32 = alpha.iter().map(|a| *a as u32).sum();
+        assert!((total as i32 - 255 * 9).abs() < 30, "{}", total);
+        // The middle stays the most covered, and the edges are soft
+        assert_eq!(alpha.iter().max(), Some(&alpha[alpha.len() / 2]));
+        assert!(alpha[0] < alpha[alpha.len() / 2]);
+    }
+}
@@ -0,0 +1,675 @@
+use super::effects::EffectLayers;
+use super::{draw_glyph, FontRenderer, LayoutGlyph};
+use crate::geom::{Rectangle, Vector};
+use crate::graphics::{Color, Graphics, TextEffects};
+
+use std::ops::Range;
+
+/// How each line of text is placed horizontally
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HorizontalAlign {
+    /// Lines start at the left edge
+    Left,
+    /// Lines are centered
+    Center,
+    /// Lines end at the right edge
+    Right,
+    /// Wrapped lines are stretched to fill the width, by widening the spaces between words and
+    /// the gaps between ideographs
+    ///
+    /// The last line of each paragraph, text without a maximum width and lines with right-to-left
+    /// text are left aligned.
+    Justify,
+}
+
+/// How a block of text is placed vertically
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerticalAlign {
+    /// The baseline of the first line is at the top of the box
+    Baseline,
+    /// The top of the first line is at the top of the box
+    Top,
+    /// The text is centered in the box
+    Middle,
+    /// The bottom of the last line is at the bottom of the box
+    Bottom,
+}
+
+/// What happens to text that doesn't fit in its box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextOverflow {
+    /// Lines past `max_lines` are left out, and words wider than `max_width` stick out past it
+    Clip,
+    /// Like `Clip`, but the last line ends with an ellipsis ("…") if any lines were left out, and
+    /// lines wider than `max_width` are cut short and end with an ellipsis
+    Ellipsis,
+    /// The text is scaled down until it fits, but no further than `min_scale`
+    ///
+    /// The text fits if it has at most `max_lines` lines, is no taller than `height`, and has
+    /// no lines wider than `max_width`. Glyphs are scaled after they're rendered, so they get
+    /// blurrier as they shrink, unless the font is a distance field. If the text doesn't fit at
+    /// `min_scale`, it's cut off like `Ellipsis`.
+    Shrink {
+        /// The smallest scale to shrink the text to, between 0 and 1
+        min_scale: f32,
+    },
+}
+
+/// How to lay out a block of text
+///
+/// Text is laid out in a box with its top-left at the drawing offset. The box is `max_width`
+/// wide, or has no width (so center alignment centers on the offset) if there's no maximum. It's
+/// `height` tall, or has no height if that's `None`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextOptions {
+    /// The width to wrap lines at, if any
+    pub max_width: Option<f32>,
+    /// The height of the box to align the text vertically in, if any
+    pub height: Option<f32>,
+    /// How each line is placed horizontally
+    pub align: HorizontalAlign,
+    /// How the text is placed vertically
+    pub vertical_align: VerticalAlign,
+    /// How far apart lines are, as a multiple of the font's line height
+    pub line_spacing: f32,
+    /// How much extra space to leave after each glyph
+    pub letter_spacing: f32,
+    /// The most lines to draw, if there's a limit
+    pub max_lines: Option<usize>,
+    /// What to do with text that doesn't fit
+    pub overflow: TextOverflow,
+}
+
+impl Default for TextOptions {
+    fn default() -> TextOptions {
+        TextOptions {
+            max_width: None,
+            height: None,
+            align: HorizontalAlign::Left,
+            vertical_align: VerticalAlign::Baseline,
+            line_spacing: 1.0,
+            letter_spacing: 0.0,
+            max_lines: None,
+            overflow: TextOverflow::Clip,
+        }
+    }
+}
+
+/// The measurements of a block of text after layout
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextMetrics {
+    /// How far the text extended past the offset, e.g. the furthest right and furthest down
+    /// position
+    pub extents: Vector,
+    /// Each line of the text, in order
+    pub lines: Vec<LineMetrics>,
+}
+
+impl TextMetrics {
+    /// The smallest rectangle that contains every line
+    pub fn bounds(&self) -> Rectangle {
+        let mut lines = self.lines.iter().map(|line| line.bounds);
+        let first = match lines.next() {
+            Some(first) => first,
+            None => return Rectangle::new_sized(Vector::ZERO),
+        };
+        let (min, max) = lines.fold((first.pos, first.pos + first.size), |(min, max), line| {
+            (min.min(line.pos), max.max(line.pos + line.size))
+        });
+
+        Rectangle::new(min, max - min)
+    }
+}
+
+/// The measurements of a single line of laid-out text
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineMetrics {
+    /// The area the line takes up, from the start of its first glyph to the end of its last and
+    /// from the top of the line to the bottom
+    pub bounds: Rectangle,
+    /// The vertical position of the line's baseline
+    pub baseline: f32,
+    /// The byte range of the text on this line, not including the space or newline it ended at
+    pub text: Range<usize>,
+    /// The range of the glyphs on this line, in the order they were laid out
+    pub glyphs: Range<usize>,
+}
+
+/// A block of text that has been laid out once, so it can be measured, hit-tested and drawn many
+/// times
+///
+/// Create one with [`FontRenderer::layout`]. Changing the text or options marks the layout as out
+/// of date, and [`TextLayout::update`] lays it out again. Nothing is redone if the text, options
+/// and font are all unchanged, so it's cheap to call `update` every frame.
+///
+/// Positions are relative to the offset the layout is drawn at, and indices are byte indices into
+/// the text.
+pub struct TextLayout {
+    text: String,
+    options: TextOptions,
+    font: u64,
+    // The font's effects, as of the last update
+    effects: TextEffects,
+    effect_layers: EffectLayers,
+    dirty: bool,
+    glyphs: Vec<LayoutGlyph>,
+    carets: Vec<GlyphCaret>,
+    // The carets of each line in the order of the text, by their index in the carets
+    logical: Vec<usize>,
+    metrics: TextMetrics,
+}
+
+// Where the caret goes around a laid-out glyph
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct GlyphCaret {
+    pub x: f32,
+    pub advance: f32,
+    pub index: usize,
+    // Which of the font's sources the glyph came from
+    pub source: usize,
+    // If the glyph is part of right-to-left text, which a caret goes after instead of before
+    pub rtl: bool,
+}
+
+impl TextLayout {
+    pub(crate) fn new(
+        gfx: &Graphics,
+        font: &mut FontRenderer,
+        text: String,
+        options: TextOptions,
+    ) -> crate::Result<TextLayout> {
+        let mut layout = TextLayout {
+            text,
+            options,
+            font: font.id(),
+            effects: font.effects(),
+            effect_layers: EffectLayers::default(),
+            dirty: true,
+            glyphs: Vec::new(),
+            carets: Vec::new(),
+            logical: Vec::new(),
+            metrics: TextMetrics {
+                extents: Vector::ZERO,
+                lines: Vec::new(),
+            },
+        };
+        layout.update(gfx, font)?;
+
+        Ok(layout)
+    }
+
+    /// The text that is laid out
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Change the text, which is laid out again on the next [`TextLayout::update`]
+    pub fn set_text(&mut self, text: &str) {
+        if self.text != text {
+            self.text.clear();
+            self.text.push_str(text);
+            self.dirty = true;
+        }
+    }
+
+    /// The options the text is laid out with
+    pub fn options(&self) -> &TextOptions {
+        &self.options
+    }
+
+    /// Change the options, which are applied on the next [`TextLayout::update`]
+    pub fn set_options(&mut self, options: TextOptions) {
+        if self.options != options {
+            self.options = options;
+            self.dirty = true;
+        }
+    }
+
+    /// Change the width the text wraps at, which is applied on the next [`TextLayout::update`]
+    pub fn set_max_width(&mut self, max_width: Option<f32>) {
+        self.set_options(TextOptions {
+            max_width,
+            ..self.options
+        });
+    }
+
+    /// If the text or options have changed since the text was last laid out
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Lay out the text again if it or the options have changed, or if the font is a different
+    /// one than last time
+    ///
+    /// Returns whether the text was laid out again. The font's effects are picked up either way.
+    pub fn update(&mut self, gfx: &Graphics, font: &mut FontRenderer) -> crate::Result<bool> {
+        let effects_changed = self.effects != font.effects();
+        self.effects = font.effects();
+        if !self.dirty && self.font == font.id() {
+            if effects_changed {
+                self.effect_layers = font.effect_layers(gfx, &self.glyphs, &self.carets)?;
+            }
+            return Ok(false);
+        }
+        self.glyphs.clear();
+        self.carets.clear();
+        self.metrics = font.arrange(
+            gfx,
+            &self.text,
+            &self.options,
+            &mut self.glyphs,
+            &mut self.carets,
+        )?;
+        self.logical = logical_order(&self.metrics.lines, &self.carets);
+        self.effect_layers = font.effect_layers(gfx, &self.glyphs, &self.carets)?;
+        self.font = font.id();
+        self.dirty = false;
+
+        Ok(true)
+    }
+
+    /// The extents and the metrics of each line, as of the last time the text was laid out
+    pub fn metrics(&self) -> &TextMetrics {
+        &self.metrics
+    }
+
+    /// How far the text extends past the offset, e.g. the furthest right and furthest down
+    /// position
+    pub fn extents(&self) -> Vector {
+        self.metrics.extents
+    }
+
+    /// Each laid-out glyph, in order
+    pub fn glyphs(&self) -> &[LayoutGlyph] {
+        &self.glyphs
+    }
+
+    /// Draw the text with a given color at a given position
+    ///
+    /// The text is drawn with the font's effects as of the last update.
+    pub fn draw(&self, gfx: &mut Graphics, color: Color, offset: Vector) {
+        self.effect_layers.draw(gfx, offset);
+        for glyph in self.glyphs.iter() {
+            draw_glyph(gfx, glyph, color, offset, self.effects);
+        }
+        gfx.set_distance_field(None, TextEffects::default());
+    }
+
+    /// Find the index to place a caret at for a point, such as where the text was clicked
+    ///
+    /// The point is matched to the closest line, then to the closest gap between characters.
+    pub fn index_at(&self, point: Vector) -> usize {
+        index_at(&self.metrics.lines, &self.carets, &self.logical, point)
+    }
+
+    /// Find where to draw a caret before the character at an index
+    ///
+    /// The returned point is at the top of the caret's line, and the caret should extend down to
+    /// the bottom of the line. An index that falls between two wrapped lines is placed at the
+    /// start of the second. In right-to-left text, the caret goes on the right of the character.
+    pub fn caret_position(&self, index: usize) -> Vector {
+        caret_position(&self.metrics.lines, &self.carets, &self.logical, index)
+    }
+}
+
+// Put the carets of each line in the order of the text
+//
+// Lines are laid out in the order they're drawn, so right-to-left text has its carets reversed.
+fn logical_order(lines: &[LineMetrics], carets: &[GlyphCaret]) -> Vec<usize> {
+    let mut logical: Vec<usize> = (0..carets.len()).collect();
+    for line in lines {
+        logical[line.glyphs.clone()].sort_by_key(|i| carets[*i].index);
+    }
+
+    logical
+}
+
+// The left and right of the grapheme cluster at an index, across all of its glyphs on a line
+fn cluster_span(carets: &[GlyphCaret], index: usize) -> (f32, f32) {
+    carets.iter().filter(|caret| caret.index == index).fold(
+        (f32::INFINITY, f32::NEG_INFINITY),
+        |(left, right), caret| (left.min(caret.x), right.max(caret.x + caret.advance)),
+    )
+}
+
+fn index_at(
+    lines: &[LineMetrics],
+    carets: &[GlyphCaret],
+    logical: &[usize],
+    point: Vector,
+) -> usize {
+    let line = match lines
+        .iter()
+        .find(|line| point.y < line.bounds.y() + line.bounds.height())
+        .or_else(|| lines.last())
+    {
+        Some(line) => line,
+        None => return 0,
+    };
+    let on_line = &carets[line.glyphs.clone()];
+    let caret = match on_line
+        .iter()
+        .find(|caret| point.x < caret.x + caret.advance)
+        .or_else(|| on_line.last())
+    {
+        Some(caret) => caret,
+        None => return line.text.end,
+    };
+
+    // The caret goes before the cluster from the half it starts at, and after it from the other
+    let (left, right) = cluster_span(on_line, caret.index);
+    let left_half = point.x < (left + right) / 2.0;
+    if left_half != caret.rtl {
+        return caret.index;
+    }
+    logical[line.glyphs.clone()]
+        .iter()
+        .map(|i| carets[*i].index)
+        .find(|index| *index > caret.index)
+        .unwrap_or(line.text.end)
+}
+
+fn caret_position(
+    lines: &[LineMetrics],
+    carets: &[GlyphCaret],
+    logical: &[usize],
+    index: usize,
+) -> Vector {
+    let line = match lines
+        .iter()
+        .rev()
+        .find(|line| line.text.start <= index)
+        .or_else(|| lines.first())
+    {
+        Some(line) => line,
+        None => return Vector::ZERO,
+    };
+    let on_line = &carets[line.glyphs.clone()];
+    // Left-to-right clusters start at their left, and right-to-left clusters at their right
+    let start = |caret: &GlyphCaret| {
+        let (left, right) = cluster_span(on_line, caret.index);
+        if caret.rtl {
+            right
+        } else {
+            left
+        }
+    };
+    let end = |caret: &GlyphCaret| {
+        let (left, right) = cluster_span(on_line, caret.index);
+        if caret.rtl {
+            left
+        } else {
+            right
+        }
+    };
+    let logical: Vec<&GlyphCaret> = logical[line.glyphs.clone()]
+        .iter()
+        .map(|i| &carets[*i])
+        .collect();
+    let x = match logical.iter().position(|caret| caret.index >= index) {
+        Some(i) if i == 0 || logical[i].index == index => start(logical[i]),
+        // The index is in the space between two words
+        Some(i) => end(logical[i - 1]),
+        None => logical.last().map_or(line.bounds.x(), |caret| end(caret)),
+    };
+
+    Vector::new(x, line.bounds.y())
+}
+
+// Where a line starts horizontally, and how much extra space goes in each gap between its words
+pub(crate) fn line_start(
+    options: &TextOptions,
+    width: f32,
+    gaps: usize,
+    ends_paragraph: bool,
+) -> (f32, f32) {
+    let box_width = options.max_width.unwrap_or(0.0);
+    match options.align {
+        HorizontalAlign::Left => (0.0, 0.0),
+        HorizontalAlign::Center => ((box_width - width) / 2.0, 0.0),
+        HorizontalAlign::Right => (box_width - width, 0.0),
+        HorizontalAlign::Justify => match options.max_width {
+            Some(max_width) if !ends_paragraph && gaps > 0 => {
+                (0.0, ((max_width - width) / gaps as f32).max(0.0))
+            }
+            _ => (0.0, 0.0),
+        },
+    }
+}
+
+// How tall lines of the given heights are, from the top of the first to the bottom of the last
+pub(crate) fn block_height(options: &TextOptions, heights: &[f32]) -> f32 {
+    let (last, rest) = match heights.split_last() {
+        Some(split) => split,
+        None => return 0.0,
+    };
+
+    rest.iter().sum::<f32>() * options.line_spacing + last
+}
+
+// The options to lay out text with so it fits the original box after being scaled
+pub(crate) fn scale_options(options: &TextOptions, scale: f32) -> TextOptions {
+    TextOptions {
+        max_width: options.max_width.map(|width| width / scale),
+        height: options.height.map(|height| height / scale),
+        ..*options
+    }
+}
+
+// Find about the largest scale from `min_scale` up to 1 that the text fits at
+pub(crate) fn shrink_scale(
+    min_scale: f32,
+    mut fits: impl FnMut(f32) -> crate::Result<bool>,
+) -> crate::Result<f32> {
+    let min_scale = min_scale.clamp(f32::EPSILON, 1.0);
+    if fits(1.0)? {
+        return Ok(1.0);
+    }
+    if !fits(min_scale)? {
+        return Ok(min_scale);
+    }
+    let (mut low, mut high) = (min_scale, 1.0);
+    // Close enough that the difference is less than a pixel for most text
+    for _ in 0..8 {
+        let middle = (low + high) / 2.0;
+        if fits(middle)? {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    Ok(low)
+}
+
+// Where the top of a block of text goes, given its height and the ascent of its first line
+pub(crate) fn block_top(options: &TextOptions, first_ascent: f32, block: f32) -> f32 {
+    let height = options.height.unwrap_or(0.0);
+    match options.vertical_align {
+        VerticalAlign::Baseline => -first_ascent,
+        VerticalAlign::Top => 0.0,
+        VerticalAlign::Middle => (height - block) / 2.0,
+        VerticalAlign::Bottom => height - block,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aligned(align: HorizontalAlign) -> TextOptions {
+        TextOptions {
+            max_width: Some(100.0),
+            align,
+            ..TextOptions::default()
+        }
+    }
+
+    #[test]
+    fn horizontal_alignment() {
+        assert_eq!(
+            line_start(&aligned(HorizontalAlign::Left), 40.0, 2, false),
+            (0.0, 0.0)
+        );
+        assert_eq!(
+            line_start(&aligned(HorizontalAlign::Center), 40.0, 2, false),
+            (30.0, 0.0)
+        );
+        assert_eq!(
+            line_start(&aligned(HorizontalAlign::Right), 40.0, 2, false),
+            (60.0, 0.0)
+        );
+        let unbounded = TextOptions {
+            align: HorizontalAlign::Center,
+            ..TextOptions::default()
+        };
+        assert_eq!(line_start(&unbounded, 40.0, 2, false), (-20.0, 0.0));
+    }
+
+    #[test]
+    fn justify_skips_paragraph_ends() {
+        let options = aligned(HorizontalAlign::Justify);
+        assert_eq!(line_start(&options, 40.0, 2, false), (0.0, 30.0));
+        assert_eq!(line_start(&options, 40.0, 2, true), (0.0, 0.0));
+        assert_eq!(line_start(&options, 40.0, 0, false), (0.0, 0.0));
+    }
+
+    #[test]
+    fn vertical_alignment() {
+        let options = |vertical_align| TextOptions {
+            height: Some(100.0),
+            vertical_align,
+            line_spacing: 2.0,
+            ..TextOptions::default()
+        };
+        // Three lines, each 10 tall and 20 apart, take up 50
+        let first_baseline = |vertical_align| {
+            let options = options(vertical_align);
+            let block = block_height(&options, &[10.0; 3]);
+            block_top(&options, 8.0, block) + 8.0
+        };
+        assert_eq!(block_height(&options(VerticalAlign::Top), &[10.0; 3]), 50.0);
+        assert_eq!(block_height(&options(VerticalAlign::Top), &[]), 0.0);
+        assert_eq!(first_baseline(VerticalAlign::Baseline), 0.0);
+        assert_eq!(first_baseline(VerticalAlign::Top), 8.0);
+        assert_eq!(first_baseline(VerticalAlign::Middle), 33.0);
+        assert_eq!(first_baseline(VerticalAlign::Bottom), 58.0);
+    }
+
+    // "hello world", wrapped after "hello", with every glyph 10 wide
+    fn wrapped_lines() -> (Vec<LineMetrics>, Vec<GlyphCaret>) {
+        let line = |y, text, glyphs| LineMetrics {
+            bounds: Rectangle::new(Vector::new(0.0, y), Vector::new(50.0, 10.0)),
+            baseline: y + 8.0,
+            text,
+            glyphs,
+        };
+        let lines = vec![line(0.0, 0..5, 0..5), line(10.0, 6..11, 5..10)];
+        let carets = (0..5)
+            .chain(6..11)
+            .map(|index| GlyphCaret {
+                x: (index % 6) as f32 * 10.0,
+                advance: 10.0,
+                index,
+                source: 0,
+                rtl: false,
+            })
+            .collect();
+
+        (lines, carets)
+    }
+
+    #[test]
+    fn carets_follow_glyphs() {
+        let (lines, carets) = wrapped_lines();
+        let logical = logical_order(&lines, &carets);
+        let caret = |index| caret_position(&lines, &carets, &logical, index);
+        assert_eq!(caret(0), Vector::new(0.0, 0.0));
+        assert_eq!(caret(3), Vector::new(30.0, 0.0));
+        assert_eq!(caret(5), Vector::new(50.0, 0.0));
+        assert_eq!(caret(6), Vector::new(0.0, 10.0));
+        assert_eq!(caret(11), Vector::new(50.0, 10.0));
+        assert_eq!(caret_position(&[], &[], &[], 4), Vector::ZERO);
+    }
+
+    #[test]
+    fn points_hit_the_nearest_gap() {
+        let (lines, carets) = wrapped_lines();
+        let logical = logical_order(&lines, &carets);
+        let index = |x, y| index_at(&lines, &carets, &logical, Vector::new(x, y));
+        assert_eq!(index(14.0, 5.0), 1);
+        assert_eq!(index(16.0, 5.0), 2);
+        assert_eq!(index(100.0, 5.0), 5);
+        assert_eq!(index(-5.0, 15.0), 6);
+        assert_eq!(index(0.0, -20.0), 0);
+        assert_eq!(index(100.0, 100.0), 11);
+    }
+
+    #[test]
+    fn carets_go_after_right_to_left_clusters() {
+        // "ab \u{5D0}\u{5D1}", with the Hebrew letters drawn from right to left
+        let lines = vec![LineMetrics {
+            bounds: Rectangle::new(Vector::ZERO, Vector::new(50.0, 10.0)),
+            baseline: 8.0,
+            text: 0..7,
+            glyphs: 0..4,
+        }];
+        let caret = |x, index, rtl| GlyphCaret {
+            x,
+            advance: 10.0,
+            index,
+            source: 0,
+            rtl,
+        };
+        let carets = vec![
+            caret(0.0, 0, false),
+            caret(10.0, 1, false),
+            caret(30.0, 5, true),
+            caret(40.0, 3, true),
+        ];
+        let logical = logical_order(&lines, &carets);
+        assert_eq!(logical, [0, 1, 3, 2]);
+        let x = |index| caret_position(&lines, &carets, &logical, index).x;
+        assert_eq!(x(0), 0.0);
+        assert_eq!(x(1), 10.0);
+        assert_eq!(x(2), 20.0);
+        assert_eq!(x(3), 50.0);
+        assert_eq!(x(5), 40.0);
+        assert_eq!(x(7), 30.0);
+        let index = |x| index_at(&lines, &carets, &logical, Vector::new(x, 5.0));
+        assert_eq!(index(45.0), 3);
+        assert_eq!(index(42.0), 5);
+        assert_eq!(index(33.0), 7);
+        assert_eq!(index(100.0), 3);
+    }
+
+    #[test]
+    fn bounds_cover_every_line() {
+        let line = |x, y, width| LineMetrics {
+            bounds: Rectangle::new(Vector::new(x, y), Vector::new(width, 10.0)),
+            baseline: y + 8.0,
+            text: 0..0,
+            glyphs: 0..0,
+        };
+        let metrics = TextMetrics {
+            extents: Vector::ZERO,
+            lines: vec![line(10.0, 0.0, 30.0), line(-5.0, 10.0, 20.0)],
+        };
+        assert_eq!(
+            metrics.bounds(),
+            Rectangle::new(Vector::new(-5.0, 0.0), Vector::new(45.0, 20.0))
+        );
+    }
+
+    #[test]
+    fn shrinking_finds_the_largest_scale_that_fits() {
+        // Text 100 units wide in an 80 unit box
+        let fits = |scale: f32| Ok(100.0 <= 80.0 / scale);
+        let scale = shrink_scale(0.5, fits).unwrap();
+        assert!(scale <= 0.8 && scale > 0.79, "{}", scale);
+        assert_eq!(shrink_scale(0.9, fits).unwrap(), 0.9);
+        assert_eq!(shrink_scale(0.5, |_| Ok(true)).unwrap(), 1.0);
+        let options = scale_options(&aligned(HorizontalAlign::Left), 0.5);
+        assert_eq!(options.max_width, Some(200.0));
+    }
+}
@@ -0,0 +1,569 @@
+#[cfg(feature = "shaping")]
+use super::shaping;
+use super::{layout, unicode, FontRenderer, InlineImage, WordGlyph, IMAGE_SOURCE};
+use super::{LineMetrics, TextMetrics, TextOptions, TextOverflow};
+use crate::geom::{Rectangle, Vector};
+
+use elefont::{Bounds, Glyph, TextureGlyph};
+use std::mem;
+use std::ops::Range;
+
+// A part of a block of text drawn with one font at one scale, or an inline image
+pub(crate) struct TextRun {
+    pub text: Range<usize>,
+    pub scale: f32,
+    pub content: RunContent,
+}
+
+pub(crate) enum RunContent {
+    // An index into the fonts the block is laid out with
+    Font(usize),
+    // An image standing in for the run's text
+    Image(InlineImage),
+}
+
+// A glyph placed on a line, before the line is aligned
+pub(crate) struct LineGlyph {
+    pub glyph: WordGlyph,
+    pub x: f32,
+    // How far the glyph moves the pen, at the scale of its run
+    pub advance: f32,
+    // How many words come before this glyph's word on the line
+    pub gap: usize,
+    // The byte index of the grapheme cluster the glyph was made from
+    pub index: usize,
+    // The bidirectional embedding level of the glyph, which is odd for right-to-left text
+    pub level: u8,
+    // Which run the glyph was made from
+    pub run: usize,
+}
+
+// A line of text that has been broken but not yet aligned
+pub(crate) struct PendingLine {
+    pub glyphs: Vec<LineGlyph>,
+    pub words: usize,
+    pub width: f32,
+    pub text: Range<usize>,
+    pub ends_paragraph: bool,
+}
+
+impl PendingLine {
+    fn new(start: usize) -> PendingLine {
+        PendingLine {
+            glyphs: Vec::new(),
+            words: 0,
+            width: 0.0,
+            text: start..start,
+            ends_paragraph: false,
+        }
+    }
+}
+
+// A glyph or image placed in the box of a block of text
+pub(crate) struct PlacedGlyph {
+    pub glyph: WordGlyph,
+    // Where the glyph is drawn, and how much it's scaled by its run and by shrinking
+    pub area: Rectangle,
+    pub scale: f32,
+    // Where the pen was on the baseline before the glyph, and how far the glyph moved it
+    pub pen: Vector,
+    pub advance: f32,
+    pub index: usize,
+    pub run: usize,
+    // If the glyph is part of right-to-left text
+    pub rtl: bool,
+}
+
+// The fonts and runs of a block of text, which break it into lines and place them in the box
+//
+// Plain text is a single run in the first font, and rich text has a run for each span.
+pub(crate) struct TextBlock<'a> {
+    pub fonts: &'a mut [FontRenderer],
+    pub text: &'a str,
+    pub runs: &'a [TextRun],
+}
+
+impl TextBlock<'_> {
+    // Lay out the text, returning the placed glyphs and the metrics of each line
+    pub fn arrange(
+        &mut self,
+        gfx: &crate::graphics::Graphics,
+        options: &TextOptions,
+    ) -> crate::Result<(Vec<PlacedGlyph>, TextMetrics)> {
+        let scale = match options.overflow {
+            TextOverflow::Shrink { min_scale } => {
+                layout::shrink_scale(min_scale, |scale| self.fits(gfx, options, scale))?
+            }
+            _ => 1.0,
+        };
+        let options = &layout::scale_options(options, scale);
+        let mut lines = self.break_lines(gfx, options)?;
+        self.limit_lines(gfx, &mut lines, options)?;
+        #[cfg(feature = "shaping")]
+        lines.iter_mut().for_each(shaping::reorder_line);
+        let extents: Vec<(f32, f32)> = lines.iter().map(|line| self.line_extent(line)).collect();
+        let heights: Vec<f32> = extents
+            .iter()
+            .map(|(ascent, descent)| ascent + descent)
+            .collect();
+        let block = layout::block_height(options, &heights);
+        let first_ascent = extents.first().map_or(0.0, |(ascent, _)| *ascent);
+        let mut top = layout::block_top(options, first_ascent, block);
+        let mut glyphs = Vec::new();
+        let mut text_extents = Vector::ZERO;
+        let mut metrics = Vec::with_capacity(lines.len());
+
+        for (line, (ascent, descent)) in lines.into_iter().zip(extents) {
+            let height = ascent + descent;
+            let baseline = top + ascent;
+            let gaps = line.words.saturating_sub(1);
+            let (start, gap_width) =
+                layout::line_start(options, line.width, gaps, line.ends_paragraph);
+            let first_glyph = glyphs.len();
+            for LineGlyph {
+                glyph,
+                x,
+                advance,
+                gap,
+                index,
+                level,
+                run,
+            } in line.glyphs
+            {
+                let pen = Vector::new(start + x + gap as f32 * gap_width, baseline);
+                let run_scale = self.runs[run].scale;
+                let size = match &self.runs[run].content {
+                    RunContent::Image(image) => image.size,
+                    RunContent::Font(_) => {
+                        let bounds = glyph.glyph.bounds;
+                        Vector::new(bounds.width as f32, bounds.height as f32) * run_scale
+                    }
+                };
+                let area = Rectangle::new((pen + glyph.offset * run_scale) * scale, size * scale);
+                text_extents = text_extents.max(area.pos + area.size);
+                glyphs.push(PlacedGlyph {
+                    glyph,
+                    area,
+                    scale: run_scale * scale,
+                    pen: pen * scale,
+                    advance: advance * scale,
+                    index,
+                    run,
+                    rtl: level % 2 == 1,
+                });
+            }
+            metrics.push(LineMetrics {
+                bounds: Rectangle::new(
+                    Vector::new(start, top) * scale,
+                    Vector::new(line.width + gaps as f32 * gap_width, height) * scale,
+                ),
+                baseline: baseline * scale,
+                text: line.text,
+                glyphs: first_glyph..glyphs.len(),
+            });
+            top += height * options.line_spacing;
+
+            let next_baseline = baseline + height * options.line_spacing;
+            text_extents = text_extents.max(Vector::new(0.0, next_baseline * scale));
+        }
+
+        Ok((
+            glyphs,
+            TextMetrics {
+                extents: text_extents,
+                lines: metrics,
+            },
+        ))
+    }
+
+    // If the text fits in the box of the options at a scale, without overflowing
+    fn fits(
+        &mut self,
+        gfx: &crate::graphics::Graphics,
+        options: &TextOptions,
+        scale: f32,
+    ) -> crate::Result<bool> {
+        let options = layout::scale_options(options, scale);
+        let lines = self.break_lines(gfx, &options)?;
+        let too_many = matches!(options.max_lines, Some(max) if lines.len() > max);
+        let too_wide =
+            matches!(options.max_width, Some(max) if lines.iter().any(|line| line.width > max));
+        let heights: Vec<f32> = lines
+            .iter()
+            .map(|line| {
+                let (ascent, descent) = self.line_extent(line);
+                ascent + descent
+            })
+            .collect();
+        let block = layout::block_height(&options, &heights);
+        let too_tall = matches!(options.height, Some(height) if block > height);
+
+        Ok(!too_many && !too_wide && !too_tall)
+    }
+
+    // Leave out lines past the maximum, and end lines that overflow with an ellipsis
+    fn limit_lines(
+        &mut self,
+        gfx: &crate::graphics::Graphics,
+        lines: &mut Vec<PendingLine>,
+        options: &TextOptions,
+    ) -> crate::Result<()> {
+        let clipped = match options.max_lines {
+            Some(max) if lines.len() > max => {
+                lines.truncate(max);
+                true
+            }
+            _ => false,
+        };
+        if options.overflow == TextOverflow::Clip {
+            return Ok(());
+        }
+        let max_width = options.max_width.unwrap_or(f32::INFINITY);
+        let last = lines.len().saturating_sub(1);
+        for (i, line) in lines.iter_mut().enumerate() {
+            if line.width > max_width || (clipped && i == last) {
+                self.add_ellipsis(gfx, line, max_width, options)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Cut a line short so it ends with an ellipsis within the width
+    //
+    // The ellipsis is drawn in the font of the last text on the line.
+    fn add_ellipsis(
+        &mut self,
+        gfx: &crate::graphics::Graphics,
+        line: &mut PendingLine,
+        max_width: f32,
+        options: &TextOptions,
+    ) -> crate::Result<()> {
+        let last_text = line.glyphs.last().map_or(line.text.end, |last| last.index);
+        let (run, font) = match self.font_run_before(last_text) {
+            Some(found) => found,
+            None => return Ok(()),
+        };
+        let scale = self.runs[run].scale;
+        let font = &mut self.fonts[font];
+        let ellipsis = match font.source_for('…') {
+            Some(_) => "…",
+            None => "...",
+        };
+        let mut glyphs = Vec::new();
+        font.word_glyphs(gfx, ellipsis, &mut glyphs)?;
+        let width: f32 = glyphs
+            .iter()
+            .map(|glyph| glyph.advance * scale + options.letter_spacing)
+            .sum();
+        // Leave out the grapheme clusters that would overlap the ellipsis
+        while let Some(last) = line.glyphs.last() {
+            if last.x + last.advance <= max_width - width {
+                break;
+            }
+            let cluster = last.index;
+            while matches!(line.glyphs.last(), Some(glyph) if glyph.index == cluster) {
+                line.glyphs.pop();
+            }
+            line.text.end = cluster;
+        }
+        let (mut x, gap, level) = line.glyphs.last().map_or((0.0, 0, 0), |last| {
+            let end = last.x + last.advance + options.letter_spacing;
+            (end, last.gap, last.level)
+        });
+        for glyph in glyphs {
+            let advance = glyph.advance * scale;
+            line.glyphs.push(LineGlyph {
+                glyph,
+                x,
+                advance,
+                gap,
+                index: line.text.end,
+                level,
+                run,
+            });
+            x += advance + options.letter_spacing;
+        }
+        line.width = x;
+        line.words = gap + 1;
+        // A cut-off line isn't stretched to fill the width
+        line.ends_paragraph = true;
+
+        Ok(())
+    }
+
+    // Break the text into lines, placing each glyph relative to the start of its line
+    //
+    // Lines end at mandatory breaks, and wrap at the break opportunities of UAX #14. Each line
+    // break segment is laid out a piece at a time (see pieces), and every glyph knows which
+    // grapheme cluster it was made from.
+    fn break_lines(
+        &mut self,
+        gfx: &crate::graphics::Graphics,
+        options: &TextOptions,
+    ) -> crate::Result<Vec<PendingLine>> {
+        let text = self.text;
+        let mut lines = Vec::new();
+        let mut glyphs = Vec::new();
+        let mut segment_glyphs: Vec<LineGlyph> = Vec::new();
+
+        for paragraph in unicode::paragraphs(text) {
+            let content = &text[paragraph.clone()];
+            #[cfg(feature = "shaping")]
+            let levels = shaping::levels(content);
+            #[cfg(not(feature = "shaping"))]
+            let levels = vec![0; content.len()];
+
+            let mut line = PendingLine::new(paragraph.start);
+            let mut cursor = 0.0;
+            // If the last segment ended in spaces or an ideograph, which justified text stretches
+            let mut spaced = false;
+            let mut starts = unicode::line_breaks(content);
+            starts.push(content.len());
+            let mut segment_start = 0;
+            for segment_end in starts {
+                let segment = &content[segment_start..segment_end];
+                let start = paragraph.start + segment_start;
+                segment_start = segment_end;
+                let (word, _) = unicode::trim_spaces(segment);
+                let spaces = start + word.len()..start + segment.len();
+                let spaces_width = self.spaces_width(spaces.clone(), options);
+                if word.is_empty() {
+                    cursor += spaces_width;
+                    continue;
+                }
+
+                // Find where each glyph goes in the segment, and the total width of the segment
+                let mut word_width = 0.0;
+                let mut kerned = false;
+                let clusters: Vec<usize> = unicode::clusters(word)
+                    .into_iter()
+                    .map(|cluster| start + cluster.start)
+                    .collect();
+                for (piece, run, level) in
+                    self.pieces(word, start, &levels[start - paragraph.start..])
+                {
+                    // Spaces that lines don't break at aren't drawn, and aren't kerned across
+                    if text[piece.clone()].chars().all(char::is_whitespace) {
+                        word_width += self.spaces_width(piece, options);
+                        kerned = false;
+                        continue;
+                    }
+                    let scale = self.runs[run].scale;
+                    self.piece_glyphs(gfx, run, piece, level % 2 == 1, &mut glyphs)?;
+                    // Each glyph belongs to the cluster its character is in, and the glyphs of
+                    // right-to-left text are put back in the order of their clusters
+                    for (index, _) in glyphs.iter_mut() {
+                        *index = clusters[clusters.partition_point(|start| start <= index) - 1];
+                    }
+                    glyphs.sort_by_key(|(index, _)| *index);
+                    for (cluster_start, glyph) in glyphs.drain(..) {
+                        if let Some(previous) = segment_glyphs.last() {
+                            if kerned && previous.run == run {
+                                word_width += self.kerning(run, &previous.glyph, &glyph) * scale;
+                            }
+                        }
+                        let advance = glyph.advance * scale;
+                        segment_glyphs.push(LineGlyph {
+                            glyph,
+                            x: word_width,
+                            advance,
+                            gap: 0,
+                            index: cluster_start,
+                            level,
+                            run,
+                        });
+                        word_width += advance + options.letter_spacing;
+                        kerned = true;
+                    }
+                }
+
+                // If the segment would overflow the line, move down a line
+                if let Some(width) = options.max_width {
+                    if line.words > 0 && cursor + word_width > width {
+                        lines.push(mem::replace(&mut line, PendingLine::new(start)));
+                        cursor = 0.0;
+                    }
+                }
+
+                // Segments that aren't separated by spaces, like the parts of a hyphenated word,
+                // are stretched as one word when justifying
+                let ideographic =
+                    matches!(word.chars().next(), Some(c) if unicode::is_ideographic(c));
+                if line.words == 0 || spaced || ideographic {
+                    line.words += 1;
+                }
+                let gap = line.words - 1;
+                line.glyphs
+                    .extend(segment_glyphs.drain(..).map(|glyph| LineGlyph {
+                        x: cursor + glyph.x,
+                        gap,
+                        ..glyph
+                    }));
+                cursor += word_width;
+                line.width = cursor;
+                line.text.end = start + word.len();
+                cursor += spaces_width;
+                spaced = !spaces.is_empty()
+                    || matches!(word.chars().last(), Some(c) if unicode::is_ideographic(c));
+            }
+            line.ends_paragraph = true;
+            lines.push(line);
+        }
+
+        Ok(lines)
+    }
+
+    // Split a line break segment into the pieces that are laid out together, with their runs and
+    // bidirectional levels
+    //
+    // With the `shaping` feature, grapheme clusters in the same run and at the same level are
+    // shaped together so letters can join across them. Otherwise each cluster is its own piece.
+    fn pieces(&self, word: &str, start: usize, levels: &[u8]) -> Vec<(Range<usize>, usize, u8)> {
+        let blank =
+            |range: &Range<usize>| self.text[range.clone()].chars().all(char::is_whitespace);
+        let mut pieces: Vec<(Range<usize>, usize, u8)> = Vec::new();
+        for cluster in unicode::clusters(word) {
+            let level = levels[cluster.start];
+            let cluster = start + cluster.start..start + cluster.end;
+            let run = self.run_at(cluster.start).unwrap_or(0);
+            match pieces.last_mut() {
+                Some((piece, piece_run, piece_level))
+                    if cfg!(feature = "shaping")
+                        && (*piece_run, *piece_level) == (run, level)
+                        && !blank(piece)
+                        && !blank(&cluster) =>
+                {
+                    piece.end = cluster.end
+                }
+                _ => pieces.push((cluster, run, level)),
+            }
+        }
+
+        pieces
+    }
+
+    // Find the glyphs for a piece of the text, with the byte index of the character each came
+    // from, or the image its run stands for
+    fn piece_glyphs(
+        &mut self,
+        gfx: &crate::graphics::Graphics,
+        run: usize,
+        piece: Range<usize>,
+        rtl: bool,
+        glyphs: &mut Vec<(usize, WordGlyph)>,
+    ) -> crate::Result<()> {
+        match &self.runs[run].content {
+            RunContent::Font(font) => {
+                let text = &self.text[piece.clone()];
+                self.fonts[*font].shaped_glyphs(gfx, text, piece.start, rtl, glyphs)
+            }
+            RunContent::Image(image) => {
+                glyphs.push((
+                    piece.start,
+                    WordGlyph {
+                        glyph: TextureGlyph {
+                            glyph: Glyph(0),
+                            bounds: Bounds {
+                                x: image.region.x() as i32,
+                                y: image.region.y() as i32,
+                                width: image.region.width() as u32,
+                                height: image.region.height() as u32,
+                            },
+                        },
+                        image: image.image.clone(),
+                        offset: Vector::new(0.0, -image.size.y),
+                        advance: image.size.x,
+                        source: IMAGE_SOURCE,
+                    },
+                ));
+
+                Ok(())
+            }
+        }
+    }
+
+    fn kerning(&self, run: usize, a: &WordGlyph, b: &WordGlyph) -> f32 {
+        match self.runs[run].content {
+            RunContent::Font(font) => self.fonts[font].kerning(a, b),
+            RunContent::Image(_) => 0.0,
+        }
+    }
+
+    // How wide the whitespace in a range of the text is
+    fn spaces_width(&self, spaces: Range<usize>, options: &TextOptions) -> f32 {
+        self.text[spaces.clone()]
+            .char_indices()
+            .filter(|(_, c)| c.is_whitespace())
+            .map(|(i, _)| {
+                let advance = self
+                    .font_run_before(spaces.start + i)
+                    .map_or(0.0, |(run, font)| {
+                        self.fonts[font].space_advance() * self.runs[run].scale
+                    });
+                advance + options.letter_spacing
+            })
+            .sum()
+    }
+
+    // How far a line reaches above and below its baseline, which is as far as the runs on it do
+    fn line_extent(&self, line: &PendingLine) -> (f32, f32) {
+        let text = &line.text;
+        let on_line = |(i, run): &(usize, &TextRun)| {
+            let overlaps = run.text.start < text.end && text.start < run.text.end;
+            overlaps || line.glyphs.iter().any(|glyph| glyph.run == *i)
+        };
+        let mut runs: Vec<usize> = self
+            .runs
+            .iter()
+            .enumerate()
+            .filter(on_line)
+            .map(|(i, _)| i)
+            .collect();
+        // An empty line is as tall as the text it's in
+        if runs.is_empty() {
+            runs.extend(self.font_run_before(text.start).map(|(run, _)| run));
+        }
+
+        runs.into_iter().fold((0.0, 0.0), |(ascent, descent), run| {
+            let run = &self.runs[run];
+            let (run_ascent, run_descent) = match &run.content {
+                RunContent::Font(font) => {
+                    let font = &self.fonts[*font];
+                    let line_height = font.line_height();
+                    (
+                        font.ascent * run.scale,
+                        (line_height - font.ascent) * run.scale,
+                    )
+                }
+                RunContent::Image(image) => (image.size.y, 0.0),
+            };
+            (ascent.max(run_ascent), descent.max(run_descent))
+        })
+    }
+
+    // The run a byte of the text is in
+    fn run_at(&self, index: usize) -> Option<usize> {
+        let after = self.runs.partition_point(|run| run.text.start <= index);
+        after.checked_sub(1)
+    }
+
+    // The last run drawn with a font at or before a byte of the text, and its font
+    fn font_run_before(&self, index: usize) -> Option<(usize, usize)> {
+        let end = self.run_at(index).map_or(0, |run| run + 1);
+        self.runs[..end]
+            .iter()
+            .enumerate()
+            .rev()
+            .chain(
+                self.runs[end..]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, run)| (end + i, run)),
+            )
+            .find_map(|(i, run)| match run.content {
+                RunContent::Font(font) => Some((i, font)),
+                RunContent::Image(_) => None,
+            })
+    }
+}
@@ -0,0 +1,539 @@
+use super::lines::{PlacedGlyph, RunContent, TextBlock, TextRun};
+use super::{FontRenderer, GlyphSource, TextMetrics, TextOptions};
+use crate::error::FontError;
+use crate::geom::{Rectangle, Vector};
+use crate::graphics::{Color, DistanceField, Graphics, Image, TextEffects};
+use crate::QuicksilverError;
+
+use std::collections::HashMap;
+use std::mem;
+
+/// How a span of [`RichText`] is drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextStyle {
+    /// The color of the glyphs and the underline
+    pub color: Color,
+    /// Which of the fonts given to [`RichText::layout`] to draw with
+    pub font: usize,
+    /// How much to scale the glyphs up or down
+    ///
+    /// Glyphs are scaled after they're rendered, so a cached [`VectorFont`] will look blurry at
+    /// large scales. A renderer at a larger font size looks better.
+    ///
+    /// [`VectorFont`]: crate::graphics::VectorFont
+    pub scale: f32,
+    /// If a line is drawn under the text
+    pub underline: bool,
+}
+
+impl Default for TextStyle {
+    fn default() -> TextStyle {
+        TextStyle {
+            color: Color::WHITE,
+            font: 0,
+            scale: 1.0,
+            underline: false,
+        }
+    }
+}
+
+/// An image drawn inline with [`RichText`], sitting on the baseline like a glyph
+#[derive(Clone)]
+pub struct InlineImage {
+    /// The image to draw from
+    pub image: Image,
+    /// The region of the image to draw
+    pub region: Rectangle,
+    /// How large to draw the region
+    pub size: Vector,
+}
+
+impl InlineImage {
+    /// Draw a whole image at its own size
+    pub fn new(image: Image) -> InlineImage {
+        let size = image.size();
+        InlineImage {
+            image,
+            region: Rectangle::new_sized(size),
+            size,
+        }
+    }
+
+    /// Draw a region of an image, such as an icon in an atlas, at the region's size
+    pub fn from_region(image: Image, region: Rectangle) -> InlineImage {
+        InlineImage {
+            image,
+            region,
+            size: region.size,
+        }
+    }
+}
+
+/// A part of [`RichText`]
+#[derive(Clone)]
+pub enum RichSpan {
+    /// Text drawn in a given style
+    Text(String, TextStyle),
+    /// An image drawn inline with the text
+    Image(InlineImage),
+}
+
+/// Text made of spans in different colors, fonts and scales, with inline images
+///
+/// Build it span by span, or [`parse`] it from markup. Lines are broken, shaped and cut off across
+/// spans in the same way as [`FontRenderer::layout_glyphs_with_options`], and are aligned with the
+/// same [`TextOptions`]. Each line is as tall as the tallest span or image on it. An ellipsis is
+/// drawn in the style of the text it follows.
+///
+/// [`parse`]: RichText::parse
+#[derive(Clone, Default)]
+pub struct RichText {
+    spans: Vec<RichSpan>,
+}
+
+// Images take up a single object replacement character in the plain text
+const IMAGE_CHARACTER: char = '\u{FFFC}';
+
+impl RichText {
+    /// Create rich text with no spans
+    pub fn new() -> RichText {
+        RichText::default()
+    }
+
+    /// Parse rich text from markup
+    ///
+    /// Text is drawn in the `base` style, which is changed by tags until they're closed:
+    /// - `[color=#f80]`, `[color=#ff8800]` or `[color=#ff880080]` ... `[/color]`
+    /// - `[font=1]` ... `[/font]`
+    /// - `[scale=1.5]` ... `[/scale]`
+    /// - `[u]` ... `[/u]`
+    ///
+    /// `[icon=name]` inserts the image with that name in `icons`, and `[[` is a literal `[`. Tags
+    /// must be closed in the reverse order they were opened.
+    pub fn parse(
+        markup: &str,
+        base: TextStyle,
+        icons: &HashMap<String, InlineImage>,
+    ) -> Result<RichText, QuicksilverError> {
+        let mut rich = RichText::new();
+        let mut open_tags = Vec::new();
+        let mut style = base;
+        let mut text = String::new();
+        let mut rest = markup;
+
+        while let Some(open) = rest.find('[') {
+            text.push_str(&rest[..open]);
+            rest = &rest[open + 1..];
+            if rest.starts_with('[') {
+                text.push('[');
+                rest = &rest[1..];
+                continue;
+            }
+            let close = rest
+                .find(']')
+                .ok_or_else(|| invalid("a tag is missing its closing `]`"))?;
+            let tag = &rest[..close];
+            rest = &rest[close + 1..];
+            if !text.is_empty() {
+                rich.push_text(mem::take(&mut text), style);
+            }
+
+            if let Some(name) = tag.strip_prefix('/') {
+                match open_tags.pop() {
+                    Some((open, previous)) if open == name => style = previous,
+                    _ => {
+                        return Err(invalid(format!(
+                            "`[/{}]` doesn't close the last opened tag",
+                            name
+                        )))
+                    }
+                }
+                continue;
+            }
+            let (name, value) = match tag.find('=') {
+                Some(split) => (&tag[..split], Some(&tag[split + 1..])),
+                None => (tag, None),
+            };
+            let previous = style;
+            match (name, value) {
+                ("icon", Some(icon)) => {
+                    let image = icons
+                        .get(icon)
+                        .ok_or_else(|| invalid(format!("there's no icon named `{}`", icon)))?;
+                    rich.push_image(image.clone());
+                    continue;
+                }
+                ("color", Some(hex)) => style.color = parse_color(hex)?,
+                ("font", Some(font)) => {
+                    style.font = font
+                        .parse()
+                        .map_err(|_| invalid(format!("`{}` isn't a font index", font)))?;
+                }
+                ("scale", Some(scale)) => {
+                    style.scale = match scale.parse::<f32>() {
+                        Ok(scale) if scale > 0.0 => scale,
+                        _ => return Err(invalid(format!("`{}` isn't a valid scale", scale))),
+                    };
+                }
+                ("u", None) => style.underline = true,
+                _ => return Err(invalid(format!("`[{}]` isn't a known tag", tag))),
+            }
+            open_tags.push((name, previous));
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            rich.push_text(text, style);
+        }
+        if let Some((name, _)) = open_tags.last() {
+            return Err(invalid(format!("`[{}]` is never closed", name)));
+        }
+
+        Ok(rich)
+    }
+
+    /// Add some text in the given style
+    pub fn push_text(&mut self, text: impl Into<String>, style: TextStyle) {
+        self.spans.push(RichSpan::Text(text.into(), style));
+    }
+
+    /// Add an image inline with the text
+    pub fn push_image(&mut self, image: InlineImage) {
+        self.spans.push(RichSpan::Image(image));
+    }
+
+    /// The spans that make up the text
+    pub fn spans(&self) -> &[RichSpan] {
+        &self.spans
+    }
+
+    /// The text without any styling
+    ///
+    /// Images are included as U+FFFC, the object replacement character. The text ranges in the
+    /// [`TextMetrics`] of laid-out rich text are byte ranges of this string.
+    pub fn plain_text(&self) -> String {
+        self.spans
+            .iter()
+            .fold(String::new(), |mut plain, span| match span {
+                RichSpan::Text(text, _) => plain + text,
+                RichSpan::Image(_) => {
+                    plain.push(IMAGE_CHARACTER);
+                    plain
+                }
+            })
+    }
+
+    /// Lay out the text with the given fonts and options, so it can be drawn
+    ///
+    /// Each span's [`TextStyle::font`] is an index into `fonts`. If a span uses an index past the
+    /// end of `fonts`, this fails with an `InvalidMarkup` font error.
+    pub fn layout(
+        &self,
+        gfx: &Graphics,
+        fonts: &mut [FontRenderer],
+        options: &TextOptions,
+    ) -> crate::Result<RichLayout> {
+        let text = self.plain_text();
+        let runs = self.runs(fonts.len())?;
+        let mut block = TextBlock {
+            fonts: &mut *fonts,
+            text: &text,
+            runs: &runs,
+        };
+        let (placed, metrics) = block.arrange(gfx, options)?;
+        let underlines = self.underlines(fonts, &placed, &metrics);
+        let glyphs = placed
+            .into_iter()
+            .map(|placed| self.rich_glyph(fonts, placed))
+            .collect();
+
+        Ok(RichLayout {
+            glyphs,
+            underlines,
+            metrics,
+        })
+    }
+
+    /// Lay out the text and draw it at a given position, returning where each line ended up
+    ///
+    /// To draw the same text many times, [`RichText::layout`] it once instead.
+    pub fn draw(
+        &self,
+        gfx: &mut Graphics,
+        fonts: &mut [FontRenderer],
+        options: &TextOptions,
+        offset: Vector,
+    ) -> crate::Result<TextMetrics> {
+        let layout = self.layout(gfx, fonts, options)?;
+        layout.draw(gfx, offset);
+
+        Ok(layout.metrics)
+    }
+
+    // A run of the plain text for each span, checking that the spans' fonts are in range
+    fn runs(&self, fonts: usize) -> crate::Result<Vec<TextRun>> {
+        let mut start = 0;
+        let mut runs = Vec::with_capacity(self.spans.len());
+        for span in self.spans.iter() {
+            let (length, scale, content) = match span {
+                RichSpan::Text(_, style) if style.font >= fonts => {
+                    return Err(invalid(format!(
+                        "there's no font {}, only {} fonts were given",
+                        style.font, fonts
+                    )));
+                }
+                RichSpan::Text(text, style) => {
+                    (text.len(), style.scale, RunContent::Font(style.font))
+                }
+                RichSpan::Image(image) => (
+                    IMAGE_CHARACTER.len_utf8(),
+                    1.0,
+                    RunContent::Image(image.clone()),
+                ),
+            };
+            runs.push(TextRun {
+                text: start..start + length,
+                scale,
+                content,
+            });
+            start += length;
+        }
+
+        Ok(runs)
+    }
+
+    fn rich_glyph(&self, fonts: &[FontRenderer], placed: PlacedGlyph) -> RichGlyph {
+        let (region, color, field) = match &self.spans[placed.run] {
+            RichSpan::Text(_, style) => {
+                let bounds = placed.glyph.glyph.bounds;
+                let region = Rectangle::new(
+                    Vector::new(bounds.x as f32, bounds.y as f32),
+                    Vector::new(bounds.width as f32, bounds.height as f32),
+                );
+                let font = &fonts[style.font];
+                let field = font
+                    .source(placed.glyph.source)
+                    .and_then(GlyphSource::distance_field)
+                    .map(|field| (field, font.effects, placed.scale));
+                (region, style.color, field)
+            }
+            RichSpan::Image(image) => (image.region, Color::WHITE, None),
+        };
+
+        RichGlyph {
+            image: placed.glyph.image,
+            region,
+            area: placed.area,
+            color,
+            field,
+        }
+    }
+
+    // The lines under underlined glyphs, joined across the spaces between them if the text in
+    // between is underlined in the same color
+    fn underlines(
+        &self,
+        fonts: &[FontRenderer],
+        placed: &[PlacedGlyph],
+        metrics: &TextMetrics,
+    ) -> Vec<(Rectangle, Color)> {
+        let mut underlines: Vec<(Rectangle, Color)> = Vec::new();
+        for line in metrics.lines.iter() {
+            let mut last_run: Option<usize> = None;
+            for glyph in placed[line.glyphs.clone()].iter() {
+                let style = match &self.spans[glyph.run] {
+                    RichSpan::Text(_, style) if style.underline => style,
+                    _ => {
+                        last_run = None;
+                        continue;
+                    }
+                };
+                let (left, right) = (glyph.pen.x, glyph.pen.x + glyph.advance);
+                let joined = match (last_run, underlines.last_mut()) {
+                    (Some(run), Some((area, color))) if *color == style.color => {
+                        let (first, last) = (run.min(glyph.run), run.max(glyph.run));
+                        let between = self.spans[first..=last].iter().all(|span| {
+                            matches!(span, RichSpan::Text(_, between)
+                                if between.underline && between.color == style.color)
+                        });
+                        if between {
+                            let right = right.max(area.x() + area.width());
+                            area.pos.x = area.x().min(left);
+                            area.size.x = right - area.x();
+                        }
+                        between
+                    }
+                    _ => false,
+                };
+                if !joined {
+                    let line_height = fonts[style.font].line_height();
+                    let thickness = (line_height * glyph.scale / 16.0).max(1.0);
+                    let area = Rectangle::new(
+                        Vector::new(left, glyph.pen.y + thickness),
+                        Vector::new(right - left, thickness),
+                    );
+                    underlines.push((area, style.color));
+                }
+                last_run = Some(glyph.run);
+            }
+        }
+
+        underlines
+    }
+}
+
+fn invalid(message: impl Into<String>) -> QuicksilverError {
+    FontError::InvalidMarkup(message.into()).into()
+}
+
+fn parse_color(hex: &str) -> Result<Color, QuicksilverError> {
+    let digits = hex.trim_start_matches('#');
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid(format!("`{}` isn't a hex color", hex)));
+    }
+    match digits.len() {
+        3 | 6 => Ok(Color::from_hex(digits)),
+        8 => {
+            let alpha = u8::from_str_radix(&digits[6..], 16).unwrap_or(255);
+            Ok(Color::from_hex(&digits[..6]).with_alpha(alpha as f32 / 255.0))
+        }
+        _ => Err(invalid(format!("`{}` isn't a hex color", hex))),
+    }
+}
+
+/// [`RichText`] that has been laid out, ready to be drawn many times
+///
+/// Create it with [`RichText::layout`]. It keeps the glyphs it was laid out with, so it doesn't
+/// need the fonts to draw.
+pub struct RichLayout {
+    glyphs: Vec<RichGlyph>,
+    underlines: Vec<(Rectangle, Color)>,
+    metrics: TextMetrics,
+}
+
+// A glyph or image placed in a laid-out block of rich text
+struct RichGlyph {
+    image: Image,
+    region: Rectangle,
+    area: Rectangle,
+    color: Color,
+    // How the image stores a distance field, the effects to draw with it, and how much it's scaled
+    field: Option<(DistanceField, TextEffects, f32)>,
+}
+
+impl RichLayout {
+    /// Where each line ended up, and how far the text extends past the offset
+    ///
+    /// The glyph ranges of each line count both glyphs and inline images.
+    pub fn metrics(&self) -> &TextMetrics {
+        &self.metrics
+    }
+
+    /// How far the text extends past the offset, e.g. the furthest right and furthest down
+    /// position
+    pub fn extents(&self) -> Vector {
+        self.metrics.extents
+    }
+
+    /// Draw the text at a given position
+    pub fn draw(&self, gfx: &mut Graphics, offset: Vector) {
+        for glyph in self.glyphs.iter() {
+            match glyph.field {
+                Some((field, effects, scale)) => {
+                    gfx.set_scaled_distance_field(field, effects, scale)
+                }
+                None => gfx.set_distance_field(None, TextEffects::default()),
+            }
+            let area = Rectangle::new(offset + glyph.area.pos, glyph.area.size);
+            gfx.draw_subimage_tinted(&glyph.image, glyph.region, area, glyph.color);
+        }
+        gfx.set_distance_field(None, TextEffects::default());
+        for (area, color) in self.underlines.iter() {
+            gfx.fill_rect(&Rectangle::new(offset + area.pos, area.size), *color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(rich: &RichText) -> Vec<(&str, TextStyle)> {
+        rich.spans()
+            .iter()
+            .filter_map(|span| match span {
+                RichSpan::Text(text, style) => Some((text.as_str(), *style)),
+                RichSpan::Image(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tags_nest() {
+        let base = TextStyle::default();
+        let rich = RichText::parse(
+            "a [color=#f00]red [u]link[/u][/color] [[b] [scale=2][font=1]big[/font][/scale]",
+            base,
+            &HashMap::new(),
+        )
+        .unwrap();
+        let red = TextStyle {
+            color: Color::from_hex("ff0000"),
+            ..base
+        };
+        let big = TextStyle {
+            font: 1,
+            scale: 2.0,
+            ..base
+        };
+        assert_eq!(
+            spans(&rich),
+            vec![
+                ("a ", base),
+                ("red ", red),
+                (
+                    "link",
+                    TextStyle {
+                        underline: true,
+                        ..red
+                    }
+                ),
+                (" [b] ", base),
+                ("big", big),
+            ]
+        );
+        assert_eq!(rich.plain_text(), "a red link [b] big");
+    }
+
+    #[test]
+    fn colors_parse_with_alpha() {
+        let color = parse_color("#ff000080").unwrap();
+        assert_eq!(color.with_alpha(1.0), Color::RED);
+        assert!((color.a - 128.0 / 255.0).abs() < f32::EPSILON);
+        assert!(parse_color("#ggg").is_err());
+        assert!(parse_color("#ff00").is_err());
+    }
+
+    #[test]
+    fn bad_markup_fails() {
+        let parse = |markup| RichText::parse(markup, TextStyle::default(), &HashMap::new());
+        assert!(parse("[color=#fff]unclosed").is_err());
+        assert!(parse("[u][color=#fff]crossed[/u][/color]").is_err());
+        assert!(parse("[icon=missing]").is_err());
+        assert!(parse("[bold]").is_err());
+        assert!(parse("[scale=0]x[/scale]").is_err());
+        assert!(parse("[u").is_err());
+    }
+
+    #[test]
+    fn fonts_past_the_end_fail() {
+        let rich =
+            RichText::parse("a [font=2]b[/font]", TextStyle::default(), &HashMap::new()).unwrap();
+        let runs = rich.runs(3).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[1].text, 2..3);
+        assert!(matches!(
+            rich.runs(2),
+            Err(QuicksilverError::FontError(FontError::InvalidMarkup(_)))
+        ));
+    }
+}
@@ -0,0 +1,424 @@
+use crate::geom::Vector;
+use crate::graphics::DistanceField;
+
+use elefont::rusttype_provider::SizedFont;
+use elefont::{Bounds, CacheError, FontProvider, Glyph, Metrics, PixelType};
+use rusttype::{GlyphId, Scale, Segment};
+
+// Renders glyphs as distance fields instead of coverage, padded by the field's range
+pub(crate) struct DistanceFieldFont {
+    font: SizedFont<'static>,
+    field: DistanceField,
+}
+
+impl DistanceFieldFont {
+    pub fn new(font: SizedFont<'static>, field: DistanceField) -> DistanceFieldFont {
+        DistanceFieldFont { font, field }
+    }
+
+    fn padding(&self) -> i32 {
+        self.field.range().ceil() as i32
+    }
+
+    // The edges of a glyph's outline, with the y axis pointing down like the rest of the
+    // glyph metrics
+    fn edges(&self, glyph: Glyph) -> Vec<Vec<Segment>> {
+        let scaled = self
+            .font
+            .font()
+            .glyph(GlyphId(glyph.0))
+            .scaled(Scale::uniform(self.font.size()));
+
+        scaled
+            .shape()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|contour| contour.segments)
+            .collect()
+    }
+}
+
+impl FontProvider for DistanceFieldFont {
+    fn pixel_type(&self) -> PixelType {
+        match self.field {
+            DistanceField::Sdf { .. } => PixelType::Alpha,
+            DistanceField::Msdf { .. } => PixelType::RGBA,
+        }
+    }
+
+    fn single_glyph(&self, character: char) -> Glyph {
+        self.font.single_glyph(character)
+    }
+
+    fn glyphs(&self, string: &str, glyphs: &mut Vec<Glyph>) {
+        self.font.glyphs(string, glyphs)
+    }
+
+    fn line_height(&self) -> f32 {
+        self.font.line_height()
+    }
+
+    fn metrics(&self, glyph: Glyph) -> Metrics {
+        let mut metrics = self.font.metrics(glyph);
+        let padding = self.padding();
+        metrics.bounds = metrics.bounds.map(|bounds| Bounds {
+            x: bounds.x - padding,
+            y: bounds.y - padding,
+            width: bounds.width + padding as u32 * 2,
+            height: bounds.height + padding as u32 * 2,
+        });
+
+        metrics
+    }
+
+    fn rasterize(&self, glyph: Glyph) -> Result<Vec<u8>, CacheError> {
+        let bounds = self
+            .metrics(glyph)
+            .bounds
+            .ok_or(CacheError::NonRenderableGlyph(glyph))?;
+        let contours = self
+            .edges(glyph)
+            .iter()
+            .map(|segments| flatten(segments))
+            .collect::<Vec<_>>();
+
+        Ok(render_field(&contours, bounds, self.field))
+    }
+
+    fn kerning(&self, a: Glyph, b: Glyph) -> f32 {
+        self.font.kerning(a, b)
+    }
+}
+
+const RED: u8 = 1;
+const GREEN: u8 = 2;
+const BLUE: u8 = 4;
+const WHITE: u8 = RED | GREEN | BLUE;
+const CYCLE: [u8; 3] = [GREEN | BLUE, RED | BLUE, RED | GREEN];
+
+// A straight piece of an outline, and which channels of a multi-channel field it counts for
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Edge {
+    a: Vector,
+    b: Vector,
+    color: u8,
+}
+
+// A piece of a flattened contour, and if the outline has a corner where it starts
+struct Piece {
+    a: Vector,
+    b: Vector,
+    corner: bool,
+}
+
+// Turn a contour into straight pieces with the y axis flipped, marking the corners
+fn flatten(segments: &[Segment]) -> Vec<Piece> {
+    let point = |p: rusttype::Point<f32>| Vector::new(p.x, -p.y);
+    // The direction the outline leaves and enters each segment
+    let tangents = |segment: &Segment| match segment {
+        Segment::Line(line) => {
+            let direction = point(line.p[1]) - point(line.p[0]);
+            (direction, direction)
+        }
+        Segment::Curve(curve) => {
+            let [start, control, end] = [point(curve.p[0]), point(curve.p[1]), point(curve.p[2])];
+            let fallback = end - start;
+            let leaving = if control == start {
+                fallback
+            } else {
+                control - start
+            };
+            let entering = if control == end {
+                fallback
+            } else {
+                end - control
+            };
+            (leaving, entering)
+        }
+    };
+    let mut pieces = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let previous = &segments[(i + segments.len() - 1) % segments.len()];
+        let corner = is_corner(tangents(previous).1, tangents(segment).0);
+        match segment {
+            Segment::Line(line) => pieces.push(Piece {
+                a: point(line.p[0]),
+                b: point(line.p[1]),
+                corner,
+            }),
+            Segment::Curve(curve) => {
+                let [start, control, end] =
+                    [point(curve.p[0]), point(curve.p[1]), point(curve.p[2])];
+                let length = (control - start).len() + (end - control).len();
+                let steps = (length / 2.0).ceil().clamp(2.0, 16.0) as u32;
+                let at = |t: f32| {
+                    start * ((1.0 - t) * (1.0 - t))
+                        + control * (2.0 * t * (1.0 - t))
+                        + end * (t * t)
+                };
+                for step in 0..steps {
+                    pieces.push(Piece {
+                        a: at(step as f32 / steps as f32),
+                        b: at((step + 1) as f32 / steps as f32),
+                        corner: corner && step == 0,
+                    });
+                }
+            }
+        }
+    }
+    pieces.retain(|piece| piece.a != piece.b);
+
+    pieces
+}
+
+fn is_corner(entering: Vector, leaving: Vector) -> bool {
+    if entering.len2() <= 0.0 || leaving.len2() <= 0.0 {
+        return false;
+    }
+    let (entering, leaving) = (entering.normalize(), leaving.normalize());
+    // Anything sharper than about 8 degrees
+    entering.dot(leaving) <= 0.0 || entering.cross(leaving).abs() > 0.14
+}
+
+// Color the edges of a contour so the two edges at each corner share exactly one channel
+fn color_edges(pieces: &[Piece]) -> Vec<Edge> {
+    let corners: Vec<usize> = (0..pieces.len()).filter(|i| pieces[*i].corner).collect();
+    let edge = |piece: &Piece, color| Edge {
+        a: piece.a,
+        b: piece.b,
+        color,
+    };
+    let start = match corners.first() {
+        // A smooth contour doesn't need separate channels
+        None => return pieces.iter().map(|piece| edge(piece, WHITE)).collect(),
+        Some(start) => *start,
+    };
+    let count = pieces.len();
+    let rotated = (0..count).map(|i| &pieces[(start + i) % count]);
+    if corners.len() == 1 {
+        // A teardrop is split into thirds, so the corner still has two colors
+        return rotated
+            .enumerate()
+            .map(|(i, piece)| {
+                let third = (i * 3 / count).min(2);
+                edge(piece, [CYCLE[1], WHITE, CYCLE[2]][third])
+            })
+            .collect();
+    }
+    let splines = corners.len();
+    let mut spline = 0;
+    rotated
+        .enumerate()
+        .map(|(i, piece)| {
+            if i > 0 && piece.corner {
+                spline += 1;
+            }
+            // The last spline also meets the first, so it can't share its color
+            let color = if spline == splines - 1 && splines % 3 == 1 {
+                CYCLE[1]
+            } else {
+                CYCLE[spline % 3]
+            };
+            edge(piece, color)
+        })
+        .collect()
+}
+
+// The closest point on an edge, how far along it is, and how square on to the edge the point is
+fn nearest(edge: &Edge, point: Vector) -> (f32, f32, f32) {
+    let direction = edge.b - edge.a;
+    let t = ((point - edge.a).dot(direction) / direction.len2()).clamp(0.0, 1.0);
+    let offset = point - (edge.a + direction * t);
+    let distance = offset.len();
+    let orthogonality = if distance > 0.0 {
+        direction.normalize().cross(offset / distance).abs()
+    } else {
+        1.0
+    };
+
+    (distance, t, orthogonality)
+}
+
+// The distance to the line an edge is on, positive on the inside
+fn signed_pseudo_distance(edge: &Edge, point: Vector, t: f32, distance: f32, winding: f32) -> f32 {
+    let direction = edge.b - edge.a;
+    let side = direction.cross(point - edge.a).signum() * winding;
+    // Past the ends of an edge, extend it in a straight line to keep corners sharp
+    if t <= 0.0 || t >= 1.0 {
+        let perpendicular = direction.normalize().cross(point - edge.a).abs();
+        side * perpendicular.min(distance)
+    } else {
+        side * distance
+    }
+}
+
+// If a point is inside the outline, by the nonzero winding rule
+fn inside(edges: &[Edge], point: Vector) -> bool {
+    let winding: i32 = edges
+        .iter()
+        .map(|edge| {
+            let (a, b) = (edge.a, edge.b);
+            if (a.y <= point.y) == (b.y <= point.y) {
+                return 0;
+            }
+            let x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+            match (x > point.x, b.y > a.y) {
+                (true, true) => 1,
+                (true, false) => -1,
+                _ => 0,
+            }
+        })
+        .sum();
+
+    winding != 0
+}
+
+// Render a distance field of the outline over the given pixel bounds
+fn render_field(contours: &[Vec<Piece>], bounds: Bounds, field: DistanceField) -> Vec<u8> {
+    let edges: Vec<Edge> = contours
+        .iter()
+        .flat_map(|pieces| color_edges(pieces))
+        .collect();
+    // Outer contours and holes wind opposite ways, and the area says which way the outer ones go
+    let area: f32 = edges.iter().map(|edge| edge.a.cross(edge.b)).sum();
+    let winding = if area < 0.0 { -1.0 } else { 1.0 };
+    let range = field.range();
+    let encode = |distance: f32| ((0.5 + distance / (range * 2.0)).clamp(0.0, 1.0) * 255.0) as u8;
+
+    let mut pixels = Vec::new();
+    for y in 0..bounds.height {
+        for x in 0..bounds.width {
+            let point = Vector::new(
+                bounds.x as f32 + x as f32 + 0.5,
+                bounds.y as f32 + y as f32 + 0.5,
+            );
+            let sign = if inside(&edges, point) { 1.0 } else { -1.0 };
+            let distance = edges
+                .iter()
+                .map(|edge| nearest(edge, point).0)
+                .fold(range * 2.0, f32::min);
+            let true_distance = encode(sign * distance);
+            match field {
+                DistanceField::Sdf { .. } => pixels.push(true_distance),
+                DistanceField::Msdf { .. } => {
+                    let mut channels = [RED, GREEN, BLUE].iter().map(|channel| {
+                        let closest = edges
+                            .iter()
+                            .filter(|edge| edge.color & channel != 0)
+                            .map(|edge| (edge, nearest(edge, point)))
+                            .fold(None, |closest: Option<(&Edge, (f32, f32, f32))>, next| {
+                                match closest {
+                                    Some(current) if !closer(next.1, current.1) => Some(current),
+                                    _ => Some(next),
+                                }
+                            });
+                        match closest {
+                            Some((edge, (distance, t, _))) => {
+                                encode(signed_pseudo_distance(edge, point, t, distance, winding))
+                            }
+                            None => true_distance,
+                        }
+                    });
+                    let (r, g, b) = (
+                        channels.next().unwrap_or(true_distance),
+                        channels.next().unwrap_or(true_distance),
+                        channels.next().unwrap_or(true_distance),
+                    );
+                    // Where the channels disagree with the true distance, fall back to it
+                    let median = r.max(g).min(r.min(g).max(b));
+                    if (median >= 128) == (true_distance >= 128) {
+                        pixels.extend_from_slice(&[r, g, b, true_distance]);
+                    } else {
+                        pixels.extend_from_slice(&[true_distance; 4]);
+                    }
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+// If one nearest point is closer than another, preferring edges the point is square on to
+fn closer(a: (f32, f32, f32), b: (f32, f32, f32)) -> bool {
+    if (a.0 - b.0).abs() <= 1e-4 {
+        a.2 > b.2
+    } else {
+        a.0 < b.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> Vec<Piece> {
+        let corners = [
+            Vector::new(2.0, 2.0),
+            Vector::new(8.0, 2.0),
+            Vector::new(8.0, 8.0),
+            Vector::new(2.0, 8.0),
+        ];
+        (0..4)
+            .map(|i| Piece {
+                a: corners[i],
+                b: corners[(i + 1) % 4],
+                corner: true,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn corners_get_different_colors() {
+        let edges = color_edges(&square());
+        for i in 0..edges.len() {
+            let (a, b) = (edges[i].color, edges[(i + 1) % edges.len()].color);
+            assert_ne!(a, b);
+            // Neighbors share exactly one channel
+            assert_eq!((a & b).count_ones(), 1);
+        }
+        let smooth: Vec<Piece> = square()
+            .into_iter()
+            .map(|piece| Piece {
+                corner: false,
+                ..piece
+            })
+            .collect();
+        assert!(color_edges(&smooth).iter().all(|edge| edge.color == WHITE));
+    }
+
+    #[test]
+    fn fields_are_positive_inside() {
+        let bounds = Bounds {
+            x: 0,
+            y: 0,
+            width: 10,
+            height: 10,
+        };
+        let sdf = render_field(&[square()], bounds, DistanceField::Sdf { range: 2.0 });
+        assert_eq!(sdf.len(), 100);
+        // The middle is more than the range inside, and the corner is more than the range outside
+        assert_eq!(sdf[5 * 10 + 5], 255);
+        assert_eq!(sdf[0], 0);
+        // Half a pixel inside the left edge
+        assert!(sdf[5 * 10 + 2] > 128);
+        assert!(sdf[5 * 10 + 1] < 128);
+
+        let msdf = render_field(&[square()], bounds, DistanceField::Msdf { range: 2.0 });
+        assert_eq!(msdf.len(), 400);
+        for (i, pixel) in msdf.chunks(4).enumerate() {
+            let median = pixel[0]
+                .max(pixel[1])
+                .min(pixel[0].min(pixel[1]).max(pixel[2]));
+            assert_eq!(median >= 128, sdf[i] >= 128, "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn winding_finds_holes() {
+        let edges = color_edges(&square());
+        assert!(inside(&edges, Vector::new(5.0, 5.0)));
+        assert!(!inside(&edges, Vector::new(1.0, 5.0)));
+        assert!(!inside(&edges, Vector::new(9.0, 9.0)));
+    }
+}
@@ -0,0 +1,178 @@
+// Shaping with the fonts' own tables, and the order of right-to-left text
+//
+// Text is shaped by rustybuzz, which reads a font's substitution and positioning tables to join
+// Arabic letters, move Indic vowel signs and form conjuncts and ligatures. Embedding levels come
+// from the Unicode bidirectional algorithm (UAX #9), and each line is reordered with them once
+// it's been broken.
+use super::lines::PendingLine;
+use crate::geom::Vector;
+
+use rustybuzz::{Direction, Face, UnicodeBuffer};
+use std::mem;
+use std::sync::Arc;
+use unicode_bidi::BidiInfo;
+
+// A font file, and how many pixels a font unit is at the size it's rendered at
+#[derive(Clone)]
+pub(crate) struct FontFile {
+    data: Arc<[u8]>,
+    scale: f32,
+}
+
+impl FontFile {
+    // Fonts that rustybuzz can't read aren't shaped
+    pub fn new(data: Arc<[u8]>, scale: f32) -> Option<FontFile> {
+        Face::from_slice(&data, 0)?;
+
+        Some(FontFile { data, scale })
+    }
+}
+
+// A glyph chosen and positioned by shaping
+pub(crate) struct ShapedGlyph {
+    // The glyph's index in the font
+    pub glyph: u32,
+    // The byte index of the first character the glyph was made from
+    pub cluster: usize,
+    pub advance: f32,
+    pub offset: Vector,
+}
+
+// Shape text that's all in one direction, returning its glyphs in the order they're drawn
+pub(crate) fn shape(file: &FontFile, text: &str, rtl: bool) -> Vec<ShapedGlyph> {
+    let face = Face::from_slice(&file.data, 0).expect("The font was read when it was loaded");
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    buffer.set_direction(if rtl {
+        Direction::RightToLeft
+    } else {
+        Direction::LeftToRight
+    });
+    let output = rustybuzz::shape(&face, &[], buffer);
+
+    output
+        .glyph_infos()
+        .iter()
+        .zip(output.glyph_positions())
+        .map(|(info, position)| ShapedGlyph {
+            glyph: info.glyph_id,
+            cluster: info.cluster as usize,
+            advance: position.x_advance as f32 * file.scale,
+            // Font units go up, and the screen goes down
+            offset: Vector::new(position.x_offset as f32, -position.y_offset as f32) * file.scale,
+        })
+        .collect()
+}
+
+// The embedding level of each byte of a paragraph, which is odd for right-to-left text
+pub(crate) fn levels(paragraph: &str) -> Vec<u8> {
+    BidiInfo::new(paragraph, None)
+        .levels
+        .iter()
+        .map(|level| level.number())
+        .collect()
+}
+
+// Put the glyphs of a line in the order they're drawn, reversing right-to-left runs (L2)
+//
+// Glyphs from the same grapheme cluster move together, and the space after a right-to-left
+// cluster moves to its left. Reordered lines aren't justified.
+pub(crate) fn reorder_line(line: &mut PendingLine) {
+    let highest = line
+        .glyphs
+        .iter()
+        .map(|glyph| glyph.level)
+        .max()
+        .unwrap_or(0);
+    if highest == 0 {
+        return;
+    }
+    let mut clusters: Vec<(usize, usize)> = Vec::new();
+    for (i, glyph) in line.glyphs.iter().enumerate() {
+        match clusters.last_mut() {
+            Some(cluster) if line.glyphs[cluster.0].index == glyph.index => cluster.1 = i + 1,
+            _ => clusters.push((i, i + 1)),
+        }
+    }
+    let levels: Vec<u8> = clusters
+        .iter()
+        .map(|(start, _)| line.glyphs[*start].level)
+        .collect();
+    let lowest_odd = levels.iter().copied().min().unwrap_or(0) | 1;
+    let mut order: Vec<usize> = (0..clusters.len()).collect();
+    for level in (lowest_odd..=highest).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order[start..i].reverse();
+            i += 1;
+        }
+    }
+
+    // Place the clusters one after another, in their new order
+    let mut positions = vec![0.0; line.glyphs.len()];
+    let mut ranks = vec![0; line.glyphs.len()];
+    let mut x = line.glyphs[0].x;
+    for (rank, cluster) in order.into_iter().enumerate() {
+        let (start, end) = clusters[cluster];
+        let glyphs = &line.glyphs[start..end];
+        let first = glyphs[0].x;
+        let cell_end = line.glyphs.get(end).map_or(line.width, |next| next.x);
+        let last = &glyphs[glyphs.len() - 1];
+        let shift = match levels[cluster] % 2 {
+            1 => (cell_end - last.x - last.advance).max(0.0),
+            _ => 0.0,
+        };
+        for (i, glyph) in (start..end).zip(glyphs) {
+            positions[i] = x + shift + glyph.x - first;
+            ranks[i] = rank;
+        }
+        x += cell_end - first;
+    }
+    let mut glyphs: Vec<_> = mem::take(&mut line.glyphs)
+        .into_iter()
+        .zip(positions.into_iter().zip(ranks))
+        .map(|(mut glyph, (x, rank))| {
+            glyph.x = x;
+            glyph.gap = 0;
+            (rank, glyph)
+        })
+        .collect();
+    glyphs.sort_by_key(|(rank, _)| *rank);
+    line.glyphs = glyphs.into_iter().map(|(_, glyph)| glyph).collect();
+    line.words = line.words.min(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_follow_the_text_direction() {
+        assert_eq!(levels("ab"), [0, 0]);
+        // Hebrew letters are two bytes each
+        assert_eq!(levels("a \u{5D0}\u{5D1}"), [0, 0, 1, 1, 1, 1]);
+        // A right-to-left paragraph, with a number that reads left-to-right
+        assert_eq!(levels("\u{5D0} 1,5 \u{5D1}"), [1, 1, 1, 2, 2, 2, 1, 1, 1]);
+        assert_eq!(levels("\u{5D0} b."), [1, 1, 1, 2, 1]);
+    }
+
+    #[test]
+    fn right_to_left_glyphs_come_out_in_drawing_order() {
+        let data: Arc<[u8]> = include_bytes!("../../../static/font.ttf").to_vec().into();
+        let file = FontFile::new(data, 1.0).unwrap();
+        let clusters = |rtl| -> Vec<usize> {
+            shape(&file, "abc", rtl)
+                .iter()
+                .map(|glyph| glyph.cluster)
+                .collect()
+        };
+        assert_eq!(clusters(false), [0, 1, 2]);
+        assert_eq!(clusters(true), [2, 1, 0]);
+        assert!(shape(&file, "a", false)[0].advance > 0.0);
+    }
+}
@@ -0,0 +1,206 @@
+// Where text may be broken into lines (UAX #14), and which characters are drawn and selected
+// together as grapheme clusters (UAX #29)
+use std::ops::Range;
+use unicode_linebreak::{break_property, linebreaks, BreakClass};
+use unicode_segmentation::UnicodeSegmentation;
+
+// If the character ends a paragraph, like a newline
+pub(crate) fn is_mandatory_break(c: char) -> bool {
+    matches!(
+        break_property(c as u32),
+        BreakClass::Mandatory
+            | BreakClass::CarriageReturn
+            | BreakClass::LineFeed
+            | BreakClass::NextLine
+    )
+}
+
+// If the character is a formatting character that isn't drawn, like a zero width space or joiner
+pub(crate) fn is_invisible(c: char) -> bool {
+    matches!(c,
+        '\u{AD}'
+        | '\u{34F}'
+        | '\u{61C}'
+        | '\u{180B}'..='\u{180F}'
+        | '\u{200B}'..='\u{200F}'
+        | '\u{202A}'..='\u{202E}'
+        | '\u{2060}'..='\u{206F}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FEFF}'
+        | '\u{E0000}'..='\u{E0FFF}'
+    )
+}
+
+// If the character is a CJK ideograph, kana or Hangul syllable, which lines may break between
+pub(crate) fn is_ideographic(c: char) -> bool {
+    matches!(
+        break_property(c as u32),
+        BreakClass::Ideographic
+            | BreakClass::ConditionalJapaneseStarter
+            | BreakClass::HangulLvSyllable
+            | BreakClass::HangulLvtSyllable
+    )
+}
+
+// The paragraphs of the text, split at mandatory breaks like newlines
+//
+// The ranges leave out the breaks themselves, and a `"\r\n"` counts as a single break.
+pub(crate) fn paragraphs(text: &str) -> Vec<Range<usize>> {
+    let mut paragraphs = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if !is_mandatory_break(c) {
+            continue;
+        }
+        if c == '\r' {
+            chars.next_if(|(_, next)| *next == '\n');
+        }
+        paragraphs.push(start..i);
+        start = chars.peek().map_or(text.len(), |(next, _)| *next);
+    }
+    paragraphs.push(start..text.len());
+
+    paragraphs
+}
+
+// The byte indices in a paragraph where a new line may start, besides the start itself
+//
+// Spaces stay at the end of the line before a break, rather than starting the next line. Thai,
+// Lao, Khmer and Myanmar are written without spaces between words, and finding the words takes a
+// dictionary, so lines may break between any two of their grapheme clusters instead, except after
+// a vowel that's written before its consonant.
+pub(crate) fn line_breaks(text: &str) -> Vec<usize> {
+    let complex = |c: char| break_property(c as u32) == BreakClass::ComplexContext;
+    let leading_vowel = |c: char| matches!(c, '\u{E40}'..='\u{E44}' | '\u{EC0}'..='\u{EC4}');
+    let mut breaks: Vec<usize> = linebreaks(text)
+        .map(|(i, _)| i)
+        .filter(|i| *i < text.len())
+        .collect();
+    let clusters = clusters(text);
+    for pair in clusters.windows(2) {
+        let before = text[pair[0].clone()].chars().next_back();
+        let after = text[pair[1].clone()].chars().next();
+        if let (Some(before), Some(after)) = (before, after) {
+            if complex(before) && complex(after) && !leading_vowel(before) {
+                breaks.push(pair[1].start);
+            }
+        }
+    }
+    breaks.sort_unstable();
+    breaks.dedup();
+
+    breaks
+}
+
+// Split a segment between line breaks into its word and the spaces after it, which don't take up
+// room at the end of a line
+//
+// Any space a line may break after counts, like a tab or an ideographic space, but no-break spaces
+// are part of the word.
+pub(crate) fn trim_spaces(segment: &str) -> (&str, &str) {
+    let is_breaking_space =
+        |c: char| c.is_whitespace() && break_property(c as u32) != BreakClass::NonBreakingGlue;
+    let word = segment.trim_end_matches(is_breaking_space);
+
+    (word, &segment[word.len()..])
+}
+
+// The byte ranges of the grapheme clusters of the text: the characters that are drawn and
+// selected as one, like a letter and its accents, a conjunct or a flag
+pub(crate) fn clusters(text: &str) -> Vec<Range<usize>> {
+    text.grapheme_indices(true)
+        .map(|(i, cluster)| i..i + cluster.len())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<&str> {
+        let mut starts = vec![0];
+        starts.extend(line_breaks(text));
+        starts.push(text.len());
+        starts.windows(2).map(|w| &text[w[0]..w[1]]).collect()
+    }
+
+    #[test]
+    fn paragraphs_split_at_mandatory_breaks() {
+        let text = "one\ntwo\r\nthree\u{2029}\rfour";
+        let paragraphs: Vec<&str> = paragraphs(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect();
+        assert_eq!(paragraphs, ["one", "two", "three", "", "four"]);
+    }
+
+    #[test]
+    fn lines_break_after_spaces_and_hyphens() {
+        assert_eq!(lines("the quick  fox"), ["the ", "quick  ", "fox"]);
+        assert_eq!(lines("well-known -5"), ["well-", "known ", "-5"]);
+        assert_eq!(
+            lines("(in parentheses), or \"quotes\"!"),
+            ["(in ", "parentheses), ", "or ", "\"quotes\"!"]
+        );
+        assert_eq!(lines("$9.99 100%"), ["$9.99 ", "100%"]);
+    }
+
+    #[test]
+    fn segments_end_in_any_breaking_space() {
+        let words: Vec<(&str, &str)> = lines("one\ttwo\u{3000}three\u{2003}four 10\u{A0}")
+            .into_iter()
+            .map(trim_spaces)
+            .collect();
+        assert_eq!(
+            words,
+            [
+                ("one", "\t"),
+                ("two", "\u{3000}"),
+                ("three", "\u{2003}"),
+                ("four", " "),
+                ("10\u{A0}", "")
+            ]
+        );
+    }
+
+    #[test]
+    fn no_break_spaces_glue_words() {
+        assert_eq!(lines("10\u{A0}km away"), ["10\u{A0}km ", "away"]);
+        assert_eq!(lines("a\u{2060}b c"), ["a\u{2060}b ", "c"]);
+        assert_eq!(lines("a\u{200B}b"), ["a\u{200B}", "b"]);
+    }
+
+    #[test]
+    fn ideographs_break_between_characters() {
+        assert_eq!(lines("日本語です。"), ["日", "本", "語", "で", "す。"]);
+        // Small kana and the long vowel mark don't start lines
+        assert_eq!(lines("チョコレート"), ["チョ", "コ", "レー", "ト"]);
+        assert_eq!(lines("「引用」と"), ["「引", "用」", "と"]);
+    }
+
+    #[test]
+    fn marks_stay_with_their_letters() {
+        assert_eq!(lines("e\u{301}te\u{301} x"), ["e\u{301}te\u{301} ", "x"]);
+        assert_eq!(lines("नमस्ते दुनिया"), ["नमस्ते ", "दुनिया"]);
+    }
+
+    #[test]
+    fn scripts_without_spaces_break_between_clusters() {
+        assert_eq!(lines("ไทย ok"), ["ไท", "ย ", "ok"]);
+        assert_eq!(lines("ភាសា"), ["ភា", "សា"]);
+    }
+
+    #[test]
+    fn clusters_keep_characters_together() {
+        let text = "e\u{301}a\r\nक्षि🇫🇷🇩🇪👩\u{200D}💻";
+        let clusters: Vec<&str> = clusters(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect();
+        assert_eq!(
+            clusters,
+            ["e\u{301}", "a", "\r\n", "क्षि", "🇫🇷", "🇩🇪", "👩\u{200D}💻"]
+        );
+    }
+}
@@ -1,5 +1,5 @@
-use super::{ColorFormat, Graphics, PixelFormat};
-use crate::geom::Vector;
+use super::{ColorFormat, Graphics, PixelFormat, Pixmap};
+use crate::geom::{Rectangle, Transform, Vector};
 use crate::QuicksilverError;
 
 use std::cell::{Ref, RefCell};
@@ -12,11 +12,42 @@ use golem::*;
 ///
 /// See [`Graphics::draw_image`] to draw it
 #[derive(Clone)]
-pub struct Image(pub(crate) Rc<RefCell<Texture>>);
+pub struct Image(pub(crate) Rc<RefCell<ImageData>>);
+
+pub(crate) struct ImageData {
+    texture: Texture,
+    // The size of the image data, if the texture was padded to a power of 2 for mipmaps
+    padded_from: Option<(u32, u32)>,
+    has_alpha: HasAlpha,
+}
+
+// Whether the texture is RGBA or RGB, since PixelFormat can't be copied
+#[derive(Clone, Copy)]
+pub(crate) struct HasAlpha(pub(crate) bool);
+
+impl HasAlpha {
+    pub(crate) fn of(format: &PixelFormat) -> HasAlpha {
+        HasAlpha(match format {
+            PixelFormat::RGB => false,
+            PixelFormat::RGBA => true,
+        })
+    }
+
+    pub(crate) fn format(self) -> PixelFormat {
+        match self {
+            HasAlpha(true) => PixelFormat::RGBA,
+            HasAlpha(false) => PixelFormat::RGB,
+        }
+    }
+}
 
 impl Image {
-    pub(crate) fn new(texture: Texture) -> Image {
-        Image(Rc::new(RefCell::new(texture)))
+    pub(crate) fn new(texture: Texture, has_alpha: HasAlpha) -> Image {
+        Image(Rc::new(RefCell::new(ImageData {
+            texture,
+            padded_from: None,
+            has_alpha,
+        })))
     }
 
     /// Create an image with a given width and height
@@ -30,15 +61,48 @@ impl Image {
         height: u32,
         format: PixelFormat,
     ) -> Result<Image, GolemError> {
+        let has_alpha = HasAlpha::of(&format);
         let mut texture = Texture::new(&gfx.ctx)?;
         texture.set_image(data, width, height, format);
 
-        Ok(Image::new(texture))
+        Ok(Image::new(texture, has_alpha))
+    }
+
+    /// Upload the contents of a [`Pixmap`] to a new image on the GPU
+    pub fn from_pixmap(gfx: &Graphics, pixmap: &Pixmap) -> Result<Image, QuicksilverError> {
+        Ok(Image::from_raw(
+            gfx,
+            Some(pixmap.data()),
+            pixmap.width(),
+            pixmap.height(),
+            PixelFormat::RGBA,
+        )?)
+    }
+
+    /// Upload the contents of a [`Pixmap`] with mipmaps, and use `min` as the minification filter
+    ///
+    /// See [`Image::generate_mipmaps`] for how images that aren't a power-of-2 size are handled.
+    pub fn from_pixmap_mipmapped(
+        gfx: &Graphics,
+        pixmap: &Pixmap,
+        min: TextureFilter,
+    ) -> Result<Image, QuicksilverError> {
+        let mut image = Image::from_raw(gfx, None, 1, 1, PixelFormat::RGBA)?;
+        image.set_data_mipmapped(
+            pixmap.data(),
+            pixmap.width(),
+            pixmap.height(),
+            ColorFormat::RGBA,
+        );
+        image.set_minification(min)?;
+
+        Ok(image)
     }
 
     /// Create an image from an encoded image format
     ///
-    /// JPEG and PNG are supported
+    /// JPEG and PNG are supported, as are BMP, GIF, TGA and WebP with the features of the same
+    /// name enabled. For animated images, see [`Animation`](crate::graphics::Animation)
     pub fn from_encoded_bytes(gfx: &Graphics, raw: &[u8]) -> Result<Image, QuicksilverError> {
         let img = image::load_from_memory(raw)?.to_rgba();
         let width = img.width();
@@ -54,18 +118,64 @@ impl Image {
 
     /// Load an image from a file at the given path
     ///
-    /// JPEG and PNG file formats are supported
+    /// JPEG and PNG file formats are supported, as are BMP, GIF, TGA and WebP with the features
+    /// of the same name enabled
     pub async fn load(gfx: &Graphics, path: impl AsRef<Path>) -> Result<Image, QuicksilverError> {
         let file_contents = platter::load_file(path).await?;
         Image::from_encoded_bytes(gfx, file_contents.as_slice())
     }
 
     /// Replace the backing data for the image, or create a blank image
+    ///
+    /// Images with a power-of-2 width and height get mipmaps, but others don't. To always
+    /// generate mipmaps, use [`Image::set_data_mipmapped`].
     pub fn set_data(&mut self, data: Option<&[u8]>, width: u32, height: u32, color: ColorFormat) {
-        self.0.borrow_mut().set_image(data, width, height, color);
+        let mut image = self.0.borrow_mut();
+        image.has_alpha = HasAlpha::of(&color);
+        image.texture.set_image(data, width, height, color);
+        image.padded_from = None;
+    }
+
+    /// Replace the backing data for the image, generating mipmaps for it
+    ///
+    /// See [`Image::generate_mipmaps`] for how images that aren't a power-of-2 size are handled.
+    pub fn set_data_mipmapped(&mut self, data: &[u8], width: u32, height: u32, color: ColorFormat) {
+        let (padded_width, padded_height) = (width.next_power_of_two(), height.next_power_of_two());
+        let mut image = self.0.borrow_mut();
+        image.has_alpha = HasAlpha::of(&color);
+        if (padded_width, padded_height) == (width, height) {
+            image.texture.set_image(Some(data), width, height, color);
+            image.padded_from = None;
+        } else {
+            let padded = extrude(
+                data,
+                width,
+                height,
+                padded_width,
+                padded_height,
+                color.bytes_per_pixel(),
+            );
+            let padded = Some(padded.as_slice());
+            image
+                .texture
+                .set_image(padded, padded_width, padded_height, color);
+            // Repeating wrap modes would sample the padding, so only clamping is allowed
+            let clamp = "Every texture can clamp to the edge";
+            image
+                .texture
+                .set_wrap_h(TextureWrap::ClampToEdge)
+                .expect(clamp);
+            image
+                .texture
+                .set_wrap_v(TextureWrap::ClampToEdge)
+                .expect(clamp);
+            image.padded_from = Some((width, height));
+        }
     }
 
     /// Set the data for some region of this image, without clearing it
+    ///
+    /// If the image has mipmaps, they are regenerated to match the new data.
     pub fn set_sub_data(
         &self,
         data: &[u8],
@@ -75,11 +185,107 @@ impl Image {
         height: u32,
         color: ColorFormat,
     ) {
-        self.raw().set_subimage(data, x, y, width, height, color);
+        let image = self.0.borrow();
+        let texture = &image.texture;
+        match image.padded_from {
+            Some((image_width, image_height)) => {
+                assert!(
+                    x + width <= image_width && y + height <= image_height,
+                    "The region over-ran the bounds of the image"
+                );
+                // Keep the padding up to date with the edges of the image
+                let padded_width = if x + width == image_width {
+                    texture.width() - x
+                } else {
+                    width
+                };
+                let padded_height = if y + height == image_height {
+                    texture.height() - y
+                } else {
+                    height
+                };
+                let padded = extrude(
+                    data,
+                    width,
+                    height,
+                    padded_width,
+                    padded_height,
+                    color.bytes_per_pixel(),
+                );
+                texture.set_subimage(&padded, x, y, padded_width, padded_height, color);
+            }
+            None => texture.set_subimage(data, x, y, width, height, color),
+        }
+    }
+
+    /// Replace the region of this image starting at (x, y) with the contents of a [`Pixmap`]
+    pub fn set_sub_pixmap(&self, pixmap: &Pixmap, x: u32, y: u32) {
+        self.set_sub_data(
+            pixmap.data(),
+            x,
+            y,
+            pixmap.width(),
+            pixmap.height(),
+            ColorFormat::RGBA,
+        );
+    }
+
+    /// Read the contents of the image back from the GPU
+    ///
+    /// This is a slow operation, because it requires the GPU to finish drawing and copy the data
+    /// over. It's best suited to occasional tasks like saving screenshots.
+    pub fn to_pixels(&self, gfx: &mut Graphics) -> Result<Pixmap, QuicksilverError> {
+        let size = self.size();
+        gfx.render_offscreen(
+            size.x as u32,
+            size.y as u32,
+            Rectangle::new_sized(size),
+            Transform::IDENTITY,
+            |gfx| {
+                // Copy the pixels exactly, rather than blending them with the blank target
+                gfx.set_blend_mode(None);
+                gfx.draw_image(self, Rectangle::new_sized(size));
+            },
+        )
+    }
+
+    /// Generate mipmaps for this image, if it doesn't already have them
+    ///
+    /// The GPU can only generate mipmaps for images with a power-of-2 width and height. Other
+    /// images are padded up to the next power-of-2 size, repeating the pixels along their right
+    /// and bottom edges, but their size and coordinates stay the same. Because of the padding,
+    /// those images can only use [`TextureWrap::ClampToEdge`], and texture coordinates given
+    /// directly in a [`Vertex`](crate::graphics::Vertex) cover the padding as well.
+    ///
+    /// Mipmaps are kept up to date when the image is changed with [`Image::set_sub_data`].
+    pub fn generate_mipmaps(&self, gfx: &mut Graphics) -> Result<(), QuicksilverError> {
+        if self.has_mipmaps() {
+            return Ok(());
+        }
+        let pixels = self.to_pixels(gfx)?;
+        let mut image = self.clone();
+        image.set_data_mipmapped(
+            pixels.data(),
+            pixels.width(),
+            pixels.height(),
+            ColorFormat::RGBA,
+        );
+
+        Ok(())
+    }
+
+    /// If the image has mipmaps, and can use mipmap filters for minification
+    pub fn has_mipmaps(&self) -> bool {
+        let texture = self.raw();
+        texture.width().is_power_of_two() && texture.height().is_power_of_two()
     }
 
     pub(crate) fn raw(&self) -> Ref<Texture> {
-        self.0.borrow()
+        Ref::map(self.0.borrow(), |image| &image.texture)
+    }
+
+    pub(crate) fn is_unique(&self) -> bool {
+        Rc::strong_count(&self.0) == 1
     }
 
     pub(crate) fn ptr_eq(&self, other: &Image) -> bool {
@@ -88,15 +294,27 @@ impl Image {
 
     /// Get the size of the image
     pub fn size(&self) -> Vector {
+        let image = self.0.borrow();
+        let (width, height) = image
+            .padded_from
+            .unwrap_or_else(|| (image.texture.width(), image.texture.height()));
+
         Vector {
-            x: self.raw().width() as f32,
-            y: self.raw().height() as f32,
+            x: width as f32,
+            y: height as f32,
         }
     }
 
+    // The size of the texture on the GPU, which may be padded past the size of the image
+    pub(crate) fn texture_size(&self) -> Vector {
+        let texture = self.raw();
+        Vector::new(texture.width() as f32, texture.height() as f32)
+    }
+
     /// Determine how the texture should scale down
     ///
-    /// Only textures with a power-of-2 size support mipmaps, all others will return errors
+    /// Only images with mipmaps support mipmap filters, all others will return errors. See
+    /// [`Image::has_mipmaps`] and [`Image::generate_mipmaps`].
     pub fn set_minification(&self, min: TextureFilter) -> Result<(), QuicksilverError> {
         Ok(self.raw().set_minification(min)?)
     }
@@ -113,6 +331,7 @@ impl Image {
     /// Only textures with a power-of-2 size support texture wrapping, all others must ClampToEdge
     /// or will return an error
     pub fn set_wrap_h(&self, wrap: TextureWrap) -> Result<(), QuicksilverError> {
+        self.check_wrap(wrap)?;
         Ok(self.raw().set_wrap_h(wrap)?)
     }
 
@@ -121,10 +340,74 @@ impl Image {
     /// Only textures with a power-of-2 size support texture wrapping, all others must ClampToEdge
     /// or will return an error
     pub fn set_wrap_v(&self, wrap: TextureWrap) -> Result<(), QuicksilverError> {
+        self.check_wrap(wrap)?;
         Ok(self.raw().set_wrap_v(wrap)?)
     }
 
-    pub(crate) fn into_raw(self) -> Result<Texture, Rc<RefCell<Texture>>> {
-        Ok(Rc::try_unwrap(self.0)?.into_inner())
+    // Padded images would wrap around their padding rather than their data
+    fn check_wrap(&self, wrap: TextureWrap) -> Result<(), GolemError> {
+        if self.0.borrow().padded_from.is_some() && wrap != TextureWrap::ClampToEdge {
+            Err(GolemError::IllegalWrapOption)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The format of the image data on the GPU
+    pub fn format(&self) -> PixelFormat {
+        self.0.borrow().has_alpha.format()
+    }
+
+    pub(crate) fn into_raw(self) -> Result<(Texture, HasAlpha), Rc<RefCell<ImageData>>> {
+        let image = Rc::try_unwrap(self.0)?.into_inner();
+        Ok((image.texture, image.has_alpha))
+    }
+}
+
+// Grow a block of pixels to a larger size by repeating its right and bottom edges
+fn extrude(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    new_height: u32,
+    bytes_per_pixel: u32,
+) -> Vec<u8> {
+    let pixel = bytes_per_pixel as usize;
+    let row_len = width as usize * pixel;
+    let mut padded = Vec::with_capacity(new_width as usize * new_height as usize * pixel);
+    for y in 0..new_height.min(height) {
+        let start = y as usize * row_len;
+        let row = &data[start..start + row_len];
+        padded.extend_from_slice(row);
+        let last = &row[row_len - pixel..];
+        for _ in width..new_width {
+            padded.extend_from_slice(last);
+        }
+    }
+    let padded_row_len = new_width as usize * pixel;
+    let last_row = padded[padded.len() - padded_row_len..].to_vec();
+    for _ in height..new_height {
+        padded.extend_from_slice(&last_row);
+    }
+
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extrude_repeats_edges() {
+        let data = [1, 2, 3, 4];
+        let padded = extrude(&data, 2, 2, 4, 3, 1);
+        assert_eq!(padded, vec![1, 2, 2, 2, 3, 4, 4, 4, 3, 4, 4, 4]);
+    }
+
+    #[test]
+    fn extrude_same_size() {
+        let data = [1, 2, 3, 4, 5, 6];
+        assert_eq!(extrude(&data, 1, 2, 1, 2, 3), data.to_vec());
     }
 }
@@ -0,0 +1,418 @@
+use super::blend::{BlendChannel, BlendFactor, BlendFunction, BlendInput, BlendMode};
+use super::{circle_points, Color, Element, Graphics, Image, PixelFormat, Surface, Vertex};
+use crate::geom::{Circle, Line, Rectangle, Shape, Triangle, Vector};
+use crate::QuicksilverError;
+
+use std::f32::consts::PI;
+
+/// A source of light, drawn with [`Graphics::draw_lighting`]
+///
+/// Lights shine out from their `position` to their `radius`, fading out as they get further
+/// away, and are blocked by [`Occluder`]s. A light with a `spread` under 360 degrees is a spot
+/// light, which only shines in a cone around its `direction`.
+///
+/// [`Graphics::draw_lighting`]: crate::Graphics::draw_lighting
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    /// Where the light shines from
+    pub position: Vector,
+    /// The color of the light at its brightest
+    pub color: Color,
+    /// How far the light reaches
+    pub radius: f32,
+    /// How quickly the light fades out with distance
+    ///
+    /// The brightness at a distance is `(1 - distance / radius)` raised to this power, so 1 fades
+    /// out evenly and higher values fade out faster near the light.
+    pub falloff: f32,
+    /// The clockwise angle in degrees that a spot light points in
+    pub direction: f32,
+    /// How wide the cone of light is in degrees, which is 360 for a point light
+    pub spread: f32,
+    /// The radius of the light source itself
+    ///
+    /// Lights with a size of 0 cast hard shadows, and larger lights cast softer shadows.
+    pub size: f32,
+}
+
+impl Light {
+    /// Create a light that shines in every direction
+    pub fn point(position: Vector, radius: f32, color: Color) -> Light {
+        Light {
+            position,
+            color,
+            radius,
+            falloff: 1.0,
+            direction: 0.0,
+            spread: 360.0,
+            size: 0.0,
+        }
+    }
+
+    /// Create a light that shines in a cone `spread` degrees wide, centered on `direction`
+    pub fn spot(position: Vector, radius: f32, color: Color, direction: f32, spread: f32) -> Light {
+        Light {
+            direction,
+            spread,
+            ..Light::point(position, radius, color)
+        }
+    }
+
+    fn is_spot(&self) -> bool {
+        self.spread < 360.0
+    }
+
+    fn brightness(&self, distance: f32) -> f32 {
+        (1.0 - distance / self.radius).max(0.0).powf(self.falloff)
+    }
+}
+
+/// A polygon that blocks light and casts shadows
+///
+/// Occluders can be built from any list of points, or from the shapes in [`geom`]. Light stops at
+/// the edges of the occluder, so its inside is left unlit.
+///
+/// [`geom`]: crate::geom
+#[derive(Clone, Debug, PartialEq)]
+pub struct Occluder {
+    points: Vec<Vector>,
+}
+
+impl Occluder {
+    /// Create an occluder from the points of a polygon, in order
+    ///
+    /// Two points make a wall with no thickness.
+    pub fn new(points: Vec<Vector>) -> Occluder {
+        assert!(points.len() >= 2, "An occluder needs at least two points");
+        Occluder { points }
+    }
+
+    /// The points of the polygon
+    pub fn points(&self) -> &[Vector] {
+        &self.points
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Vector, Vector)> + '_ {
+        // A wall only has the one edge, rather than an edge there and back
+        let count = match self.points.len() {
+            2 => 1,
+            len => len,
+        };
+        (0..count).map(move |i| (self.points[i], self.points[(i + 1) % self.points.len()]))
+    }
+}
+
+impl From<Rectangle> for Occluder {
+    fn from(rect: Rectangle) -> Occluder {
+        let (pos, size) = (rect.pos, rect.size);
+        Occluder::new(vec![
+            pos,
+            pos + size.x_comp(),
+            pos + size,
+            pos + size.y_comp(),
+        ])
+    }
+}
+
+impl From<Circle> for Occluder {
+    fn from(circle: Circle) -> Occluder {
+        let points = circle_points::CIRCLE_POINTS
+            .iter()
+            .map(|point| circle.center() + *point * circle.radius)
+            .collect();
+
+        Occluder::new(points)
+    }
+}
+
+impl From<Triangle> for Occluder {
+    fn from(triangle: Triangle) -> Occluder {
+        Occluder::new(vec![triangle.a, triangle.b, triangle.c])
+    }
+}
+
+impl From<Line> for Occluder {
+    fn from(line: Line) -> Occluder {
+        Occluder::new(vec![line.a, line.b])
+    }
+}
+
+/// A render target that lights are accumulated into
+///
+/// See [`Graphics::draw_lighting`] to use it. Like a [`Mask`], the light map covers the camera
+/// area, and smaller light maps than the window are fine since light is usually smooth.
+///
+/// The light map holds onto its image until it's drawn, so it can only be used once per call to
+/// [`Graphics::flush_window`] or [`Graphics::present`].
+///
+/// [`Graphics::draw_lighting`]: crate::Graphics::draw_lighting
+/// [`Graphics::flush_window`]: crate::Graphics::flush_window
+/// [`Graphics::present`]: crate::Graphics::present
+/// [`Mask`]: crate::graphics::Mask
+pub struct LightMap {
+    pub(crate) surface: Surface,
+    pub(crate) image: Option<Image>,
+}
+
+impl LightMap {
+    /// Create a light map whose image is `width` by `height` pixels
+    pub fn new(gfx: &Graphics, width: u32, height: u32) -> Result<LightMap, QuicksilverError> {
+        let image = Image::from_raw(gfx, None, width, height, PixelFormat::RGBA)?;
+        let mut surface = Surface::new(gfx, image)?;
+        let image = surface.detach();
+
+        Ok(LightMap { surface, image })
+    }
+
+    // If the image isn't waiting to be drawn from the last use of the light map
+    pub(crate) fn is_ready(&self) -> bool {
+        match &self.image {
+            Some(image) => image.is_unique(),
+            None => false,
+        }
+    }
+}
+
+// Add each light on top of the ambient light
+pub(crate) fn additive_blend_mode() -> BlendMode {
+    BlendMode {
+        function: BlendFunction::Same {
+            source: BlendFactor::One,
+            destination: BlendFactor::One,
+        },
+        ..BlendMode::default()
+    }
+}
+
+// Multiply the scene by the light map
+pub(crate) fn multiply_blend_mode() -> BlendMode {
+    BlendMode {
+        function: BlendFunction::Same {
+            source: BlendFactor::Color {
+                input: BlendInput::Destination,
+                channel: BlendChannel::Color,
+                is_inverse: false,
+            },
+            destination: BlendFactor::Zero,
+        },
+        ..BlendMode::default()
+    }
+}
+
+// How many rings the light is split into, so it can fade out smoothly
+const BANDS: u32 = 8;
+// How many rays are cast around the edge of a point light
+const EDGE_RAYS: u32 = 64;
+// How many copies of a light with a size are drawn to soften its shadows
+const SOFT_SAMPLES: u32 = 8;
+// How far to either side of an occluder's corner to cast rays, in radians
+const CORNER_OFFSET: f32 = 0.0001;
+
+// Build the triangles lit by a light, in the order they should be drawn
+pub(crate) fn light_geometry(light: &Light, occluders: &[Occluder]) -> (Vec<Vertex>, Vec<Element>) {
+    let mut vertices = Vec::new();
+    let mut elements = Vec::new();
+    if light.radius <= 0.0 || light.spread <= 0.0 {
+        return (vertices, elements);
+    }
+    let edges: Vec<(Vector, Vector)> = occluders
+        .iter()
+        .flat_map(Occluder::edges)
+        .filter(|(a, b)| segment_distance(light.position, *a, *b) < light.radius + light.size)
+        .collect();
+
+    // Soft shadows come from blending dimmer copies of the light spread across its size
+    if light.size > 0.0 {
+        let scale = 1.0 / SOFT_SAMPLES as f32;
+        for i in 0..SOFT_SAMPLES {
+            let offset = Vector::from_angle(i as f32 * 360.0 / SOFT_SAMPLES as f32) * light.size;
+            let origin = light.position + offset;
+            add_fan(light, origin, scale, &edges, &mut vertices, &mut elements);
+        }
+    } else {
+        add_fan(
+            light,
+            light.position,
+            1.0,
+            &edges,
+            &mut vertices,
+            &mut elements,
+        );
+    }
+
+    (vertices, elements)
+}
+
+// Cast rays out from the origin to find what it can see, and fill that area with light
+fn add_fan(
+    light: &Light,
+    origin: Vector,
+    scale: f32,
+    edges: &[(Vector, Vector)],
+    vertices: &mut Vec<Vertex>,
+    elements: &mut Vec<Element>,
+) {
+    let half_spread = light.spread.min(360.0).to_radians() / 2.0;
+    let direction = light.direction.to_radians();
+    // Angles are measured from the start of the cone, so they can be sorted in order
+    let start = direction - half_spread;
+    let width = half_spread * 2.0;
+    let mut angles: Vec<f32> = (0..=EDGE_RAYS)
+        .map(|i| width * i as f32 / EDGE_RAYS as f32)
+        .collect();
+    for (a, b) in edges.iter() {
+        for corner in [*a, *b].iter() {
+            let angle = (*corner - origin).y.atan2((*corner - origin).x);
+            for offset in [-CORNER_OFFSET, 0.0, CORNER_OFFSET].iter() {
+                let relative = (angle + offset - start).rem_euclid(2.0 * PI);
+                if relative <= width {
+                    angles.push(relative);
+                }
+            }
+        }
+    }
+    angles.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    angles.dedup();
+
+    let hits: Vec<Vector> = angles
+        .iter()
+        .map(|angle| {
+            let ray = Vector::new((start + angle).cos(), (start + angle).sin());
+            let distance = edges
+                .iter()
+                .filter_map(|(a, b)| ray_distance(origin, ray, *a, *b))
+                .fold(light.radius, f32::min);
+            origin + ray * distance
+        })
+        .collect();
+
+    let base = vertices.len() as u32;
+    let vertex = |pos: Vector| {
+        let brightness = light.brightness(pos.distance(light.position)) * scale;
+        Vertex {
+            pos,
+            uv: None,
+            color: Color {
+                r: light.color.r * brightness,
+                g: light.color.g * brightness,
+                b: light.color.b * brightness,
+                a: brightness,
+            },
+        }
+    };
+    vertices.push(vertex(origin));
+    for hit in hits.iter() {
+        for band in 1..=BANDS {
+            let along = band as f32 / BANDS as f32;
+            vertices.push(vertex(origin + (*hit - origin) * along));
+        }
+    }
+
+    // A point light's fan wraps back around to the first ray
+    let ray_count = hits.len() as u32;
+    let pairs = if light.is_spot() {
+        ray_count.saturating_sub(1)
+    } else {
+        ray_count
+    };
+    let index = |ray: u32, band: u32| base + 1 + (ray % ray_count) * BANDS + band - 1;
+    for ray in 0..pairs {
+        let next = ray + 1;
+        elements.push(Element::Triangle([base, index(ray, 1), index(next, 1)]));
+        for band in 2..=BANDS {
+            let (a, b) = (index(ray, band - 1), index(next, band - 1));
+            let (c, d) = (index(next, band), index(ray, band));
+            elements.push(Element::Triangle([a, b, c]));
+            elements.push(Element::Triangle([a, c, d]));
+        }
+    }
+}
+
+// How far along a ray it hits a line segment, if it does
+fn ray_distance(origin: Vector, ray: Vector, a: Vector, b: Vector) -> Option<f32> {
+    let edge = b - a;
+    let denominator = ray.cross(edge);
+    if denominator.abs() < f32::EPSILON {
+        return None;
+    }
+    let to_edge = a - origin;
+    let distance = to_edge.cross(edge) / denominator;
+    let along_edge = to_edge.cross(ray) / denominator;
+    if distance >= 0.0 && (0.0..=1.0).contains(&along_edge) {
+        Some(distance)
+    } else {
+        None
+    }
+}
+
+// The shortest distance from a point to a line segment
+fn segment_distance(point: Vector, a: Vector, b: Vector) -> f32 {
+    let edge = b - a;
+    let length = edge.len2();
+    if length <= 0.0 {
+        return point.distance(a);
+    }
+    let along = ((point - a).dot(edge) / length).clamp(0.0, 1.0);
+
+    point.distance(a + edge * along)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // If any of the lit triangles cover the point
+    fn lit_at(vertices: &[Vertex], elements: &[Element], point: Vector) -> bool {
+        elements.iter().any(|element| match element {
+            Element::Triangle([a, b, c]) => {
+                let (a, b, c) = (
+                    vertices[*a as usize].pos,
+                    vertices[*b as usize].pos,
+                    vertices[*c as usize].pos,
+                );
+                let sides = [
+                    (b - a).cross(point - a),
+                    (c - b).cross(point - b),
+                    (a - c).cross(point - c),
+                ];
+                sides.iter().all(|side| *side >= 0.0) || sides.iter().all(|side| *side <= 0.0)
+            }
+            _ => false,
+        })
+    }
+
+    #[test]
+    fn occluders_cast_shadows() {
+        let light = Light::point(Vector::ZERO, 100.0, Color::WHITE);
+        let wall = Occluder::from(Rectangle::new(
+            Vector::new(20.0, -10.0),
+            Vector::new(10.0, 20.0),
+        ));
+        let (vertices, elements) = light_geometry(&light, &[wall]);
+        assert!(lit_at(&vertices, &elements, Vector::new(10.0, 0.0)));
+        assert!(lit_at(&vertices, &elements, Vector::new(-50.0, 0.0)));
+        assert!(!lit_at(&vertices, &elements, Vector::new(50.0, 0.0)));
+        assert!(!lit_at(&vertices, &elements, Vector::new(25.0, 0.0)));
+        assert!(!lit_at(&vertices, &elements, Vector::new(150.0, 0.0)));
+    }
+
+    #[test]
+    fn spot_lights_shine_in_a_cone() {
+        let light = Light::spot(Vector::ZERO, 100.0, Color::WHITE, 90.0, 60.0);
+        let (vertices, elements) = light_geometry(&light, &[]);
+        assert!(lit_at(&vertices, &elements, Vector::new(0.0, 50.0)));
+        assert!(!lit_at(&vertices, &elements, Vector::new(0.0, -50.0)));
+        assert!(!lit_at(&vertices, &elements, Vector::new(50.0, 10.0)));
+    }
+
+    #[test]
+    fn light_fades_with_distance() {
+        let light = Light {
+            falloff: 2.0,
+            ..Light::point(Vector::ZERO, 10.0, Color::WHITE)
+        };
+        assert_eq!(light.brightness(0.0), 1.0);
+        assert_eq!(light.brightness(5.0), 0.25);
+        assert_eq!(light.brightness(20.0), 0.0);
+    }
+}
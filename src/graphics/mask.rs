@@ -0,0 +1,110 @@
+use super::blend::{BlendChannel, BlendFactor, BlendFunction, BlendInput, BlendMode};
+use super::{Graphics, Image, PixelFormat, Surface};
+use crate::QuicksilverError;
+
+/// Whether masked content is drawn inside or outside the mask shape
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskMode {
+    /// Only draw the content where the mask shape was drawn
+    Inside,
+    /// Only draw the content where the mask shape wasn't drawn
+    Outside,
+}
+
+/// Render targets for clipping draws to an arbitrary shape
+///
+/// See [`Graphics::draw_masked`] to use it.
+///
+/// The mask shape and the masked content are each rendered to an offscreen image the size of the
+/// mask, and then combined and drawn over the camera area. Usually the mask should be the size
+/// of the window in physical pixels, but smaller masks work if some blurriness is acceptable.
+///
+/// Each mask holds onto its images until they're drawn, so a mask can only be used once per call
+/// to [`Graphics::flush_window`] or [`Graphics::present`]. Create more masks to clip several
+/// groups of draws in one frame.
+pub struct Mask {
+    pub(crate) shape_surface: Surface,
+    pub(crate) shape: Option<Image>,
+    pub(crate) content_surface: Surface,
+    pub(crate) content: Option<Image>,
+}
+
+impl Mask {
+    /// Create a mask whose images are `width` by `height` pixels
+    pub fn new(gfx: &Graphics, width: u32, height: u32) -> Result<Mask, QuicksilverError> {
+        // Each image keeps its own surface, so it's never attached to a different framebuffer
+        let shape = Image::from_raw(gfx, None, width, height, PixelFormat::RGBA)?;
+        let mut shape_surface = Surface::new(gfx, shape)?;
+        let shape = shape_surface.detach();
+        let content = Image::from_raw(gfx, None, width, height, PixelFormat::RGBA)?;
+        let mut content_surface = Surface::new(gfx, content)?;
+        let content = content_surface.detach();
+
+        Ok(Mask {
+            shape_surface,
+            shape,
+            content_surface,
+            content,
+        })
+    }
+
+    // If the images aren't waiting to be drawn from the last use of the mask
+    pub(crate) fn is_ready(&self) -> bool {
+        match (&self.shape, &self.content) {
+            (Some(shape), Some(content)) => shape.is_unique() && content.is_unique(),
+            _ => false,
+        }
+    }
+}
+
+impl MaskMode {
+    // Multiply the content by the alpha of the mask, or its inverse
+    pub(crate) fn blend_mode(self) -> BlendMode {
+        BlendMode {
+            function: BlendFunction::Same {
+                source: BlendFactor::Zero,
+                destination: match self {
+                    MaskMode::Inside => SOURCE_ALPHA,
+                    MaskMode::Outside => INVERSE_SOURCE_ALPHA,
+                },
+            },
+            ..BlendMode::default()
+        }
+    }
+}
+
+const SOURCE_ALPHA: BlendFactor = BlendFactor::Color {
+    input: BlendInput::Source,
+    channel: BlendChannel::Alpha,
+    is_inverse: false,
+};
+
+const INVERSE_SOURCE_ALPHA: BlendFactor = BlendFactor::Color {
+    input: BlendInput::Source,
+    channel: BlendChannel::Alpha,
+    is_inverse: true,
+};
+
+// Blend normally, but accumulate alpha correctly so the layer can be drawn as premultiplied
+pub(crate) fn layer_blend_mode() -> BlendMode {
+    BlendMode {
+        function: BlendFunction::Separate {
+            source_color: SOURCE_ALPHA,
+            source_alpha: BlendFactor::One,
+            destination_color: INVERSE_SOURCE_ALPHA,
+            destination_alpha: INVERSE_SOURCE_ALPHA,
+        },
+        ..BlendMode::default()
+    }
+}
+
+// Draw a layer whose colors were already multiplied by their alpha
+pub(crate) fn premultiplied_blend_mode() -> BlendMode {
+    BlendMode {
+        function: BlendFunction::Same {
+            source: BlendFactor::One,
+            destination: INVERSE_SOURCE_ALPHA,
+        },
+        ..BlendMode::default()
+    }
+}
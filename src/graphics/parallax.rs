@@ -0,0 +1,121 @@
+use super::Image;
+use crate::geom::{Rectangle, Vector};
+
+use std::time::Duration;
+
+/// A background image that scrolls more slowly than the world and repeats to fill the screen
+///
+/// Draw it with [`Graphics::draw_parallax`]. Layers follow the translation of the view set with
+/// [`Graphics::set_view`], scaled by their `scroll_factor`: a factor of 0 stays fixed to the
+/// screen, 1 moves along with the world, and anything in between seems further away. The view's
+/// scale and rotation aren't applied to layers.
+///
+/// [`Graphics::draw_parallax`]: crate::Graphics::draw_parallax
+/// [`Graphics::set_view`]: crate::Graphics::set_view
+#[derive(Clone)]
+pub struct ParallaxLayer {
+    /// The image to tile across the layer
+    pub image: Image,
+    /// How large each tile of the image is drawn
+    pub tile_size: Vector,
+    /// How far the layer moves along each axis when the view moves
+    pub scroll_factor: Vector,
+    /// How fast the layer scrolls by itself, in units per second
+    ///
+    /// This is applied by [`ParallaxLayer::update`].
+    pub velocity: Vector,
+    /// Where the layer's first tile is placed, relative to the top-left of the camera
+    pub offset: Vector,
+    /// If the image repeats horizontally
+    pub repeat_x: bool,
+    /// If the image repeats vertically
+    pub repeat_y: bool,
+}
+
+impl ParallaxLayer {
+    /// Create a layer that repeats horizontally, drawing the image at its own size
+    pub fn new(image: Image, scroll_factor: Vector) -> ParallaxLayer {
+        ParallaxLayer {
+            tile_size: image.size(),
+            image,
+            scroll_factor,
+            velocity: Vector::ZERO,
+            offset: Vector::ZERO,
+            repeat_x: true,
+            repeat_y: false,
+        }
+    }
+
+    /// Move the layer by its velocity over the given length of time
+    pub fn update(&mut self, elapsed: Duration) {
+        self.offset += self.velocity * elapsed.as_secs_f32();
+        // Keep the offset small on repeating axes, so it doesn't lose precision over time
+        if self.repeat_x && self.tile_size.x > 0.0 {
+            self.offset.x = self.offset.x.rem_euclid(self.tile_size.x);
+        }
+        if self.repeat_y && self.tile_size.y > 0.0 {
+            self.offset.y = self.offset.y.rem_euclid(self.tile_size.y);
+        }
+    }
+
+    // The areas to draw the image to, to cover the camera when the view is scrolled by `scroll`
+    pub(crate) fn visible_tiles(&self, camera: Rectangle, scroll: Vector) -> Vec<Rectangle> {
+        let origin = camera.pos + self.offset - scroll.times(self.scroll_factor);
+        let xs = tile_starts(
+            origin.x,
+            self.tile_size.x,
+            camera.x(),
+            camera.x() + camera.width(),
+            self.repeat_x,
+        );
+        let ys = tile_starts(
+            origin.y,
+            self.tile_size.y,
+            camera.y(),
+            camera.y() + camera.height(),
+            self.repeat_y,
+        );
+
+        ys.iter()
+            .flat_map(|y| {
+                xs.iter()
+                    .map(move |x| Rectangle::new(Vector::new(*x, *y), self.tile_size))
+            })
+            .collect()
+    }
+}
+
+// Where each visible tile along an axis starts
+fn tile_starts(origin: f32, size: f32, min: f32, max: f32, repeat: bool) -> Vec<f32> {
+    if !repeat || size <= 0.0 {
+        return if origin < max && origin + size > min {
+            vec![origin]
+        } else {
+            Vec::new()
+        };
+    }
+    let first = origin + ((min - origin) / size).floor() * size;
+    let count = ((max - first) / size).ceil().max(0.0) as u32;
+
+    (0..count).map(|i| first + i as f32 * size).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_the_camera() {
+        let starts = tile_starts(10.0, 32.0, 0.0, 100.0, true);
+        assert_eq!(starts, vec![-22.0, 10.0, 42.0, 74.0]);
+        let starts = tile_starts(-70.0, 32.0, 0.0, 64.0, true);
+        assert_eq!(starts, vec![-6.0, 26.0, 58.0]);
+    }
+
+    #[test]
+    fn single_tiles_are_culled() {
+        assert_eq!(tile_starts(10.0, 32.0, 0.0, 100.0, false), vec![10.0]);
+        assert!(tile_starts(-40.0, 32.0, 0.0, 100.0, false).is_empty());
+        assert!(tile_starts(100.0, 32.0, 0.0, 100.0, false).is_empty());
+    }
+}
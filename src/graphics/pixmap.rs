@@ -0,0 +1,546 @@
+use super::Color;
+use crate::geom::{Rectangle, Vector};
+use crate::QuicksilverError;
+
+use image::{ColorType, DynamicImage, RgbaImage};
+use std::path::Path;
+
+/// An image stored in CPU memory, as tightly-packed rows of RGBA bytes
+///
+/// Pixmaps are useful for editing pixel data before sending it to the GPU: palette swaps, flips,
+/// trimming, compositing several images into one, and so on. Once a Pixmap is ready, upload it
+/// with [`Image::from_pixmap`].
+///
+/// They are also produced by reading data back from the GPU, like [`Image::to_pixels`] or
+/// [`Graphics::render_pending_to_pixmap`]. Use [`Pixmap::encode`] to save them as a PNG or JPEG.
+///
+/// [`Image::from_pixmap`]: crate::graphics::Image::from_pixmap
+/// [`Image::to_pixels`]: crate::graphics::Image::to_pixels
+/// [`Graphics::render_pending_to_pixmap`]: crate::graphics::Graphics::render_pending_to_pixmap
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pixmap {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+/// The file format to use when encoding a [`Pixmap`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageEncoding {
+    /// Lossless PNG, which preserves transparency
+    Png,
+    /// Lossy JPEG at a given quality from 1 to 100, which discards transparency
+    Jpeg { quality: u8 },
+}
+
+impl Pixmap {
+    /// Create a fully transparent Pixmap
+    pub fn new(width: u32, height: u32) -> Pixmap {
+        Pixmap::from_raw(width, height, vec![0; width as usize * height as usize * 4])
+    }
+
+    /// Create a Pixmap from RGBA bytes, where the first row of pixels is the top of the image
+    ///
+    /// The data must be exactly `width * height * 4` bytes long.
+    pub fn from_raw(width: u32, height: u32, data: Vec<u8>) -> Pixmap {
+        assert_eq!(
+            data.len(),
+            width as usize * height as usize * 4,
+            "The pixel data was the wrong length for the width and height supplied"
+        );
+        Pixmap {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Decode a Pixmap from an encoded image format
+    ///
+    /// JPEG and PNG are supported, as are BMP, GIF, TGA and WebP with the features of the same
+    /// name enabled. For animated images, see [`Animation`](crate::graphics::Animation)
+    pub fn from_encoded_bytes(raw: &[u8]) -> Result<Pixmap, QuicksilverError> {
+        Ok(image::load_from_memory(raw)?.into())
+    }
+
+    /// Load a Pixmap from a file at the given path
+    ///
+    /// JPEG and PNG file formats are supported, as are BMP, GIF, TGA and WebP with the features
+    /// of the same name enabled
+    pub async fn load(path: impl AsRef<Path>) -> Result<Pixmap, QuicksilverError> {
+        let file_contents = platter::load_file(path).await?;
+        Pixmap::from_encoded_bytes(file_contents.as_slice())
+    }
+
+    /// The width of the pixmap in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the pixmap in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The width and height of the pixmap
+    pub fn size(&self) -> Vector {
+        Vector::new(self.width as f32, self.height as f32)
+    }
+
+    /// The RGBA bytes of the pixmap
+    pub fn data(&self) -> &[u8] {
+        &self.data[..]
+    }
+
+    /// The RGBA bytes of the pixmap, which can be edited in place
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data[..]
+    }
+
+    /// Take the RGBA bytes out of the pixmap
+    pub fn into_raw(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Get the color of a single pixel
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        let [r, g, b, a] = self.pixel_bytes(x, y);
+        Color::from_rgba(r, g, b, a as f32 / 255.0)
+    }
+
+    /// Set the color of a single pixel
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        assert!(
+            x < self.width && y < self.height,
+            "The pixel was outside the pixmap"
+        );
+        let index = self.index(x, y);
+        self.data[index..index + 4].copy_from_slice(&color_to_bytes(color));
+    }
+
+    fn pixel_bytes(&self, x: u32, y: u32) -> [u8; 4] {
+        assert!(
+            x < self.width && y < self.height,
+            "The pixel was outside the pixmap"
+        );
+        let index = self.index(x, y);
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&self.data[index..index + 4]);
+        pixel
+    }
+
+    /// Copy a region of the pixmap into a new pixmap
+    ///
+    /// The region is clamped to the bounds of the pixmap.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Pixmap {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for row in y..y + height {
+            let start = self.index(x, row);
+            data.extend_from_slice(&self.data[start..start + width as usize * 4]);
+        }
+
+        Pixmap::from_raw(width, height, data)
+    }
+
+    /// Mirror the pixmap left-to-right
+    pub fn flip_horizontal(&mut self) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        let row_len = self.width as usize * 4;
+        for row in self.data.chunks_mut(row_len) {
+            for x in 0..(self.width / 2) as usize {
+                let mirror = self.width as usize - x - 1;
+                for channel in 0..4 {
+                    row.swap(x * 4 + channel, mirror * 4 + channel);
+                }
+            }
+        }
+    }
+
+    /// Mirror the pixmap top-to-bottom
+    pub fn flip_vertical(&mut self) {
+        let row_len = self.width as usize * 4;
+        for y in 0..(self.height / 2) as usize {
+            let mirror = self.height as usize - y - 1;
+            let (top, bottom) = self.data.split_at_mut(mirror * row_len);
+            top[y * row_len..(y + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+        }
+    }
+
+    /// Create a copy of the pixmap rotated a quarter-turn clockwise
+    pub fn rotate_90(&self) -> Pixmap {
+        self.remap_positions(self.height, self.width, |x, y| (y, self.height - x - 1))
+    }
+
+    /// Create a copy of the pixmap rotated a half-turn
+    pub fn rotate_180(&self) -> Pixmap {
+        let mut rotated = self.clone();
+        rotated.flip_horizontal();
+        rotated.flip_vertical();
+        rotated
+    }
+
+    /// Create a copy of the pixmap rotated a quarter-turn counter-clockwise
+    pub fn rotate_270(&self) -> Pixmap {
+        self.remap_positions(self.height, self.width, |x, y| (self.width - y - 1, x))
+    }
+
+    // Build a new pixmap, where each destination pixel is copied from the source position
+    fn remap_positions(
+        &self,
+        width: u32,
+        height: u32,
+        source: impl Fn(u32, u32) -> (u32, u32),
+    ) -> Pixmap {
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = source(x, y);
+                data.extend_from_slice(&self.pixel_bytes(src_x, src_y));
+            }
+        }
+
+        Pixmap::from_raw(width, height, data)
+    }
+
+    /// Replace colors throughout the pixmap, for palette swaps
+    ///
+    /// Each pixel that exactly matches the first color of a pair is replaced with the second.
+    /// Colors are compared after being converted to 8-bit channels.
+    pub fn remap_palette(&mut self, palette: &[(Color, Color)]) {
+        let palette: Vec<_> = palette
+            .iter()
+            .map(|(from, to)| (color_to_bytes(*from), color_to_bytes(*to)))
+            .collect();
+        for pixel in self.data.chunks_mut(4) {
+            if let Some((_, to)) = palette.iter().find(|(from, _)| from[..] == pixel[..]) {
+                pixel.copy_from_slice(to);
+            }
+        }
+    }
+
+    /// Multiply the color channels of each pixel by its alpha
+    ///
+    /// Premultiplied images avoid dark fringes when they are scaled with linear filtering, but
+    /// they need to be drawn with a blend mode that expects premultiplied colors.
+    pub fn premultiply_alpha(&mut self) {
+        for pixel in self.data.chunks_mut(4) {
+            let alpha = u32::from(pixel[3]);
+            for channel in pixel[..3].iter_mut() {
+                *channel = ((u32::from(*channel) * alpha + 127) / 255) as u8;
+            }
+        }
+    }
+
+    /// Find the smallest region that contains every pixel that isn't fully transparent
+    ///
+    /// Returns None if the whole pixmap is transparent. Pair this with [`Pixmap::crop`] to trim
+    /// the empty space from around a sprite, keeping the region's position as the draw offset.
+    pub fn opaque_bounds(&self) -> Option<Rectangle> {
+        let mut min = (self.width, self.height);
+        let mut max = (0, 0);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.data[self.index(x, y) + 3] != 0 {
+                    min = (min.0.min(x), min.1.min(y));
+                    max = (max.0.max(x), max.1.max(y));
+                }
+            }
+        }
+        if min.0 > max.0 || min.1 > max.1 {
+            None
+        } else {
+            Some(Rectangle::new(
+                Vector::new(min.0 as f32, min.1 as f32),
+                Vector::new((max.0 - min.0 + 1) as f32, (max.1 - min.1 + 1) as f32),
+            ))
+        }
+    }
+
+    /// Crop away the fully transparent border of the pixmap, returning the remaining region
+    ///
+    /// Returns None if the whole pixmap is transparent. See [`Pixmap::opaque_bounds`].
+    pub fn trim_alpha(&self) -> Option<(Pixmap, Rectangle)> {
+        let bounds = self.opaque_bounds()?;
+        let trimmed = self.crop(
+            bounds.x() as u32,
+            bounds.y() as u32,
+            bounds.width() as u32,
+            bounds.height() as u32,
+        );
+        Some((trimmed, bounds))
+    }
+
+    /// Draw another pixmap on top of this one, with its top-left corner at the given position
+    ///
+    /// The source is blended over the destination using its alpha, and any part of it that lands
+    /// outside this pixmap is clipped.
+    pub fn blit(&mut self, source: &Pixmap, x: i32, y: i32) {
+        for src_y in 0..source.height {
+            let dst_y = y + src_y as i32;
+            if dst_y < 0 || dst_y >= self.height as i32 {
+                continue;
+            }
+            for src_x in 0..source.width {
+                let dst_x = x + src_x as i32;
+                if dst_x < 0 || dst_x >= self.width as i32 {
+                    continue;
+                }
+                let src = source.pixel_bytes(src_x, src_y);
+                let index = self.index(dst_x as u32, dst_y as u32);
+                blend_over(&mut self.data[index..index + 4], src);
+            }
+        }
+    }
+
+    /// Shrink the pixmap by an integer factor, averaging each block of pixels
+    ///
+    /// A factor of 2 halves the width and height. Any pixels left over at the right or bottom edge
+    /// that don't fill a whole block are dropped.
+    pub fn downscale(&self, factor: u32) -> Pixmap {
+        assert!(factor > 0, "The downscale factor must be at least 1");
+        if factor == 1 {
+            return self.clone();
+        }
+        let width = (self.width / factor).max(1);
+        let height = (self.height / factor).max(1);
+        let block_w = factor.min(self.width);
+        let block_h = factor.min(self.height);
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0u32; 4];
+                for by in 0..block_h {
+                    for bx in 0..block_w {
+                        let index = self.index(x * factor + bx, y * factor + by);
+                        for (total, value) in sum.iter_mut().zip(&self.data[index..index + 4]) {
+                            *total += u32::from(*value);
+                        }
+                    }
+                }
+                let count = block_w * block_h;
+                data.extend(sum.iter().map(|total| (total / count) as u8));
+            }
+        }
+
+        Pixmap::from_raw(width, height, data)
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+
+    /// Encode the pixmap into the bytes of an image file
+    pub fn encode(&self, encoding: ImageEncoding) -> Result<Vec<u8>, QuicksilverError> {
+        let mut buffer = Vec::new();
+        match encoding {
+            ImageEncoding::Png => {
+                image::png::PNGEncoder::new(&mut buffer).encode(
+                    &self.data[..],
+                    self.width,
+                    self.height,
+                    ColorType::RGBA(8),
+                )?;
+            }
+            ImageEncoding::Jpeg { quality } => {
+                // JPEG has no alpha channel, so it needs to be stripped before encoding
+                let rgb: Vec<u8> = self
+                    .data
+                    .chunks(4)
+                    .flat_map(|pixel| pixel[..3].iter().copied())
+                    .collect();
+                image::jpeg::JPEGEncoder::new_with_quality(&mut buffer, quality).encode(
+                    &rgb[..],
+                    self.width,
+                    self.height,
+                    ColorType::RGB(8),
+                )?;
+            }
+        }
+
+        Ok(buffer)
+    }
+}
+
+impl From<RgbaImage> for Pixmap {
+    fn from(image: RgbaImage) -> Pixmap {
+        let (width, height) = image.dimensions();
+        Pixmap::from_raw(width, height, image.into_raw())
+    }
+}
+
+impl From<DynamicImage> for Pixmap {
+    fn from(image: DynamicImage) -> Pixmap {
+        image.to_rgba().into()
+    }
+}
+
+fn color_to_bytes(color: Color) -> [u8; 4] {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [
+        channel(color.r),
+        channel(color.g),
+        channel(color.b),
+        channel(color.a),
+    ]
+}
+
+// Blend a straight-alpha source pixel over a destination pixel in place
+fn blend_over(dst: &mut [u8], src: [u8; 4]) {
+    let src_a = u32::from(src[3]);
+    if src_a == 255 {
+        dst.copy_from_slice(&src);
+        return;
+    }
+    let dst_a = u32::from(dst[3]) * (255 - src_a) / 255;
+    let out_a = src_a + dst_a;
+    if out_a == 0 {
+        dst.copy_from_slice(&[0; 4]);
+        return;
+    }
+    for channel in 0..3 {
+        let blended = u32::from(src[channel]) * src_a + u32::from(dst[channel]) * dst_a;
+        dst[channel] = (blended / out_a) as u8;
+    }
+    dst[3] = out_a as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_round_trip() {
+        let data = (0..4 * 3 * 4).map(|x| (x * 5) as u8).collect();
+        let pixmap = Pixmap::from_raw(4, 3, data);
+        let encoded = pixmap.encode(ImageEncoding::Png).unwrap();
+        let decoded = image::load_from_memory(&encoded[..]).unwrap().to_rgba();
+        assert_eq!(decoded.width(), 4);
+        assert_eq!(decoded.height(), 3);
+        assert_eq!(&decoded.into_raw()[..], pixmap.data());
+    }
+
+    #[test]
+    fn jpeg_dimensions() {
+        let pixmap = Pixmap::from_raw(8, 2, vec![255; 8 * 2 * 4]);
+        let encoded = pixmap.encode(ImageEncoding::Jpeg { quality: 90 }).unwrap();
+        let decoded = image::load_from_memory(&encoded[..]).unwrap();
+        assert_eq!(decoded.to_rgba().dimensions(), (8, 2));
+    }
+
+    #[test]
+    fn downscale_averages() {
+        let mut data = vec![0; 4 * 2 * 4];
+        data[..4].copy_from_slice(&[200, 100, 0, 255]);
+        data[4 * 4..4 * 5].copy_from_slice(&[200, 100, 0, 255]);
+        let pixmap = Pixmap::from_raw(4, 2, data).downscale(2);
+        assert_eq!(pixmap.width(), 2);
+        assert_eq!(pixmap.height(), 1);
+        assert_eq!(&pixmap.data()[..8], &[100, 50, 0, 127, 0, 0, 0, 0]);
+    }
+
+    // A 3x2 pixmap where each pixel's red channel is its index
+    fn numbered() -> Pixmap {
+        let data = (0..6).flat_map(|i| vec![i, 0, 0, 255]).collect();
+        Pixmap::from_raw(3, 2, data)
+    }
+
+    fn reds(pixmap: &Pixmap) -> Vec<u8> {
+        pixmap.data().chunks(4).map(|pixel| pixel[0]).collect()
+    }
+
+    #[test]
+    fn crop() {
+        let cropped = numbered().crop(1, 0, 2, 5);
+        assert_eq!((cropped.width(), cropped.height()), (2, 2));
+        assert_eq!(reds(&cropped), vec![1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn flips() {
+        let mut pixmap = numbered();
+        pixmap.flip_horizontal();
+        assert_eq!(reds(&pixmap), vec![2, 1, 0, 5, 4, 3]);
+        pixmap.flip_vertical();
+        assert_eq!(reds(&pixmap), vec![5, 4, 3, 2, 1, 0]);
+        assert_eq!(pixmap, numbered().rotate_180());
+    }
+
+    #[test]
+    fn empty_crops_flip() {
+        let mut cropped = numbered().crop(3, 0, 2, 2);
+        assert_eq!((cropped.width(), cropped.height()), (0, 2));
+        cropped.flip_horizontal();
+        cropped.flip_vertical();
+        assert!(cropped.data().is_empty());
+        Pixmap::new(0, 4).flip_horizontal();
+    }
+
+    #[test]
+    fn rotations() {
+        let clockwise = numbered().rotate_90();
+        assert_eq!((clockwise.width(), clockwise.height()), (2, 3));
+        assert_eq!(reds(&clockwise), vec![3, 0, 4, 1, 5, 2]);
+        let counter = numbered().rotate_270();
+        assert_eq!(reds(&counter), vec![2, 5, 1, 4, 0, 3]);
+        assert_eq!(clockwise.rotate_270(), numbered());
+    }
+
+    #[test]
+    fn palette_and_premultiply() {
+        let mut pixmap = Pixmap::new(2, 1);
+        pixmap.set_pixel(0, 0, Color::RED);
+        pixmap.set_pixel(1, 0, Color::WHITE.with_alpha(0.5));
+        pixmap.remap_palette(&[(Color::RED, Color::BLUE)]);
+        assert_eq!(pixmap.pixel(0, 0), Color::BLUE);
+        pixmap.premultiply_alpha();
+        assert_eq!(&pixmap.data()[4..], &[128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn trim() {
+        let mut pixmap = Pixmap::new(5, 4);
+        assert!(pixmap.trim_alpha().is_none());
+        pixmap.set_pixel(1, 1, Color::RED);
+        pixmap.set_pixel(3, 2, Color::RED);
+        let (trimmed, bounds) = pixmap.trim_alpha().unwrap();
+        assert_eq!(
+            bounds,
+            Rectangle::new(Vector::new(1.0, 1.0), Vector::new(3.0, 2.0))
+        );
+        assert_eq!((trimmed.width(), trimmed.height()), (3, 2));
+    }
+
+    #[test]
+    fn blit_clips_and_blends() {
+        let mut dst = Pixmap::new(2, 2);
+        dst.set_pixel(1, 1, Color::BLUE);
+        let mut src = Pixmap::new(2, 2);
+        for y in 0..2 {
+            for x in 0..2 {
+                src.set_pixel(x, y, Color::RED.with_alpha(0.5));
+            }
+        }
+        dst.blit(&src, 1, 1);
+        assert_eq!(dst.pixel(0, 0).a, 0.0);
+        assert_eq!(&dst.data()[12..], &[128, 0, 127, 255]);
+    }
+
+    #[test]
+    #[should_panic(expected = "wrong length")]
+    fn wrong_length() {
+        Pixmap::from_raw(2, 2, vec![0; 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside the pixmap")]
+    fn set_pixel_out_of_bounds() {
+        // Without the check, this would write into the first pixel of the next row
+        Pixmap::new(2, 2).set_pixel(2, 0, Color::WHITE);
+    }
+}
@@ -0,0 +1,220 @@
+use super::{Graphics, ImageEncoding, Pixmap};
+use crate::{QuicksilverError, Window};
+
+use instant::Instant;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Keeps a rolling buffer of recently presented frames, for "replay" clips and bug reports
+///
+/// Call [`FrameRecorder::capture`] once per frame, right before [`Graphics::present`]. Every Nth
+/// frame's pending draws are rendered offscreen, read back from the GPU and stored, and once the
+/// buffer is full the oldest frames are dropped. Like [`Graphics::render_pending_to_pixmap`], the
+/// frames leave out anything already flushed to the window that frame, and the letterbox bars. The most recent stretch of frames can then be exported as a numbered PNG sequence, or
+/// as an animated GIF with the `gif` feature enabled.
+///
+/// Reading frames back from the GPU is slow, so prefer capturing only some frames and downscaling
+/// them (see [`FrameRecorder::set_downscale`]).
+pub struct FrameRecorder {
+    frames: VecDeque<(Instant, Pixmap)>,
+    capacity: usize,
+    capture_every: u32,
+    downscale: u32,
+    frames_until_capture: u32,
+}
+
+impl FrameRecorder {
+    /// Create a recorder that holds at most `capacity` frames, capturing one of every
+    /// `capture_every` frames
+    ///
+    /// For example, capturing every 3rd frame of a 60 FPS game and holding 200 frames keeps the
+    /// last 10 seconds of gameplay.
+    pub fn new(capacity: usize, capture_every: u32) -> FrameRecorder {
+        assert!(capacity > 0, "The recorder must hold at least one frame");
+        assert!(capture_every > 0, "The capture interval must be at least 1");
+        FrameRecorder {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            capture_every,
+            downscale: 1,
+            frames_until_capture: 0,
+        }
+    }
+
+    /// Shrink each captured frame by an integer factor before storing it
+    ///
+    /// See [`Pixmap::downscale`]
+    pub fn set_downscale(&mut self, factor: u32) {
+        assert!(factor > 0, "The downscale factor must be at least 1");
+        self.downscale = factor;
+    }
+
+    /// Count a presented frame, and record it if it falls on the capture interval
+    ///
+    /// This must be called before [`Graphics::present`] and after any other flushes in the frame,
+    /// see [`Graphics::render_pending_to_pixmap`].
+    pub fn capture(&mut self, gfx: &mut Graphics, window: &Window) -> Result<(), QuicksilverError> {
+        if self.frames_until_capture == 0 {
+            self.frames_until_capture = self.capture_every;
+            let frame = gfx.render_pending_to_pixmap(window)?;
+            self.push(frame);
+        }
+        self.frames_until_capture -= 1;
+
+        Ok(())
+    }
+
+    /// Add a frame to the recording directly, timestamped with the current time
+    ///
+    /// If the frame is a different size than the frames already stored (for example, because
+    /// the window was resized) the older frames are discarded, so every exported clip has a
+    /// single size.
+    pub fn push(&mut self, frame: Pixmap) {
+        let frame = frame.downscale(self.downscale);
+        if let Some((_, last)) = self.frames.back() {
+            if last.width() != frame.width() || last.height() != frame.height() {
+                self.frames.clear();
+            }
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((Instant::now(), frame));
+    }
+
+    /// Drop all the stored frames
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// How many frames are currently stored
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// If no frames are currently stored
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The frames captured within `duration` of the most recent frame, oldest first
+    pub fn frames(&self, duration: Duration) -> impl Iterator<Item = &Pixmap> {
+        self.recent(duration).map(|(_, frame)| frame)
+    }
+
+    fn recent(&self, duration: Duration) -> impl Iterator<Item = &(Instant, Pixmap)> {
+        let newest = self.frames.back().map(|(time, _)| *time);
+        self.frames.iter().filter(move |(time, _)| match newest {
+            Some(newest) => newest.duration_since(*time) <= duration,
+            None => false,
+        })
+    }
+
+    /// Encode the frames from the last `duration` as PNG files, oldest first
+    ///
+    /// The index of each file in the returned list is its frame number in the sequence.
+    pub fn export_png_sequence(
+        &self,
+        duration: Duration,
+    ) -> Result<Vec<Vec<u8>>, QuicksilverError> {
+        self.frames(duration)
+            .map(|frame| frame.encode(ImageEncoding::Png))
+            .collect()
+    }
+
+    /// Encode the frames from the last `duration` as a looping animated GIF
+    ///
+    /// Each frame is shown for as long as it was on screen when it was captured. GIF only
+    /// supports 256 colors per frame, so colors will be approximated.
+    #[cfg(feature = "gif")]
+    pub fn export_gif(&self, duration: Duration) -> Result<Vec<u8>, QuicksilverError> {
+        use image_gif::SetParameter;
+
+        let frames: Vec<_> = self.recent(duration).collect();
+        let mut buffer = Vec::new();
+        let (width, height) = match frames.first() {
+            Some((_, frame)) => (frame.width() as u16, frame.height() as u16),
+            None => return Ok(buffer),
+        };
+        {
+            let mut encoder = image_gif::Encoder::new(&mut buffer, width, height, &[])?;
+            encoder.set(image_gif::Repeat::Infinite)?;
+            let mut previous_delay = 0;
+            for (i, (time, frame)) in frames.iter().enumerate() {
+                // GIF delays are measured in hundredths of a second
+                let delay = match frames.get(i + 1) {
+                    Some((next, _)) => (next.duration_since(*time).as_millis() / 10) as u16,
+                    None => previous_delay,
+                };
+                previous_delay = delay;
+                let mut data = frame.data().to_vec();
+                let mut gif_frame =
+                    image_gif::Frame::from_rgba_speed(width, height, &mut data[..], 10);
+                gif_frame.delay = delay;
+                encoder.write_frame(&gif_frame)?;
+            }
+        }
+
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32, value: u8) -> Pixmap {
+        Pixmap::from_raw(width, height, vec![value; (width * height * 4) as usize])
+    }
+
+    #[test]
+    fn drops_oldest_frames() {
+        let mut recorder = FrameRecorder::new(2, 1);
+        recorder.push(frame(2, 2, 1));
+        recorder.push(frame(2, 2, 2));
+        recorder.push(frame(2, 2, 3));
+        assert_eq!(recorder.len(), 2);
+        let values: Vec<u8> = recorder
+            .frames(Duration::from_secs(60))
+            .map(|frame| frame.data()[0])
+            .collect();
+        assert_eq!(values, vec![2, 3]);
+    }
+
+    #[test]
+    fn resize_discards_frames() {
+        let mut recorder = FrameRecorder::new(4, 1);
+        recorder.push(frame(2, 2, 1));
+        recorder.push(frame(4, 2, 2));
+        assert_eq!(recorder.len(), 1);
+    }
+
+    #[test]
+    fn downscales_frames() {
+        let mut recorder = FrameRecorder::new(4, 1);
+        recorder.set_downscale(2);
+        recorder.push(frame(4, 4, 1));
+        let sequence = recorder
+            .export_png_sequence(Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(sequence.len(), 1);
+        let decoded = image::load_from_memory(&sequence[0][..]).unwrap();
+        assert_eq!(decoded.to_rgba().dimensions(), (2, 2));
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn exports_gif() {
+        let mut recorder = FrameRecorder::new(4, 1);
+        recorder.push(frame(3, 2, 255));
+        recorder.push(frame(3, 2, 0));
+        let gif = recorder.export_gif(Duration::from_secs(1)).unwrap();
+        let mut reader = image_gif::Decoder::new(&gif[..]).read_info().unwrap();
+        assert_eq!((reader.width(), reader.height()), (3, 2));
+        let mut frames = 0;
+        while reader.read_next_frame().unwrap().is_some() {
+            frames += 1;
+        }
+        assert_eq!(frames, 2);
+    }
+}
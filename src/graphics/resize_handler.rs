@@ -1,6 +1,9 @@
-use crate::geom::Vector;
+use super::{Color, Image};
+use crate::geom::{Rectangle, Vector};
+use std::fmt;
+use std::rc::Rc;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Clone)]
 #[non_exhaustive]
 /// The way to adjust the content when the size of the window changes
 pub enum ResizeHandler {
@@ -26,17 +29,129 @@ pub enum ResizeHandler {
         aspect_width: u32,
         aspect_height: u32,
     },
+    /// Use the entire window, and show more of the world along its wider axis
+    ///
+    /// The whole camera area (see [`Graphics::set_camera_size`]) is always visible and centered,
+    /// without stretching. If the window has a different aspect ratio than the camera, the
+    /// visible area extends past the camera on either side instead of adding letterboxing.
+    ///
+    /// [`Graphics::set_camera_size`]: crate::Graphics::set_camera_size
+    Expand,
+    /// Determine the size of the content from the size of the window with a function
+    ///
+    /// The content is centered in the window, like the other strategies. The function can
+    /// capture settings that change while the game runs, like an aspect ratio the player chose;
+    /// see [`ResizeHandler::custom`]. Two `Custom` handlers are only equal if they share the same
+    /// function.
+    Custom(Rc<dyn Fn(Vector) -> Vector>),
+}
+
+impl fmt::Debug for ResizeHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ResizeHandler::*;
+
+        match self {
+            Stretch => f.write_str("Stretch"),
+            Maintain { width, height } => f
+                .debug_struct("Maintain")
+                .field("width", width)
+                .field("height", height)
+                .finish(),
+            Fill {
+                aspect_width,
+                aspect_height,
+            } => f
+                .debug_struct("Fill")
+                .field("aspect_width", aspect_width)
+                .field("aspect_height", aspect_height)
+                .finish(),
+            Fit {
+                aspect_width,
+                aspect_height,
+            } => f
+                .debug_struct("Fit")
+                .field("aspect_width", aspect_width)
+                .field("aspect_height", aspect_height)
+                .finish(),
+            IntegerScale {
+                aspect_width,
+                aspect_height,
+            } => f
+                .debug_struct("IntegerScale")
+                .field("aspect_width", aspect_width)
+                .field("aspect_height", aspect_height)
+                .finish(),
+            Expand => f.write_str("Expand"),
+            Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl PartialEq for ResizeHandler {
+    fn eq(&self, other: &ResizeHandler) -> bool {
+        use ResizeHandler::*;
+
+        match (self, other) {
+            (Stretch, Stretch) | (Expand, Expand) => true,
+            (
+                Maintain { width, height },
+                Maintain {
+                    width: other_width,
+                    height: other_height,
+                },
+            ) => width == other_width && height == other_height,
+            (
+                Fill {
+                    aspect_width,
+                    aspect_height,
+                },
+                Fill {
+                    aspect_width: other_width,
+                    aspect_height: other_height,
+                },
+            )
+            | (
+                Fit {
+                    aspect_width,
+                    aspect_height,
+                },
+                Fit {
+                    aspect_width: other_width,
+                    aspect_height: other_height,
+                },
+            ) => aspect_width == other_width && aspect_height == other_height,
+            (
+                IntegerScale {
+                    aspect_width,
+                    aspect_height,
+                },
+                IntegerScale {
+                    aspect_width: other_width,
+                    aspect_height: other_height,
+                },
+            ) => aspect_width == other_width && aspect_height == other_height,
+            (Custom(content_size), Custom(other_content_size)) => {
+                Rc::ptr_eq(content_size, other_content_size)
+            }
+            _ => false,
+        }
+    }
 }
 
 impl ResizeHandler {
+    /// Create a [`ResizeHandler::Custom`] that sizes the content with a function or closure
+    pub fn custom(content_size: impl Fn(Vector) -> Vector + 'static) -> ResizeHandler {
+        ResizeHandler::Custom(Rc::new(content_size))
+    }
+
     /// Determine the size of the content given a window size
     ///
     /// This depends on which ResizeStrategy is in use; check the documentation for each enum
     /// variant for more.
-    pub fn content_size(self, size: Vector) -> Vector {
+    pub fn content_size(&self, size: Vector) -> Vector {
         use ResizeHandler::*;
 
-        let is_fill = match self {
+        let is_fill = match *self {
             Fill { .. } => true,
             _ => false,
         };
@@ -44,8 +159,9 @@ impl ResizeHandler {
         // First find the size we actually want to draw to, given the total size
         // For example, for stretching, we just always use the entire screen
         // For Maintain, we always use the size provided
-        match self {
-            Stretch => size,
+        match *self {
+            Stretch | Expand => size,
+            Custom(ref content_size) => content_size(size),
             Maintain { width, height } => Vector::new(width, height),
             Fill {
                 aspect_width,
@@ -76,6 +192,39 @@ impl ResizeHandler {
             }
         }
     }
+
+    /// Determine the area of the world that is visible, given the camera and window sizes
+    ///
+    /// For every strategy but [`ResizeHandler::Expand`], this is just the camera area.
+    pub fn camera_area(&self, camera: Vector, window: Vector) -> Rectangle {
+        match self {
+            ResizeHandler::Expand => {
+                let scale = (window.x / camera.x).min(window.y / camera.y);
+                let size = window / scale;
+                Rectangle::new((camera - size) / 2.0, size)
+            }
+            _ => Rectangle::new_sized(camera),
+        }
+    }
+}
+
+/// What to draw in the bars around the content when it doesn't fill the window
+///
+/// See [`Graphics::set_letterbox`](crate::Graphics::set_letterbox)
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum Letterbox {
+    /// Leave the bars alone, so they show whatever the window was last cleared to
+    Unchanged,
+    /// Fill the bars with a solid color
+    Color(Color),
+    /// Scale an image to cover the whole window, and show it in the bars
+    Image(Image),
+    /// Scale a blurred copy of the frame to cover the whole window, and show it in the bars
+    ///
+    /// The color multiplies the blurred frame, which is useful to darken or tint it. This renders
+    /// the frame to an offscreen image first, so it is a little slower than the other options.
+    BlurredFrame(Color),
 }
 
 // Find either the n or 1 / n where n is an integer
@@ -86,3 +235,50 @@ fn int_scale(value: f32) -> f32 {
         value.recip().floor().recip()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_extends_wider_axis() {
+        let area = ResizeHandler::Expand
+            .camera_area(Vector::new(400.0, 300.0), Vector::new(1000.0, 600.0));
+        assert_eq!(
+            area,
+            Rectangle::new(Vector::new(-50.0, 0.0), Vector::new(500.0, 300.0))
+        );
+        let content = ResizeHandler::Expand.content_size(Vector::new(1000.0, 600.0));
+        assert_eq!(content, Vector::new(1000.0, 600.0));
+    }
+
+    #[test]
+    fn custom_content_size() {
+        let aspect_ratio = 2.0;
+        let handler =
+            ResizeHandler::custom(move |size: Vector| Vector::new(size.y * aspect_ratio, size.y));
+        let content = handler.content_size(Vector::new(800.0, 300.0));
+        assert_eq!(content, Vector::new(600.0, 300.0));
+    }
+
+    #[test]
+    fn equality() {
+        let fit = |aspect_width, aspect_height| ResizeHandler::Fit {
+            aspect_width,
+            aspect_height,
+        };
+        assert_eq!(fit(16.0, 9.0), fit(16.0, 9.0));
+        assert_ne!(fit(16.0, 9.0), fit(4.0, 3.0));
+        assert_ne!(
+            fit(16.0, 9.0),
+            ResizeHandler::Fill {
+                aspect_width: 16.0,
+                aspect_height: 9.0
+            }
+        );
+        assert_eq!(ResizeHandler::Expand, ResizeHandler::Expand);
+        let custom = ResizeHandler::custom(|size| size);
+        assert_eq!(custom, custom.clone());
+        assert_ne!(custom, ResizeHandler::custom(|size| size));
+    }
+}
@@ -0,0 +1,156 @@
+use super::{Color, Image};
+use crate::geom::{Rectangle, Transform, Vector};
+
+/// An image, or a region of one, placed in the world with its own transformation
+///
+/// Draw it with [`Graphics::draw_sprite`] or [`Graphics::draw_sprites`]. The sprite's vertices are
+/// transformed on the CPU, so many sprites with different transformations drawn from the same
+/// image are still sent to the GPU in a single batch.
+///
+/// The sprite is scaled, flipped and rotated around its `origin`, which is then placed at its
+/// `position`.
+///
+/// [`Graphics::draw_sprite`]: crate::Graphics::draw_sprite
+/// [`Graphics::draw_sprites`]: crate::Graphics::draw_sprites
+#[derive(Clone)]
+pub struct Sprite {
+    /// The image to draw from
+    pub image: Image,
+    /// The region of the image to draw, in pixels, or `None` to draw the whole image
+    ///
+    /// This is useful for drawing from texture atlases and sprite sheets.
+    pub region: Option<Rectangle>,
+    /// Where the origin of the sprite is placed
+    pub position: Vector,
+    /// The point the sprite is positioned, scaled and rotated around
+    ///
+    /// It is measured in pixels from the top-left of the region, so a 32x32 sprite with an origin
+    /// of (16, 16) rotates around its center.
+    pub origin: Vector,
+    /// The clockwise rotation of the sprite in degrees
+    pub rotation: f32,
+    /// How much to scale the sprite by along each axis
+    pub scale: Vector,
+    /// If the sprite is mirrored horizontally
+    pub flip_x: bool,
+    /// If the sprite is mirrored vertically
+    pub flip_y: bool,
+    /// The color to multiply the sprite by, see [`Graphics::draw_image_tinted`]
+    ///
+    /// [`Graphics::draw_image_tinted`]: crate::Graphics::draw_image_tinted
+    pub tint: Color,
+    /// The order to draw the sprite in, when drawn with [`Graphics::draw_sprites`]
+    ///
+    /// Sprites with a higher `z` are drawn over those with a lower one.
+    ///
+    /// [`Graphics::draw_sprites`]: crate::Graphics::draw_sprites
+    pub z: f32,
+}
+
+impl Sprite {
+    /// Create a sprite of a whole image, with its top-left corner at the origin
+    pub fn new(image: Image) -> Sprite {
+        Sprite {
+            image,
+            region: None,
+            position: Vector::ZERO,
+            origin: Vector::ZERO,
+            rotation: 0.0,
+            scale: Vector::ONE,
+            flip_x: false,
+            flip_y: false,
+            tint: Color::WHITE,
+            z: 0.0,
+        }
+    }
+
+    /// Create a sprite of a region of an image, such as one frame of a sprite sheet
+    pub fn from_region(image: Image, region: Rectangle) -> Sprite {
+        Sprite {
+            region: Some(region),
+            ..Sprite::new(image)
+        }
+    }
+
+    /// Move the origin to the center of the sprite, so it rotates and scales around its center
+    pub fn center_origin(&mut self) {
+        self.origin = self.size() / 2.0;
+    }
+
+    /// The size of the sprite in pixels, before it is scaled
+    pub fn size(&self) -> Vector {
+        self.region().size
+    }
+
+    /// The transformation that takes a point on the sprite, in pixels, to where it's drawn
+    pub fn transform(&self) -> Transform {
+        Transform::translate(self.position)
+            * Transform::rotate(self.rotation)
+            * Transform::scale(self.scale)
+            * Transform::translate(-self.origin)
+    }
+
+    pub(crate) fn region(&self) -> Rectangle {
+        self.region
+            .unwrap_or_else(|| Rectangle::new_sized(self.image.size()))
+    }
+
+    // The corners of the sprite in the world, in the order top-left, top-right, bottom-right,
+    // bottom-left
+    pub(crate) fn corners(&self) -> [Vector; 4] {
+        let size = self.size();
+        let transform = self.transform();
+        [
+            transform * Vector::ZERO,
+            transform * size.x_comp(),
+            transform * size,
+            transform * size.y_comp(),
+        ]
+    }
+
+    // The texture coordinates of each corner
+    pub(crate) fn uvs(&self) -> [Vector; 4] {
+        uv_corners(
+            self.region(),
+            self.image.texture_size(),
+            self.flip_x,
+            self.flip_y,
+        )
+    }
+}
+
+fn uv_corners(region: Rectangle, texture_size: Vector, flip_x: bool, flip_y: bool) -> [Vector; 4] {
+    let size_recip = texture_size.recip();
+    let mut min = region.pos.times(size_recip);
+    let mut max = (region.pos + region.size).times(size_recip);
+    if flip_x {
+        std::mem::swap(&mut min.x, &mut max.x);
+    }
+    if flip_y {
+        std::mem::swap(&mut min.y, &mut max.y);
+    }
+
+    [
+        min,
+        Vector::new(max.x, min.y),
+        max,
+        Vector::new(min.x, max.y),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flipped_uvs() {
+        let region = Rectangle::new(Vector::new(16.0, 0.0), Vector::new(16.0, 16.0));
+        let size = Vector::new(64.0, 32.0);
+        let uvs = uv_corners(region, size, false, false);
+        assert_eq!(uvs[0], Vector::new(0.25, 0.0));
+        assert_eq!(uvs[2], Vector::new(0.5, 0.5));
+        let flipped = uv_corners(region, size, true, true);
+        assert_eq!(flipped[0], uvs[2]);
+        assert_eq!(flipped[1], uvs[3]);
+    }
+}
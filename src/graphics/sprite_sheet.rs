@@ -0,0 +1,648 @@
+use super::{Image, Sprite};
+use crate::geom::{Rectangle, Vector};
+
+use std::time::Duration;
+
+/// An image divided into frames, along with the animations and regions defined on it
+///
+/// Sprite sheets can be sliced from a uniform grid with [`SpriteSheet::from_grid`], or imported
+/// from the JSON data exported by [Aseprite](https://www.aseprite.org) with the `aseprite`
+/// feature enabled.
+#[derive(Clone)]
+pub struct SpriteSheet {
+    image: Image,
+    frames: Vec<SheetFrame>,
+    clips: Vec<AnimationClip>,
+    slices: Vec<Slice>,
+}
+
+/// One frame of a [`SpriteSheet`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SheetFrame {
+    /// The name of the frame, which is its index for sheets sliced from a grid
+    pub name: String,
+    /// Where the frame is in the sheet's image, in pixels
+    pub region: Rectangle,
+    /// How long the frame is shown for when it's part of an animation
+    pub duration: Duration,
+    /// Where the region is placed within the original frame, if transparent edges were trimmed
+    pub offset: Vector,
+    /// The size of the original frame, before any trimming
+    pub source_size: Vector,
+}
+
+/// The order an [`AnimationClip`] plays its frames in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayDirection {
+    /// From the first frame to the last
+    Forward,
+    /// From the last frame to the first
+    Reverse,
+    /// From the first frame to the last, and then back
+    PingPong,
+    /// From the last frame to the first, and then back
+    PingPongReverse,
+}
+
+/// A named animation made of a range of frames in a [`SpriteSheet`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationClip {
+    /// The name of the animation
+    pub name: String,
+    /// The index of the first frame in the animation
+    pub from: usize,
+    /// The index of the last frame in the animation, inclusive
+    pub to: usize,
+    /// The order the frames are played in
+    pub direction: PlayDirection,
+}
+
+/// A named region of a [`SpriteSheet`], like a hitbox or a nine-slice UI panel
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slice {
+    /// The name of the slice
+    pub name: String,
+    /// Where the slice is on each frame, in order of the frame it starts applying at
+    pub keys: Vec<SliceKey>,
+}
+
+/// Where a [`Slice`] is, starting at a given frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct SliceKey {
+    /// The first frame this key applies to
+    pub frame: usize,
+    /// The area of the slice, relative to the top-left of the frame
+    pub bounds: Rectangle,
+    /// The center part of a nine-slice, relative to the top-left of the bounds
+    pub center: Option<Rectangle>,
+    /// The pivot point, relative to the top-left of the bounds
+    pub pivot: Option<Vector>,
+}
+
+impl SpriteSheet {
+    /// Create a sprite sheet from frames and animation clips that have already been defined
+    pub fn new(image: Image, frames: Vec<SheetFrame>, clips: Vec<AnimationClip>) -> SpriteSheet {
+        SpriteSheet {
+            image,
+            frames,
+            clips,
+            slices: Vec::new(),
+        }
+    }
+
+    /// Slice an image into a uniform grid of frames, left-to-right and then top-to-bottom
+    ///
+    /// The grid starts `margin` pixels from the top-left of the image, and there are `spacing`
+    /// pixels between each frame. Each frame is named after its index and shown for 100
+    /// milliseconds; add animations with [`SpriteSheet::add_clip`].
+    ///
+    /// The frame size must be positive, and the spacing can be negative to overlap the frames,
+    /// but only by less than the frame size.
+    pub fn from_grid(
+        image: Image,
+        frame_size: Vector,
+        margin: Vector,
+        spacing: Vector,
+    ) -> SpriteSheet {
+        let frames = grid_regions(image.size(), frame_size, margin, spacing)
+            .into_iter()
+            .enumerate()
+            .map(|(i, region)| SheetFrame {
+                name: i.to_string(),
+                region,
+                duration: Duration::from_millis(100),
+                offset: Vector::ZERO,
+                source_size: region.size,
+            })
+            .collect();
+
+        SpriteSheet::new(image, frames, Vec::new())
+    }
+
+    /// Add a named animation to the sheet, replacing any with the same name
+    pub fn add_clip(&mut self, clip: AnimationClip) {
+        assert!(
+            clip.from <= clip.to && clip.to < self.frames.len(),
+            "The clip's frames are out of the sheet's bounds"
+        );
+        self.clips.retain(|existing| existing.name != clip.name);
+        self.clips.push(clip);
+    }
+
+    /// The image the frames are drawn from
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// All the frames in the sheet
+    pub fn frames(&self) -> &[SheetFrame] {
+        &self.frames
+    }
+
+    /// All the frames in the sheet, to change their names or durations
+    pub fn frames_mut(&mut self) -> &mut [SheetFrame] {
+        &mut self.frames
+    }
+
+    /// Find the index of a frame by name
+    pub fn frame_index(&self, name: &str) -> Option<usize> {
+        self.frames.iter().position(|frame| frame.name == name)
+    }
+
+    /// Find the region of the image a frame covers by its name
+    pub fn region(&self, name: &str) -> Option<Rectangle> {
+        self.frame_index(name)
+            .map(|index| self.frames[index].region)
+    }
+
+    /// All the animations defined on the sheet
+    pub fn clips(&self) -> &[AnimationClip] {
+        &self.clips
+    }
+
+    /// Find an animation by name
+    pub fn clip(&self, name: &str) -> Option<&AnimationClip> {
+        self.clips.iter().find(|clip| clip.name == name)
+    }
+
+    /// All the slices defined on the sheet
+    pub fn slices(&self) -> &[Slice] {
+        &self.slices
+    }
+
+    /// Find a slice by name
+    pub fn slice(&self, name: &str) -> Option<&Slice> {
+        self.slices.iter().find(|slice| slice.name == name)
+    }
+
+    /// Create a sprite showing the frame at an index
+    ///
+    /// If the frame was trimmed, the sprite's origin is moved so it lines up with the untrimmed
+    /// frames around it.
+    pub fn sprite(&self, index: usize) -> Sprite {
+        let frame = &self.frames[index];
+        let mut sprite = Sprite::from_region(self.image.clone(), frame.region);
+        sprite.origin = -frame.offset;
+
+        sprite
+    }
+
+    /// The index of the frame shown after an animation has been playing for `elapsed`
+    ///
+    /// Animations loop forever, so times past the end wrap around to the start.
+    pub fn frame_at(&self, clip: &AnimationClip, elapsed: Duration) -> usize {
+        let order = clip.frame_order();
+        let total: u128 = order
+            .iter()
+            .map(|index| self.frames[*index].duration.as_micros())
+            .sum();
+        if total == 0 {
+            return order[0];
+        }
+        let mut remaining = elapsed.as_micros() % total;
+        for index in order.iter() {
+            let duration = self.frames[*index].duration.as_micros();
+            if remaining < duration {
+                return *index;
+            }
+            remaining -= duration;
+        }
+
+        order[order.len() - 1]
+    }
+}
+
+impl AnimationClip {
+    /// The indices of the frames in the order they play, for one loop of the animation
+    ///
+    /// Ping-pong animations don't repeat the frames they turn around at.
+    pub fn frame_order(&self) -> Vec<usize> {
+        let forward = self.from..=self.to;
+        let backward = forward.clone().rev();
+        let inner = self.to.saturating_sub(self.from).saturating_sub(1);
+        match self.direction {
+            PlayDirection::Forward => forward.collect(),
+            PlayDirection::Reverse => backward.collect(),
+            PlayDirection::PingPong => forward.chain(backward.skip(1).take(inner)).collect(),
+            PlayDirection::PingPongReverse => backward.chain(forward.skip(1).take(inner)).collect(),
+        }
+    }
+}
+
+impl SliceKey {
+    /// The key that applies at a given frame, if any
+    pub fn at_frame(keys: &[SliceKey], frame: usize) -> Option<&SliceKey> {
+        keys.iter().rev().find(|key| key.frame <= frame)
+    }
+}
+
+fn grid_regions(image: Vector, frame: Vector, margin: Vector, spacing: Vector) -> Vec<Rectangle> {
+    assert!(
+        frame.x > 0.0 && frame.y > 0.0,
+        "The frame size must be positive"
+    );
+    assert!(
+        frame.x + spacing.x > 0.0 && frame.y + spacing.y > 0.0,
+        "The spacing must not cancel out the frame size"
+    );
+    let mut regions = Vec::new();
+    let mut y = margin.y;
+    while y + frame.y <= image.y {
+        let mut x = margin.x;
+        while x + frame.x <= image.x {
+            regions.push(Rectangle::new(Vector::new(x, y), frame));
+            x += frame.x + spacing.x;
+        }
+        y += frame.y + spacing.y;
+    }
+
+    regions
+}
+
+#[cfg(feature = "aseprite")]
+mod aseprite {
+    use super::*;
+    use crate::graphics::Graphics;
+    use crate::QuicksilverError;
+
+    use serde::de::{Deserializer, MapAccess, Visitor};
+    use serde::Deserialize;
+    use std::fmt;
+    use std::path::Path;
+
+    #[derive(Deserialize)]
+    struct Sheet {
+        frames: Frames,
+        meta: Meta,
+    }
+
+    // Aseprite can export frames as either an array or an object keyed by name
+    struct Frames(Vec<(String, Frame)>);
+
+    #[derive(Deserialize)]
+    struct Frame {
+        #[serde(default)]
+        filename: String,
+        frame: Rect,
+        #[serde(default)]
+        rotated: bool,
+        #[serde(rename = "spriteSourceSize")]
+        sprite_source_size: Rect,
+        #[serde(rename = "sourceSize")]
+        source_size: Size,
+        duration: u64,
+    }
+
+    #[derive(Deserialize)]
+    struct Meta {
+        image: String,
+        #[serde(rename = "frameTags", default)]
+        frame_tags: Vec<Tag>,
+        #[serde(default)]
+        slices: Vec<JsonSlice>,
+    }
+
+    #[derive(Deserialize)]
+    struct Tag {
+        name: String,
+        from: usize,
+        to: usize,
+        #[serde(default = "forward")]
+        direction: String,
+    }
+
+    fn forward() -> String {
+        "forward".to_owned()
+    }
+
+    #[derive(Deserialize)]
+    struct JsonSlice {
+        name: String,
+        keys: Vec<Key>,
+    }
+
+    #[derive(Deserialize)]
+    struct Key {
+        frame: usize,
+        bounds: Rect,
+        center: Option<Rect>,
+        pivot: Option<Point>,
+    }
+
+    #[derive(Deserialize)]
+    struct Rect {
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+    }
+
+    #[derive(Deserialize)]
+    struct Size {
+        w: f32,
+        h: f32,
+    }
+
+    #[derive(Deserialize)]
+    struct Point {
+        x: f32,
+        y: f32,
+    }
+
+    impl From<Rect> for Rectangle {
+        fn from(rect: Rect) -> Rectangle {
+            Rectangle::new(Vector::new(rect.x, rect.y), Vector::new(rect.w, rect.h))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Frames {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Frames, D::Error> {
+            struct FramesVisitor;
+
+            impl<'de> Visitor<'de> for FramesVisitor {
+                type Value = Frames;
+
+                fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                    fmt.write_str("an array or object of frames")
+                }
+
+                fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                    self,
+                    mut seq: A,
+                ) -> Result<Frames, A::Error> {
+                    let mut frames = Vec::new();
+                    while let Some(frame) = seq.next_element::<Frame>()? {
+                        frames.push((frame.filename.clone(), frame));
+                    }
+                    Ok(Frames(frames))
+                }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Frames, A::Error> {
+                    let mut frames = Vec::new();
+                    while let Some(entry) = map.next_entry::<String, Frame>()? {
+                        frames.push(entry);
+                    }
+                    Ok(Frames(frames))
+                }
+            }
+
+            deserializer.deserialize_any(FramesVisitor)
+        }
+    }
+
+    // The parts of the sheet that don't need the image
+    pub(super) struct Parsed {
+        pub image: String,
+        pub frames: Vec<SheetFrame>,
+        pub clips: Vec<AnimationClip>,
+        pub slices: Vec<Slice>,
+    }
+
+    pub(super) fn parse(json: &[u8]) -> Result<Parsed, QuicksilverError> {
+        let sheet: Sheet = serde_json::from_slice(json).map_err(parse_error)?;
+        let frames: Vec<SheetFrame> = sheet
+            .frames
+            .0
+            .into_iter()
+            .map(|(name, frame)| {
+                // Sprites are drawn straight from their region, so a rotated one would be sideways
+                if frame.rotated {
+                    return Err(parse_error(format!("frame {} is rotated", name)));
+                }
+                Ok(SheetFrame {
+                    name,
+                    region: frame.frame.into(),
+                    duration: Duration::from_millis(frame.duration),
+                    offset: Vector::new(frame.sprite_source_size.x, frame.sprite_source_size.y),
+                    source_size: Vector::new(frame.source_size.w, frame.source_size.h),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let clips = sheet
+            .meta
+            .frame_tags
+            .into_iter()
+            .map(|tag| {
+                if tag.from > tag.to || tag.to >= frames.len() {
+                    return Err(parse_error(format!("tag {} is out of bounds", tag.name)));
+                }
+                let direction = match tag.direction.as_str() {
+                    "forward" => PlayDirection::Forward,
+                    "reverse" => PlayDirection::Reverse,
+                    "pingpong" => PlayDirection::PingPong,
+                    "pingpong_reverse" => PlayDirection::PingPongReverse,
+                    other => return Err(parse_error(format!("unknown direction {}", other))),
+                };
+                Ok(AnimationClip {
+                    name: tag.name,
+                    from: tag.from,
+                    to: tag.to,
+                    direction,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let slices = sheet
+            .meta
+            .slices
+            .into_iter()
+            .map(|slice| Slice {
+                name: slice.name,
+                keys: slice
+                    .keys
+                    .into_iter()
+                    .map(|key| SliceKey {
+                        frame: key.frame,
+                        bounds: key.bounds.into(),
+                        center: key.center.map(Rectangle::from),
+                        pivot: key.pivot.map(|pivot| Vector::new(pivot.x, pivot.y)),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Ok(Parsed {
+            image: sheet.meta.image,
+            frames,
+            clips,
+            slices,
+        })
+    }
+
+    fn parse_error(err: impl fmt::Display) -> QuicksilverError {
+        QuicksilverError::AsepriteError(err.to_string())
+    }
+
+    impl SpriteSheet {
+        /// Create a sprite sheet from an image and the JSON data Aseprite exported with it
+        ///
+        /// Frames are imported in the order they appear in the JSON, with their durations, and
+        /// tags become [`AnimationClip`]s. Both the array and hash layouts are supported. Frames
+        /// packed rotated aren't supported, and are reported as an error.
+        pub fn from_aseprite_json(
+            image: Image,
+            json: &[u8],
+        ) -> Result<SpriteSheet, QuicksilverError> {
+            let parsed = parse(json)?;
+            let mut sheet = SpriteSheet::new(image, parsed.frames, parsed.clips);
+            sheet.slices = parsed.slices;
+
+            Ok(sheet)
+        }
+
+        /// Load the JSON data Aseprite exported at the given path, and the image it refers to
+        ///
+        /// The path to the image is relative to the folder the JSON file is in.
+        pub async fn load_aseprite(
+            gfx: &Graphics,
+            path: impl AsRef<Path>,
+        ) -> Result<SpriteSheet, QuicksilverError> {
+            let path = path.as_ref();
+            let json = platter::load_file(path).await?;
+            let parsed = parse(&json)?;
+            let image_path = match path.parent() {
+                Some(folder) => folder.join(&parsed.image),
+                None => Path::new(&parsed.image).to_path_buf(),
+            };
+            let image = Image::load(gfx, image_path).await?;
+            let mut sheet = SpriteSheet::new(image, parsed.frames, parsed.clips);
+            sheet.slices = parsed.slices;
+
+            Ok(sheet)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_with_margin_and_spacing() {
+        let regions = grid_regions(
+            Vector::new(37.0, 20.0),
+            Vector::new(16.0, 16.0),
+            Vector::new(1.0, 2.0),
+            Vector::new(2.0, 2.0),
+        );
+        assert_eq!(
+            regions,
+            vec![
+                Rectangle::new(Vector::new(1.0, 2.0), Vector::new(16.0, 16.0)),
+                Rectangle::new(Vector::new(19.0, 2.0), Vector::new(16.0, 16.0)),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "spacing")]
+    fn grid_spacing_overlaps_whole_frame() {
+        grid_regions(
+            Vector::new(32.0, 32.0),
+            Vector::new(16.0, 16.0),
+            Vector::new(0.0, 0.0),
+            Vector::new(-16.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn clip_directions() {
+        let mut clip = AnimationClip {
+            name: "walk".to_owned(),
+            from: 2,
+            to: 5,
+            direction: PlayDirection::PingPong,
+        };
+        assert_eq!(clip.frame_order(), vec![2, 3, 4, 5, 4, 3]);
+        clip.direction = PlayDirection::PingPongReverse;
+        assert_eq!(clip.frame_order(), vec![5, 4, 3, 2, 3, 4]);
+        clip.direction = PlayDirection::Reverse;
+        assert_eq!(clip.frame_order(), vec![5, 4, 3, 2]);
+    }
+
+    #[cfg(feature = "aseprite")]
+    #[test]
+    fn parse_aseprite() {
+        let json = br##"{
+            "frames": {
+                "hero 0.aseprite": {
+                    "frame": { "x": 0, "y": 0, "w": 14, "h": 16 },
+                    "rotated": false,
+                    "trimmed": true,
+                    "spriteSourceSize": { "x": 1, "y": 0, "w": 14, "h": 16 },
+                    "sourceSize": { "w": 16, "h": 16 },
+                    "duration": 100
+                },
+                "hero 1.aseprite": {
+                    "frame": { "x": 14, "y": 0, "w": 16, "h": 16 },
+                    "rotated": false,
+                    "trimmed": false,
+                    "spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 16 },
+                    "sourceSize": { "w": 16, "h": 16 },
+                    "duration": 250
+                }
+            },
+            "meta": {
+                "image": "hero.png",
+                "size": { "w": 30, "h": 16 },
+                "frameTags": [
+                    { "name": "idle", "from": 0, "to": 1, "direction": "pingpong" }
+                ],
+                "slices": [
+                    { "name": "panel", "color": "#0000ffff", "keys": [
+                        { "frame": 0, "bounds": { "x": 0, "y": 0, "w": 16, "h": 16 },
+                          "center": { "x": 4, "y": 4, "w": 8, "h": 8 },
+                          "pivot": { "x": 8, "y": 16 } }
+                    ] }
+                ]
+            }
+        }"##;
+        let parsed = aseprite::parse(json).unwrap();
+        assert_eq!(parsed.image, "hero.png");
+        assert_eq!(parsed.frames.len(), 2);
+        assert_eq!(parsed.frames[0].name, "hero 0.aseprite");
+        assert_eq!(parsed.frames[0].offset, Vector::new(1.0, 0.0));
+        assert_eq!(parsed.frames[1].duration, Duration::from_millis(250));
+        assert_eq!(parsed.clips[0].direction, PlayDirection::PingPong);
+        let key = &parsed.slices[0].keys[0];
+        assert_eq!(
+            key.center,
+            Some(Rectangle::new(Vector::new(4.0, 4.0), Vector::new(8.0, 8.0)))
+        );
+        assert_eq!(key.pivot, Some(Vector::new(8.0, 16.0)));
+    }
+
+    #[cfg(feature = "aseprite")]
+    #[test]
+    fn aseprite_errors() {
+        let json = br#"{
+            "frames": [],
+            "meta": {
+                "image": "hero.png",
+                "frameTags": [{ "name": "walk", "from": 0, "to": 3, "direction": "forward" }]
+            }
+        }"#;
+        match aseprite::parse(json) {
+            Err(crate::QuicksilverError::AsepriteError(message)) => {
+                assert_eq!(message, "tag walk is out of bounds")
+            }
+            _ => panic!("The tag should be out of bounds"),
+        }
+        let json = br#"{
+            "frames": [{
+                "filename": "hero 0",
+                "frame": { "x": 0, "y": 0, "w": 16, "h": 14 },
+                "rotated": true,
+                "spriteSourceSize": { "x": 0, "y": 0, "w": 14, "h": 16 },
+                "sourceSize": { "w": 14, "h": 16 },
+                "duration": 100
+            }],
+            "meta": { "image": "hero.png" }
+        }"#;
+        match aseprite::parse(json) {
+            Err(crate::QuicksilverError::AsepriteError(message)) => {
+                assert_eq!(message, "frame hero 0 is rotated")
+            }
+            _ => panic!("Rotated frames should be rejected"),
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use super::{ColorFormat, Graphics, Image};
+use super::image::HasAlpha;
+use super::{ColorFormat, Graphics, Image, PixelFormat};
 use crate::geom::Vector;
 use crate::QuicksilverError;
 
@@ -9,18 +10,68 @@ use crate::QuicksilverError;
 ///
 /// If you want to get data from a texture, [`attach`] it and use [`Surface::screenshot`].
 ///
+/// To render at a different resolution than the window, like a low-resolution canvas or a
+/// full-screen post effect, create a Surface with [`Surface::with_size`] and call
+/// [`Surface::resize`] when the window size changes. The graphics backend only supports a single
+/// color attachment, so multisampled, depth and stencil attachments aren't available; only the
+/// window itself can be multisampled, through [`Settings::multisampling`].
+///
+/// [`Settings::multisampling`]: crate::Settings::multisampling
+///
 /// [`attach`]: Surface::attach
-pub struct Surface(pub(crate) golem::Surface);
+// The format is kept while the image is detached, so it can be given back and resized
+pub struct Surface(pub(crate) golem::Surface, HasAlpha);
 
 impl Surface {
     /// Create a Surface with an attached Image
     ///
     /// The image must not have any other references to it, or this function will return an error.
     pub fn new(gfx: &Graphics, attachment: Image) -> Result<Surface, QuicksilverError> {
-        let tex = attachment
+        let (tex, has_alpha) = attachment
             .into_raw()
             .map_err(|_| QuicksilverError::SurfaceImageError)?;
-        Ok(Surface(golem::Surface::new(&gfx.ctx, tex)?))
+        Ok(Surface(golem::Surface::new(&gfx.ctx, tex)?, has_alpha))
+    }
+
+    /// Create a Surface with a new, blank image of the given size and format attached
+    pub fn with_size(
+        gfx: &Graphics,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<Surface, QuicksilverError> {
+        let image = Image::from_raw(gfx, None, width, height, format)?;
+        Surface::new(gfx, image)
+    }
+
+    /// Reallocate the attached image at a new size, clearing its contents
+    ///
+    /// This is useful for render targets that should follow the size of the window, by calling
+    /// it when an [`Event::Resized`] comes in. The image keeps its pixel format.
+    ///
+    /// [`Event::Resized`]: crate::input::Event::Resized
+    pub fn resize(
+        &mut self,
+        gfx: &Graphics,
+        width: u32,
+        height: u32,
+    ) -> Result<(), QuicksilverError> {
+        // Detaching works on whichever framebuffer is bound, so make sure it isn't another surface.
+        // The texture stays attached to this one while its storage is reallocated.
+        golem::Surface::unbind(&gfx.ctx);
+        let mut texture = self
+            .0
+            .take_texture()
+            .ok_or(QuicksilverError::NoSurfaceImageBound)?;
+        texture.set_image(None, width, height, self.format());
+        self.0.put_texture(texture);
+
+        Ok(())
+    }
+
+    /// The format of the image data the Surface renders to
+    pub fn format(&self) -> PixelFormat {
+        self.1.format()
     }
 
     /// Use the attached image as the backing data for this Surface
@@ -35,10 +86,11 @@ impl Surface {
     ///
     /// [`detach`]: Surface::detach
     pub fn attach(&mut self, attachment: Image) -> Result<(), QuicksilverError> {
-        let tex = attachment
+        let (tex, has_alpha) = attachment
             .into_raw()
             .map_err(|_| QuicksilverError::SurfaceImageError)?;
         self.0.put_texture(tex);
+        self.1 = has_alpha;
 
         Ok(())
     }
@@ -48,7 +100,7 @@ impl Surface {
     /// To use the data that has been rendered to a Surface, its attachment has to be removed to
     /// avoid creating a loop (where the Image is both being drawn *from* and being drawn *to*.)
     pub fn detach(&mut self) -> Option<Image> {
-        Some(Image::new(self.0.take_texture()?))
+        Some(Image::new(self.0.take_texture()?, self.1))
     }
 
     /// Get the pixel data of a given region of this surface
@@ -0,0 +1,607 @@
+use super::{Color, Element, Mesh, Vertex};
+use crate::geom::Vector;
+use crate::QuicksilverError;
+
+use lyon_tessellation::{
+    math::{point, Point},
+    path::Path as LyonPath,
+    BuffersBuilder, FillOptions, FillRule, FillTessellator, FillVertex, LineCap, LineJoin,
+    StrokeOptions, StrokeTessellator, StrokeVertex, VertexBuffers,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use usvg::{NodeKind, Paint, PathSegment, SpreadMethod, Transform, TransformFromBBox, Units};
+
+/// A parsed SVG document, which can be tessellated into [`Mesh`]es
+///
+/// Paths are filled and stroked with solid colors or linear and radial gradients, through any
+/// nested transforms. Gradient colors are evaluated at each vertex, and triangles are split up
+/// until the gradient changes smoothly across them. Text, embedded images, patterns, filters,
+/// masks and clip paths are skipped, dashed strokes are drawn solid, and group opacity is
+/// applied to each path individually rather than to the group as a whole.
+///
+/// The meshes are measured in the document's units (its `width` and `height`, with the
+/// `viewBox` mapped onto them), with the origin at the top-left. Use [`Graphics::set_transform`]
+/// to position and scale them.
+///
+/// [`Graphics::set_transform`]: crate::Graphics::set_transform
+pub struct Svg {
+    tree: usvg::Tree,
+    meshes: Vec<(f32, Mesh)>,
+}
+
+impl Svg {
+    /// Parse an SVG document, optionally gzip-compressed
+    pub fn from_bytes(raw: &[u8]) -> Result<Svg, QuicksilverError> {
+        let tree = usvg::Tree::from_data(raw, &usvg::Options::default())?;
+
+        Ok(Svg {
+            tree,
+            meshes: Vec::new(),
+        })
+    }
+
+    /// Load an SVG document from a file at the given path
+    pub async fn load(path: impl AsRef<Path>) -> Result<Svg, QuicksilverError> {
+        let file_contents = platter::load_file(path).await?;
+        Svg::from_bytes(file_contents.as_slice())
+    }
+
+    /// The size of the document
+    pub fn size(&self) -> Vector {
+        let size = self.tree.svg_node().size;
+        Vector::new(size.width() as f32, size.height() as f32)
+    }
+
+    /// Get the document as a mesh, tessellating it the first time it's requested at this tolerance
+    ///
+    /// See [`Svg::tessellate`] for the meaning of `tolerance`. Meshes are kept for each tolerance
+    /// that has been asked for, so stick to a handful of tolerances (such as one per zoom level).
+    pub fn mesh(&mut self, tolerance: f32) -> &Mesh {
+        let index = match self
+            .meshes
+            .iter()
+            .position(|(cached, _)| cached.to_bits() == tolerance.to_bits())
+        {
+            Some(index) => index,
+            None => {
+                let mesh = self.tessellate(tolerance);
+                self.meshes.push((tolerance, mesh));
+                self.meshes.len() - 1
+            }
+        };
+
+        &self.meshes[index].1
+    }
+
+    /// Convert the document into triangles, without caching the result
+    ///
+    /// Curves are approximated by straight lines that stray at most `tolerance` from the real
+    /// curve, in document units. If the mesh will be drawn scaled up, divide the tolerance by the
+    /// scale to keep the curves smooth.
+    pub fn tessellate(&self, tolerance: f32) -> Mesh {
+        let mut mesh = Mesh {
+            vertices: Vec::new(),
+            elements: Vec::new(),
+            image: None,
+        };
+        let svg = self.tree.svg_node();
+        let view_box =
+            usvg::utils::view_box_to_transform(svg.view_box.rect, svg.view_box.aspect, svg.size);
+        let context = Context {
+            tree: &self.tree,
+            tolerance,
+        };
+        context.add_children(&mut mesh, &self.tree.root(), view_box, 1.0);
+
+        mesh
+    }
+}
+
+struct Context<'a> {
+    tree: &'a usvg::Tree,
+    tolerance: f32,
+}
+
+impl Context<'_> {
+    fn add_children(&self, mesh: &mut Mesh, node: &usvg::Node, transform: Transform, opacity: f64) {
+        for child in node.children() {
+            match *child.borrow() {
+                NodeKind::Group(ref group) => {
+                    let mut transform = transform;
+                    transform.append(&group.transform);
+                    let opacity = opacity * group.opacity.value();
+                    self.add_children(mesh, &child, transform, opacity);
+                }
+                NodeKind::Path(ref path) if path.visibility == usvg::Visibility::Visible => {
+                    let mut transform = transform;
+                    transform.append(&path.transform);
+                    self.add_path(mesh, path, transform, opacity);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn add_path(&self, mesh: &mut Mesh, path: &usvg::Path, transform: Transform, opacity: f64) {
+        let lyon_path = convert_path(&path.data);
+        // Tessellate in the path's own space, so stroke widths are transformed along with it
+        let scale = transform_scale(&transform);
+        if scale <= 0.0 {
+            return;
+        }
+        let tolerance = self.tolerance / scale;
+
+        if let Some(fill) = &path.fill {
+            let rule = match fill.rule {
+                usvg::FillRule::NonZero => FillRule::NonZero,
+                usvg::FillRule::EvenOdd => FillRule::EvenOdd,
+            };
+            let options = FillOptions::tolerance(tolerance).with_fill_rule(rule);
+            let mut buffers: VertexBuffers<Point, u32> = VertexBuffers::new();
+            let result = FillTessellator::new().tessellate_path(
+                &lyon_path,
+                &options,
+                &mut BuffersBuilder::new(&mut buffers, |vertex: FillVertex| vertex.position()),
+            );
+            match result {
+                Ok(_) => {
+                    let opacity = opacity * fill.opacity.value();
+                    self.add_triangles(mesh, buffers, path, &fill.paint, transform, opacity);
+                }
+                Err(err) => log::warn!("Failed to fill SVG path {:?}: {:?}", path.id, err),
+            }
+        }
+
+        if let Some(stroke) = &path.stroke {
+            let options = StrokeOptions::tolerance(tolerance)
+                .with_line_width(stroke.width.value() as f32)
+                .with_line_cap(match stroke.linecap {
+                    usvg::LineCap::Butt => LineCap::Butt,
+                    usvg::LineCap::Round => LineCap::Round,
+                    usvg::LineCap::Square => LineCap::Square,
+                })
+                .with_line_join(match stroke.linejoin {
+                    usvg::LineJoin::Miter => LineJoin::Miter,
+                    usvg::LineJoin::Round => LineJoin::Round,
+                    usvg::LineJoin::Bevel => LineJoin::Bevel,
+                })
+                .with_miter_limit(stroke.miterlimit.value() as f32);
+            let mut buffers: VertexBuffers<Point, u32> = VertexBuffers::new();
+            let result = StrokeTessellator::new().tessellate_path(
+                &lyon_path,
+                &options,
+                &mut BuffersBuilder::new(&mut buffers, |vertex: StrokeVertex| vertex.position()),
+            );
+            match result {
+                Ok(_) => {
+                    let opacity = opacity * stroke.opacity.value();
+                    self.add_triangles(mesh, buffers, path, &stroke.paint, transform, opacity);
+                }
+                Err(err) => log::warn!("Failed to stroke SVG path {:?}: {:?}", path.id, err),
+            }
+        }
+    }
+
+    fn add_triangles(
+        &self,
+        mesh: &mut Mesh,
+        buffers: VertexBuffers<Point, u32>,
+        path: &usvg::Path,
+        paint: &Paint,
+        transform: Transform,
+        opacity: f64,
+    ) {
+        let VertexBuffers {
+            vertices: mut points,
+            indices: mut triangles,
+        } = buffers;
+        let paint = match self.paint(path, paint, opacity) {
+            Some(paint) => paint,
+            None => return,
+        };
+        if let PathPaint::Gradient(gradient) = &paint {
+            triangles = subdivide(&mut points, &triangles, |point| gradient.offset(point));
+        }
+
+        let offset = mesh.vertices.len() as u32;
+        mesh.vertices.extend(points.iter().map(|point| {
+            let (x, y) = transform.apply(point.x as f64, point.y as f64);
+            Vertex {
+                pos: Vector::new(x as f32, y as f32),
+                uv: None,
+                color: paint.color_at(*point),
+            }
+        }));
+        mesh.elements.extend(
+            triangles
+                .chunks_exact(3)
+                .map(|tri| Element::Triangle([offset + tri[0], offset + tri[1], offset + tri[2]])),
+        );
+    }
+
+    fn paint(&self, path: &usvg::Path, paint: &Paint, opacity: f64) -> Option<PathPaint> {
+        let id = match paint {
+            Paint::Color(color) => return Some(PathPaint::Solid(to_color(*color, opacity))),
+            Paint::Link(id) => id,
+        };
+        let node = self.tree.defs_by_id(id)?;
+        let kind = node.borrow();
+        let (base, shape) = match *kind {
+            NodeKind::LinearGradient(ref gradient) => (
+                &gradient.base,
+                GradientShape::Linear {
+                    start: point(gradient.x1 as f32, gradient.y1 as f32),
+                    end: point(gradient.x2 as f32, gradient.y2 as f32),
+                },
+            ),
+            NodeKind::RadialGradient(ref gradient) => (
+                &gradient.base,
+                GradientShape::Radial {
+                    center: point(gradient.cx as f32, gradient.cy as f32),
+                    focus: point(gradient.fx as f32, gradient.fy as f32),
+                    radius: gradient.r.value() as f32,
+                },
+            ),
+            _ => {
+                log::warn!("Skipping SVG path {:?} with unsupported paint", path.id);
+                return None;
+            }
+        };
+        let stops: Vec<(f32, Color)> = base
+            .stops
+            .iter()
+            .map(|stop| {
+                let opacity = opacity * stop.opacity.value();
+                (stop.offset.value() as f32, to_color(stop.color, opacity))
+            })
+            .collect();
+        let last = stops.last()?.1;
+        // The gradient's coordinates may be relative to the bounding box of the path
+        let mut to_local = match base.units {
+            Units::UserSpaceOnUse => Transform::default(),
+            Units::ObjectBoundingBox => match path.data.bbox() {
+                Some(bbox) => Transform::from_bbox(bbox),
+                None => return None,
+            },
+        };
+        to_local.append(&base.transform);
+        // A single stop, or a gradient squashed down to nothing, is drawn as a solid color
+        let to_gradient = match invert(&to_local) {
+            Some(inverse) if stops.len() > 1 => inverse,
+            _ => return Some(PathPaint::Solid(last)),
+        };
+
+        Some(PathPaint::Gradient(Gradient {
+            to_gradient,
+            shape,
+            spread: base.spread_method,
+            stops,
+        }))
+    }
+}
+
+enum PathPaint {
+    Solid(Color),
+    Gradient(Gradient),
+}
+
+impl PathPaint {
+    fn color_at(&self, point: Point) -> Color {
+        match self {
+            PathPaint::Solid(color) => *color,
+            PathPaint::Gradient(gradient) => gradient.color_at(point),
+        }
+    }
+}
+
+struct Gradient {
+    to_gradient: Transform,
+    shape: GradientShape,
+    spread: SpreadMethod,
+    stops: Vec<(f32, Color)>,
+}
+
+enum GradientShape {
+    Linear {
+        start: Point,
+        end: Point,
+    },
+    Radial {
+        center: Point,
+        focus: Point,
+        radius: f32,
+    },
+}
+
+impl Gradient {
+    // How far along the gradient a point in the path's space is, before spreading
+    fn offset(&self, point: Point) -> f32 {
+        let (x, y) = self.to_gradient.apply(point.x as f64, point.y as f64);
+        let point = lyon_tessellation::math::point(x as f32, y as f32);
+        let offset = match self.shape {
+            GradientShape::Linear { start, end } => {
+                let direction = end - start;
+                let length = direction.square_length();
+                if length <= 0.0 {
+                    1.0
+                } else {
+                    (point - start).dot(direction) / length
+                }
+            }
+            GradientShape::Radial {
+                center,
+                focus,
+                radius,
+            } => {
+                // Find the circle, growing from the focus out to the edge, the point lies on
+                let to_point = point - focus;
+                let to_center = center - focus;
+                let a = to_center.square_length() - radius * radius;
+                let b = to_point.dot(to_center);
+                let c = to_point.square_length();
+                if a.abs() < f32::EPSILON {
+                    if b.abs() < f32::EPSILON {
+                        0.0
+                    } else {
+                        c / (2.0 * b)
+                    }
+                } else {
+                    let discriminant = (b * b - a * c).max(0.0).sqrt();
+                    ((b + discriminant) / a).max((b - discriminant) / a)
+                }
+            }
+        };
+
+        match self.spread {
+            SpreadMethod::Pad => offset.clamp(0.0, 1.0),
+            _ => offset,
+        }
+    }
+
+    fn color_at(&self, point: Point) -> Color {
+        let offset = self.offset(point);
+        let offset = match self.spread {
+            SpreadMethod::Pad => offset,
+            SpreadMethod::Repeat => offset - offset.floor(),
+            SpreadMethod::Reflect => {
+                let offset = offset.rem_euclid(2.0);
+                if offset > 1.0 {
+                    2.0 - offset
+                } else {
+                    offset
+                }
+            }
+        };
+
+        gradient_color(&self.stops, offset)
+    }
+}
+
+fn gradient_color(stops: &[(f32, Color)], offset: f32) -> Color {
+    let mut previous = stops[0];
+    if offset <= previous.0 {
+        return previous.1;
+    }
+    for &(stop, color) in stops.iter() {
+        if offset < stop {
+            let t = (offset - previous.0) / (stop - previous.0);
+            let from = previous.1;
+            return Color {
+                r: from.r + (color.r - from.r) * t,
+                g: from.g + (color.g - from.g) * t,
+                b: from.b + (color.b - from.b) * t,
+                a: from.a + (color.a - from.a) * t,
+            };
+        }
+        previous = (stop, color);
+    }
+
+    previous.1
+}
+
+// The furthest a gradient may change along one edge of a triangle
+const MAX_GRADIENT_STEP: f32 = 1.0 / 16.0;
+// The most times a triangle may be split in half
+const MAX_SUBDIVISIONS: u32 = 10;
+
+// Split triangles along their longest edge until the gradient changes smoothly across them
+//
+// Midpoints are shared between the triangles on either side of an edge, so splitting doesn't leave
+// any cracks in the mesh.
+fn subdivide(
+    points: &mut Vec<Point>,
+    triangles: &[u32],
+    offset: impl Fn(Point) -> f32,
+) -> Vec<u32> {
+    let mut offsets: Vec<f32> = points.iter().map(|point| offset(*point)).collect();
+    let mut midpoints = HashMap::new();
+    let mut output = Vec::with_capacity(triangles.len());
+    let mut stack: Vec<([u32; 3], u32)> = triangles
+        .chunks_exact(3)
+        .map(|tri| ([tri[0], tri[1], tri[2]], 0))
+        .collect();
+    while let Some((tri, depth)) = stack.pop() {
+        let steepest = (0..3)
+            .map(|i| {
+                let (a, b) = (tri[i], tri[(i + 1) % 3]);
+                (i, (offsets[a as usize] - offsets[b as usize]).abs())
+            })
+            .fold(
+                (0, 0.0),
+                |max, edge| if edge.1 > max.1 { edge } else { max },
+            );
+        if steepest.1 <= MAX_GRADIENT_STEP || depth >= MAX_SUBDIVISIONS {
+            output.extend_from_slice(&tri);
+            continue;
+        }
+        let i = steepest.0;
+        let (a, b, c) = (tri[i], tri[(i + 1) % 3], tri[(i + 2) % 3]);
+        let key = (a.min(b), a.max(b));
+        let middle = *midpoints.entry(key).or_insert_with(|| {
+            let midpoint = points[a as usize].lerp(points[b as usize], 0.5);
+            points.push(midpoint);
+            offsets.push(offset(midpoint));
+            points.len() as u32 - 1
+        });
+        stack.push(([a, middle, c], depth + 1));
+        stack.push(([middle, b, c], depth + 1));
+    }
+
+    output
+}
+
+fn convert_path(data: &usvg::PathData) -> LyonPath {
+    let mut builder = LyonPath::builder();
+    let mut open = false;
+    for segment in data.iter() {
+        match *segment {
+            PathSegment::MoveTo { x, y } => {
+                if open {
+                    builder.end(false);
+                }
+                builder.begin(point(x as f32, y as f32));
+                open = true;
+            }
+            PathSegment::LineTo { x, y } => {
+                builder.line_to(point(x as f32, y as f32));
+            }
+            PathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => {
+                builder.cubic_bezier_to(
+                    point(x1 as f32, y1 as f32),
+                    point(x2 as f32, y2 as f32),
+                    point(x as f32, y as f32),
+                );
+            }
+            PathSegment::ClosePath => {
+                if open {
+                    builder.end(true);
+                    open = false;
+                }
+            }
+        }
+    }
+    if open {
+        builder.end(false);
+    }
+
+    builder.build()
+}
+
+fn to_color(color: usvg::Color, opacity: f64) -> Color {
+    Color::from_rgba(color.red, color.green, color.blue, opacity as f32)
+}
+
+// The largest amount a transform scales along either axis
+fn transform_scale(ts: &Transform) -> f32 {
+    let x = (ts.a * ts.a + ts.b * ts.b).sqrt();
+    let y = (ts.c * ts.c + ts.d * ts.d).sqrt();
+
+    x.max(y) as f32
+}
+
+fn invert(ts: &Transform) -> Option<Transform> {
+    let det = ts.a * ts.d - ts.b * ts.c;
+    if det.abs() < f64::EPSILON {
+        return None;
+    }
+
+    Some(Transform::new(
+        ts.d / det,
+        -ts.b / det,
+        -ts.c / det,
+        ts.a / det,
+        (ts.c * ts.f - ts.d * ts.e) / det,
+        (ts.b * ts.e - ts.a * ts.f) / det,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangles(mesh: &Mesh) -> Vec<[Vector; 3]> {
+        mesh.elements
+            .iter()
+            .map(|element| match element {
+                Element::Triangle([a, b, c]) => [
+                    mesh.vertices[*a as usize].pos,
+                    mesh.vertices[*b as usize].pos,
+                    mesh.vertices[*c as usize].pos,
+                ],
+                _ => panic!("SVG meshes should only contain triangles"),
+            })
+            .collect()
+    }
+
+    fn area(mesh: &Mesh) -> f32 {
+        triangles(mesh)
+            .iter()
+            .map(|[a, b, c]| ((*b - *a).cross(*c - *a) / 2.0).abs())
+            .sum()
+    }
+
+    #[test]
+    fn fills_transformed_paths() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20" viewBox="0 0 20 10">
+            <g transform="translate(5 0)">
+                <rect width="10" height="10" fill="#ff0000"/>
+            </g>
+        </svg>"##;
+        let mut svg = Svg::from_bytes(&svg[..]).unwrap();
+        assert_eq!(svg.size(), Vector::new(40.0, 20.0));
+        let mesh = svg.mesh(0.1);
+        assert!((area(mesh) - 400.0).abs() < 0.01);
+        for vertex in mesh.vertices.iter() {
+            assert_eq!(vertex.color, Color::RED);
+            assert!(vertex.pos.x >= 10.0 && vertex.pos.x <= 30.0);
+        }
+    }
+
+    #[test]
+    fn caches_per_tolerance() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <circle cx="50" cy="50" r="40" fill="none" stroke="blue" stroke-width="4"/>
+        </svg>"#;
+        let mut svg = Svg::from_bytes(&svg[..]).unwrap();
+        let coarse = svg.mesh(5.0).vertices.len();
+        let fine = svg.mesh(0.01).vertices.len();
+        assert!(fine > coarse);
+        assert_eq!(svg.mesh(5.0).vertices.len(), coarse);
+        assert_eq!(svg.meshes.len(), 2);
+    }
+
+    #[test]
+    fn evaluates_gradients() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10">
+            <linearGradient id="fade">
+                <stop offset="0" stop-color="black"/>
+                <stop offset="1" stop-color="white"/>
+            </linearGradient>
+            <rect width="10" height="10" fill="url(#fade)"/>
+        </svg>"#;
+        let mut svg = Svg::from_bytes(&svg[..]).unwrap();
+        let mesh = svg.mesh(0.1);
+        // The quad is split up so the colors can follow the gradient
+        assert!(mesh.elements.len() > 2);
+        for vertex in mesh.vertices.iter() {
+            let expected = vertex.pos.x / 10.0;
+            assert!((vertex.color.r - expected).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn gradient_stops() {
+        let stops = [(0.25, Color::BLACK), (0.75, Color::WHITE)];
+        assert_eq!(gradient_color(&stops, 0.0), Color::BLACK);
+        assert_eq!(gradient_color(&stops, 0.5).r, 0.5);
+        assert_eq!(gradient_color(&stops, 1.0), Color::WHITE);
+    }
+}
@@ -131,6 +131,17 @@
 //! [specify which features](https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html#choosing-features)
 //! you actually want to use.
 //!
+//! There are also features which are disabled by default:
+//! - `apng`: decoding animated PNGs into an `Animation` (via [png](https://github.com/image-rs/image-png))
+//! - `aseprite`: importing sprite sheets exported by [Aseprite](https://www.aseprite.org)
+//! - `bmp`: loading BMP images
+//! - `debug-draw`: drawing the shapes and text queued in [`debug`]
+//! - `shaping`: shaping text with its font's own tables and laying out right-to-left text (via [rustybuzz](https://github.com/RazrFalcon/rustybuzz) and [unicode-bidi](https://github.com/servo/unicode-bidi))
+//! - `gif`: loading GIF images, decoding animated GIFs into an `Animation`, and exporting recorded frames as animated GIFs (via [gif](https://github.com/image-rs/image-gif))
+//! - `svg`: tessellating SVG documents into meshes (via [usvg](https://github.com/RazrFalcon/resvg) and [lyon](https://github.com/nical/lyon))
+//! - `tga`: loading TGA images
+//! - `webp`: loading WebP images
+//!
 //! ## Supported Platforms
 //!
 //! The engine is supported on Windows, macOS, Linux, and the web via WebAssembly.
@@ -150,11 +161,13 @@ pub use blinds;
 #[cfg(feature = "font")]
 pub use elefont;
 pub use golem;
+pub use image;
 pub use log;
 pub use mint;
 
 mod error;
 
+pub mod debug;
 pub mod geom;
 pub mod graphics;
 pub mod input;